@@ -0,0 +1,315 @@
+#[cfg(feature = "bzip2")]
+use bzip2;
+#[cfg(feature = "gzip")]
+use flate2;
+use std::fs::File;
+#[cfg(feature = "zstd")]
+use std::io::BufReader;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+#[cfg(feature = "zstd")]
+use zstd;
+
+/// A compression format of CSV files.
+///
+/// Data in a format other than `None` can only be read or written if the
+/// cargo feature of the same name (`gzip`, `zstd`, or `bzip2`) is enabled.
+/// Otherwise, creating a `DecompressingReader` or a `CompressingWriter` for it
+/// is an error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    /// The data isn't compressed.
+    None,
+    /// gzip, with the file extension `gz`.
+    Gzip,
+    /// Zstandard, with the file extension `zst`.
+    Zstd,
+    /// bzip2, with the file extension `bz2`.
+    Bzip2,
+}
+
+/// The compression formats, with their file extensions and magic bytes.
+const FORMATS: &'static [(Compression, &'static str, &'static [u8])] =
+    &[(Compression::Gzip, "gz", b"\x1f\x8b"),
+      (Compression::Zstd, "zst", b"\x28\xb5\x2f\xfd"),
+      (Compression::Bzip2, "bz2", b"BZh")];
+
+impl Compression {
+    /// Returns the compression format with the extension of the given path,
+    /// or `None` if the extension isn't one of a compression format.
+    pub fn from_extension<P: AsRef<Path>>(path: P) -> Option<Compression> {
+        let extension = path.as_ref().extension().and_then(|ext| ext.to_str());
+        FORMATS.iter()
+            .find(|&&(_, ext, _)| extension == Some(ext))
+            .map(|&(compression, _, _)| compression)
+    }
+
+    /// Returns the compression format of data that starts with the given
+    /// bytes, or `None` if they aren't the magic bytes of a compression
+    /// format.
+    pub fn from_magic_bytes(bytes: &[u8]) -> Option<Compression> {
+        FORMATS.iter()
+            .find(|&&(_, _, magic)| bytes.starts_with(magic))
+            .map(|&(compression, _, _)| compression)
+    }
+
+    /// Returns `true` if data in this format can be read and written, i.e.
+    /// the format is `None` or its cargo feature is enabled.
+    pub fn is_enabled(self) -> bool {
+        match self {
+            Compression::None => true,
+            Compression::Gzip => cfg!(feature = "gzip"),
+            Compression::Zstd => cfg!(feature = "zstd"),
+            Compression::Bzip2 => cfg!(feature = "bzip2"),
+        }
+    }
+}
+
+/// Returns an error if the cargo feature of the compression format isn't
+/// enabled.
+pub fn check_enabled(compression: Compression) -> io::Result<()> {
+    let feature = match compression {
+        Compression::None => return Ok(()),
+        Compression::Gzip => "gzip",
+        Compression::Zstd => "zstd",
+        Compression::Bzip2 => "bzip2",
+    };
+    if compression.is_enabled() {
+        return Ok(());
+    }
+    let msg = format!("The `{}` feature of typed_csv is required for this compression format",
+                      feature);
+    Err(io::Error::new(io::ErrorKind::Other, msg))
+}
+
+/// Determines the compression of a file from the extension of its path, or,
+/// if that isn't the extension of a compression format, from the first bytes
+/// of the file. The file is left at its start.
+pub fn detect_compression(path: &Path, file: &mut File) -> io::Result<Compression> {
+    if let Some(compression) = Compression::from_extension(path) {
+        return Ok(compression);
+    }
+    let mut magic = [0; 4];
+    let mut len = 0;
+    while len < magic.len() {
+        match file.read(&mut magic[len..])? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    file.seek(SeekFrom::Start(0))?;
+    Ok(Compression::from_magic_bytes(&magic[..len]).unwrap_or(Compression::None))
+}
+
+/// A reader that decompresses CSV data while it's read.
+pub struct DecompressingReader<R: Read> {
+    inner: Decompressor<R>,
+}
+
+enum Decompressor<R: Read> {
+    None(R),
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::read::MultiGzDecoder<R>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Decoder<'static, BufReader<R>>),
+    #[cfg(feature = "bzip2")]
+    Bzip2(bzip2::read::MultiBzDecoder<R>),
+}
+
+impl<R: Read> DecompressingReader<R> {
+    /// Creates a reader that decompresses the data of `rdr` in the given
+    /// compression format.
+    ///
+    /// The error is of kind `io::ErrorKind::Other` if the cargo feature of
+    /// the format isn't enabled.
+    pub fn new(rdr: R, compression: Compression) -> io::Result<DecompressingReader<R>> {
+        check_enabled(compression)?;
+        let inner = match compression {
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => Decompressor::Gzip(flate2::read::MultiGzDecoder::new(rdr)),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Decompressor::Zstd(zstd::Decoder::new(rdr)?),
+            #[cfg(feature = "bzip2")]
+            Compression::Bzip2 => Decompressor::Bzip2(bzip2::read::MultiBzDecoder::new(rdr)),
+            _ => Decompressor::None(rdr),
+        };
+        Ok(DecompressingReader { inner: inner })
+    }
+}
+
+impl<R: Read> Read for DecompressingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.inner {
+            Decompressor::None(ref mut rdr) => rdr.read(buf),
+            #[cfg(feature = "gzip")]
+            Decompressor::Gzip(ref mut rdr) => rdr.read(buf),
+            #[cfg(feature = "zstd")]
+            Decompressor::Zstd(ref mut rdr) => rdr.read(buf),
+            #[cfg(feature = "bzip2")]
+            Decompressor::Bzip2(ref mut rdr) => rdr.read(buf),
+        }
+    }
+}
+
+/// A writer that compresses CSV data while it's written.
+///
+/// Call [`finish`](#method.finish) to write the end of the compressed data
+/// and get the underlying writer back. Otherwise, the compressed data is
+/// finished when the writer is dropped, and errors that occur at that point
+/// are ignored.
+pub struct CompressingWriter<W: Write> {
+    /// The compressor, which is `None` once it has been finished.
+    inner: Option<Compressor<W>>,
+}
+
+enum Compressor<W: Write> {
+    None(W),
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::write::GzEncoder<W>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Encoder<'static, W>),
+    #[cfg(feature = "bzip2")]
+    Bzip2(bzip2::write::BzEncoder<W>),
+}
+
+impl<W: Write> CompressingWriter<W> {
+    /// Creates a writer that compresses data in the given compression format
+    /// and writes it to `wtr`, with the default compression level.
+    ///
+    /// The error is of kind `io::ErrorKind::Other` if the cargo feature of
+    /// the format isn't enabled.
+    pub fn new(wtr: W, compression: Compression) -> io::Result<CompressingWriter<W>> {
+        check_enabled(compression)?;
+        let inner = match compression {
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => {
+                Compressor::Gzip(flate2::write::GzEncoder::new(wtr,
+                                                               flate2::Compression::default()))
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Compressor::Zstd(zstd::Encoder::new(wtr, 0)?),
+            #[cfg(feature = "bzip2")]
+            Compression::Bzip2 => {
+                Compressor::Bzip2(bzip2::write::BzEncoder::new(wtr,
+                                                               bzip2::Compression::default()))
+            }
+            _ => Compressor::None(wtr),
+        };
+        Ok(CompressingWriter { inner: Some(inner) })
+    }
+
+    /// Writes the end of the compressed data, and returns the underlying
+    /// writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        match self.inner.take() {
+            Some(Compressor::None(wtr)) => Ok(wtr),
+            #[cfg(feature = "gzip")]
+            Some(Compressor::Gzip(wtr)) => wtr.finish(),
+            #[cfg(feature = "zstd")]
+            Some(Compressor::Zstd(wtr)) => wtr.finish(),
+            #[cfg(feature = "bzip2")]
+            Some(Compressor::Bzip2(wtr)) => wtr.finish(),
+            None => Err(finished()),
+        }
+    }
+
+    /// Writes the end of the compressed data, keeping the underlying writer.
+    fn try_finish(&mut self) -> io::Result<()> {
+        let result = match self.inner {
+            Some(Compressor::None(ref mut wtr)) => wtr.flush(),
+            #[cfg(feature = "gzip")]
+            Some(Compressor::Gzip(ref mut wtr)) => wtr.try_finish(),
+            #[cfg(feature = "zstd")]
+            Some(Compressor::Zstd(ref mut wtr)) => wtr.do_finish(),
+            #[cfg(feature = "bzip2")]
+            Some(Compressor::Bzip2(ref mut wtr)) => wtr.try_finish(),
+            None => return Ok(()),
+        };
+        // Nothing can be written after the end of the compressed data.
+        if result.is_ok() {
+            self.inner = None;
+        }
+        result
+    }
+}
+
+/// The error for writing to a `CompressingWriter` that has been finished.
+fn finished() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "The compressed data has been finished")
+}
+
+impl<W: Write> Write for CompressingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.inner {
+            Some(Compressor::None(ref mut wtr)) => wtr.write(buf),
+            #[cfg(feature = "gzip")]
+            Some(Compressor::Gzip(ref mut wtr)) => wtr.write(buf),
+            #[cfg(feature = "zstd")]
+            Some(Compressor::Zstd(ref mut wtr)) => wtr.write(buf),
+            #[cfg(feature = "bzip2")]
+            Some(Compressor::Bzip2(ref mut wtr)) => wtr.write(buf),
+            None => Err(finished()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.inner {
+            Some(Compressor::None(ref mut wtr)) => wtr.flush(),
+            #[cfg(feature = "gzip")]
+            Some(Compressor::Gzip(ref mut wtr)) => wtr.flush(),
+            #[cfg(feature = "zstd")]
+            Some(Compressor::Zstd(ref mut wtr)) => wtr.flush(),
+            #[cfg(feature = "bzip2")]
+            Some(Compressor::Bzip2(ref mut wtr)) => wtr.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> Drop for CompressingWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.try_finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CompressingWriter, Compression, DecompressingReader};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_detect_uncompressed() {
+        assert_eq!(Compression::from_extension("zoo.csv"), None);
+        assert_eq!(Compression::from_magic_bytes(b"a,b"), None);
+    }
+
+    #[test]
+    fn test_detect_gzip() {
+        assert_eq!(Compression::from_extension("zoo.csv.gz"), Some(Compression::Gzip));
+        assert_eq!(Compression::from_magic_bytes(b"\x1f\x8b\x08"), Some(Compression::Gzip));
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    #[test]
+    fn test_disabled_format() {
+        assert!(!Compression::Zstd.is_enabled());
+        assert!(CompressingWriter::new(Vec::new(), Compression::Zstd).is_err());
+        assert!(DecompressingReader::new(Cursor::new(Vec::new()), Compression::Zstd).is_err());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_finish_zstd() {
+        use std::io::{Read, Write};
+
+        let mut wtr = CompressingWriter::new(Vec::new(), Compression::Zstd).unwrap();
+        wtr.write_all(b"a,b\n0,1\n").unwrap();
+        let data = wtr.finish().unwrap();
+        let mut contents = String::new();
+        DecompressingReader::new(Cursor::new(data), Compression::Zstd)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "a,b\n0,1\n");
+    }
+}
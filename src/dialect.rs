@@ -0,0 +1,292 @@
+use csv::{QuoteStyle, RecordTerminator};
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+
+/// A set of CSV formatting options that can be applied to both a `Reader`
+/// and a `Writer`.
+///
+/// A dialect can be written to a config string with `to_string()`, and read
+/// from one with `parse()`. The config string is a space-separated list of
+/// `key=value` settings, in this format:
+///
+/// ```text
+/// delimiter=, quote=" escape=none double_quote=true terminator=crlf quote_style=necessary
+/// ```
+///
+/// When parsing, settings that aren't given keep the value from
+/// `Dialect::rfc4180()`, and a preset name (`rfc4180`, `excel`, `tsv`, or
+/// `unix`) sets all of the settings that come after it to the values of that
+/// preset. Characters other than printable ASCII are written as `\t`, `\r`,
+/// `\n`, or `\xNN`, and a space or a backslash is written as `\x20` or `\\`.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate rustc_serialize;
+/// # extern crate typed_csv;
+/// # fn main() {
+/// use typed_csv::Dialect;
+///
+/// #[derive(RustcDecodable, RustcEncodable)]
+/// struct Record {
+///     count: usize,
+///     animal: String,
+/// }
+///
+/// let dialect: Dialect = "tsv quote_style=always".parse().unwrap();
+///
+/// let mut wtr = typed_csv::Writer::from_memory().dialect(dialect);
+/// wtr.encode(Record { count: 7, animal: "penguin".to_string() }).unwrap();
+///
+/// let rdr = typed_csv::Reader::from_string(wtr.as_string()).dialect(dialect);
+/// for record in rdr.decode() {
+///     let record: Record = record.unwrap();
+///     assert_eq!(record.animal, "penguin");
+/// }
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Dialect {
+    /// The field delimiter.
+    pub delimiter: u8,
+    /// The quote character.
+    pub quote: u8,
+    /// The escape character for quotes, or `None` to only escape quotes by
+    /// doubling them.
+    pub escape: Option<u8>,
+    /// Whether quotes are escaped by doubling them.
+    pub double_quote: bool,
+    /// The record terminator. When reading, `RecordTerminator::CRLF` accepts
+    /// `\r`, `\n`, or `\r\n`.
+    pub record_terminator: RecordTerminator,
+    /// When fields are quoted. This is only used when writing.
+    pub quote_style: QuoteStyle,
+}
+
+impl Dialect {
+    /// The dialect of [RFC 4180](http://tools.ietf.org/html/rfc4180):
+    /// comma-separated, with `\r\n` after each record, and quotes escaped by
+    /// doubling them.
+    pub fn rfc4180() -> Dialect {
+        Dialect {
+            delimiter: b',',
+            quote: b'"',
+            escape: None,
+            double_quote: true,
+            record_terminator: RecordTerminator::CRLF,
+            quote_style: QuoteStyle::Necessary,
+        }
+    }
+
+    /// The dialect of CSV files written by Microsoft Excel, which is the same
+    /// as `rfc4180()`.
+    pub fn excel() -> Dialect {
+        Dialect::rfc4180()
+    }
+
+    /// Tab-separated, with `\n` after each record.
+    pub fn tsv() -> Dialect {
+        Dialect {
+            delimiter: b'\t',
+            record_terminator: RecordTerminator::Any(b'\n'),
+            ..Dialect::rfc4180()
+        }
+    }
+
+    /// Comma-separated, with `\n` after each record, and every field quoted
+    /// when writing.
+    pub fn unix() -> Dialect {
+        Dialect {
+            record_terminator: RecordTerminator::Any(b'\n'),
+            quote_style: QuoteStyle::Always,
+            ..Dialect::rfc4180()
+        }
+    }
+
+    /// Returns the preset with the given name.
+    fn preset(name: &str) -> Option<Dialect> {
+        match name {
+            "rfc4180" => Some(Dialect::rfc4180()),
+            "excel" => Some(Dialect::excel()),
+            "tsv" => Some(Dialect::tsv()),
+            "unix" => Some(Dialect::unix()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let escape = match self.escape {
+            Some(escape) => format_char(escape),
+            None => "none".to_string(),
+        };
+        let terminator = match self.record_terminator {
+            RecordTerminator::CRLF => "crlf".to_string(),
+            RecordTerminator::Any(terminator) => format_char(terminator),
+        };
+        let quote_style = match self.quote_style {
+            QuoteStyle::Always => "always",
+            QuoteStyle::Necessary => "necessary",
+            QuoteStyle::Never => "never",
+            QuoteStyle::NonNumeric => "non_numeric",
+        };
+        write!(f,
+               "delimiter={} quote={} escape={} double_quote={} terminator={} quote_style={}",
+               format_char(self.delimiter),
+               format_char(self.quote),
+               escape,
+               self.double_quote,
+               terminator,
+               quote_style)
+    }
+}
+
+impl FromStr for Dialect {
+    type Err = ParseDialectError;
+
+    fn from_str(s: &str) -> Result<Dialect, ParseDialectError> {
+        let mut dialect = Dialect::rfc4180();
+        for setting in s.split_whitespace() {
+            let (key, value) = match setting.find('=') {
+                Some(i) => (&setting[..i], &setting[i + 1..]),
+                None => {
+                    dialect = Dialect::preset(setting)
+                        .ok_or_else(|| ParseDialectError::new(setting, "unknown preset"))?;
+                    continue;
+                }
+            };
+            let invalid = || ParseDialectError::new(setting, "invalid value");
+            match key {
+                "delimiter" => dialect.delimiter = parse_char(value).ok_or_else(invalid)?,
+                "quote" => dialect.quote = parse_char(value).ok_or_else(invalid)?,
+                "escape" => {
+                    dialect.escape = match value {
+                        "none" => None,
+                        _ => Some(parse_char(value).ok_or_else(invalid)?),
+                    }
+                }
+                "double_quote" => dialect.double_quote = value.parse().map_err(|_| invalid())?,
+                "terminator" => {
+                    dialect.record_terminator = match value {
+                        "crlf" => RecordTerminator::CRLF,
+                        _ => RecordTerminator::Any(parse_char(value).ok_or_else(invalid)?),
+                    }
+                }
+                "quote_style" => {
+                    dialect.quote_style = match value {
+                        "always" => QuoteStyle::Always,
+                        "necessary" => QuoteStyle::Necessary,
+                        "never" => QuoteStyle::Never,
+                        "non_numeric" => QuoteStyle::NonNumeric,
+                        _ => return Err(invalid()),
+                    }
+                }
+                _ => return Err(ParseDialectError::new(setting, "unknown setting")),
+            }
+        }
+        Ok(dialect)
+    }
+}
+
+/// Formats a character of a dialect for the config string.
+fn format_char(c: u8) -> String {
+    match c {
+        b'\t' => "\\t".to_string(),
+        b'\r' => "\\r".to_string(),
+        b'\n' => "\\n".to_string(),
+        b'\\' => "\\\\".to_string(),
+        _ if c.is_ascii_graphic() => (c as char).to_string(),
+        _ => format!("\\x{:02x}", c),
+    }
+}
+
+/// Parses a character of a dialect from the config string.
+fn parse_char(s: &str) -> Option<u8> {
+    match s {
+        "\\t" => Some(b'\t'),
+        "\\r" => Some(b'\r'),
+        "\\n" => Some(b'\n'),
+        "\\\\" => Some(b'\\'),
+        _ if s.starts_with("\\x") && s.len() == 4 => u8::from_str_radix(&s[2..], 16).ok(),
+        _ if s.len() == 1 && s != "\\" => s.bytes().next().filter(|c| c.is_ascii_graphic()),
+        _ => None,
+    }
+}
+
+/// An error parsing a `Dialect` from a config string.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseDialectError {
+    /// The setting that couldn't be parsed.
+    pub setting: String,
+    /// Description of the error.
+    pub message: String,
+}
+
+impl ParseDialectError {
+    fn new(setting: &str, message: &str) -> ParseDialectError {
+        ParseDialectError {
+            setting: setting.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ParseDialectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid CSV dialect setting '{}': {}", self.setting, self.message)
+    }
+}
+
+impl error::Error for ParseDialectError {
+    fn description(&self) -> &str {
+        "invalid CSV dialect setting"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Dialect;
+
+    #[test]
+    fn test_to_string() {
+        assert_eq!(Dialect::tsv().to_string(),
+                   "delimiter=\\t quote=\" escape=none double_quote=true terminator=\\n \
+                    quote_style=necessary");
+    }
+
+    #[test]
+    fn test_parse_round_trip() {
+        for s in &["rfc4180", "excel", "tsv", "unix"] {
+            let dialect = Dialect::preset(s).unwrap();
+            let parsed: Dialect = dialect.to_string().parse().unwrap();
+            assert_eq!(parsed.to_string(), dialect.to_string());
+        }
+        let dialect = Dialect {
+            delimiter: b' ',
+            escape: Some(b'\\'),
+            double_quote: false,
+            ..Dialect::unix()
+        };
+        let s = dialect.to_string();
+        assert_eq!(s.parse::<Dialect>().unwrap().to_string(), s);
+    }
+
+    #[test]
+    fn test_parse_preset_and_settings() {
+        let dialect: Dialect = "tsv quote=' quote_style=non_numeric".parse().unwrap();
+        assert_eq!(dialect.to_string(),
+                   "delimiter=\\t quote=' escape=none double_quote=true terminator=\\n \
+                    quote_style=non_numeric");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = "delimiter=ab".parse::<Dialect>().unwrap_err();
+        assert_eq!(err.setting, "delimiter=ab");
+        assert!("csv".parse::<Dialect>().is_err());
+        assert!("separator=;".parse::<Dialect>().is_err());
+        assert!("double_quote=yes".parse::<Dialect>().is_err());
+    }
+}
@@ -0,0 +1,354 @@
+use std::char;
+use std::io::{self, Read, Write};
+use std::str;
+
+/// The UTF-8 encoding of the byte order mark.
+pub const UTF8_BOM: &'static [u8] = b"\xef\xbb\xbf";
+
+/// A character encoding of CSV data.
+///
+/// The CSV data is converted to or from UTF-8 by
+/// [`DecodingReader`](struct.DecodingReader.html) and
+/// [`EncodingWriter`](struct.EncodingWriter.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// UTF-8, which isn't converted.
+    Utf8,
+    /// UTF-16, little-endian.
+    Utf16Le,
+    /// UTF-16, big-endian.
+    Utf16Be,
+    /// ISO 8859-1, which maps each byte to the Unicode character with the same
+    /// value.
+    Latin1,
+    /// Windows-1252, which is the same as Latin-1 except for the characters
+    /// `0x80` to `0x9f`.
+    Windows1252,
+}
+
+/// The characters of Windows-1252 from `0x80` to `0x9f`. The bytes that
+/// aren't defined map to the control characters with the same value, like
+/// in Latin-1.
+const WINDOWS_1252: [u16; 32] =
+    [0x20ac, 0x0081, 0x201a, 0x0192, 0x201e, 0x2026, 0x2020, 0x2021, 0x02c6, 0x2030, 0x0160,
+     0x2039, 0x0152, 0x008d, 0x017d, 0x008f, 0x0090, 0x2018, 0x2019, 0x201c, 0x201d, 0x2022,
+     0x2013, 0x2014, 0x02dc, 0x2122, 0x0161, 0x203a, 0x0153, 0x009d, 0x017e, 0x0178];
+
+/// Size of the chunks read from the underlying reader.
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// A reader that converts CSV data from an encoding to UTF-8.
+///
+/// A byte order mark at the start of the data is removed, and if it's the
+/// byte order mark of UTF-8 or UTF-16, the data is read in that encoding
+/// instead of the given one. Invalid UTF-16 is replaced with `U+FFFD`.
+/// UTF-8 isn't validated.
+pub struct DecodingReader<R> {
+    rdr: R,
+    encoding: Encoding,
+    /// Whether the start of the data has been checked for a byte order mark.
+    checked_bom: bool,
+    /// Bytes that have been read, but not converted yet.
+    input: Vec<u8>,
+    /// Whether the underlying reader is at the end of the data.
+    eof: bool,
+    /// Converted bytes that haven't been returned yet, starting at `pos`.
+    output: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> DecodingReader<R> {
+    /// Creates a reader that converts the data of `rdr` from `encoding` to
+    /// UTF-8, unless the data starts with a byte order mark.
+    pub fn new(rdr: R, encoding: Encoding) -> DecodingReader<R> {
+        DecodingReader {
+            rdr: rdr,
+            encoding: encoding,
+            checked_bom: false,
+            input: Vec::new(),
+            eof: false,
+            output: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Returns the encoding of the data, which is only determined from the
+    /// byte order mark once some data has been read.
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Consumes the reader and returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.rdr
+    }
+
+    /// Reads a chunk from the underlying reader into `self.input`.
+    fn fill_input(&mut self) -> io::Result<()> {
+        let len = self.input.len();
+        self.input.resize(len + CHUNK_SIZE, 0);
+        let result = self.rdr.read(&mut self.input[len..]);
+        self.input.truncate(len + *result.as_ref().unwrap_or(&0));
+        self.eof = *result.as_ref().unwrap_or(&1) == 0;
+        result.map(|_| ())
+    }
+
+    /// Removes a byte order mark from the start of the data, and determines
+    /// the encoding from it.
+    fn check_bom(&mut self) -> io::Result<()> {
+        while self.input.len() < UTF8_BOM.len() && !self.eof {
+            self.fill_input()?;
+        }
+        let (encoding, len) = if self.input.starts_with(UTF8_BOM) {
+            (Encoding::Utf8, UTF8_BOM.len())
+        } else if self.input.starts_with(b"\xff\xfe") {
+            (Encoding::Utf16Le, 2)
+        } else if self.input.starts_with(b"\xfe\xff") {
+            (Encoding::Utf16Be, 2)
+        } else {
+            (self.encoding, 0)
+        };
+        self.encoding = encoding;
+        self.input.drain(..len);
+        self.checked_bom = true;
+        Ok(())
+    }
+
+    /// Converts as much of `self.input` as possible into `self.output`.
+    fn convert(&mut self) {
+        let mut output = String::new();
+        let used = match self.encoding {
+            Encoding::Utf8 => {
+                self.output.extend_from_slice(&self.input);
+                self.input.len()
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let big_endian = self.encoding == Encoding::Utf16Be;
+                let mut units = self.input
+                    .chunks(2)
+                    .filter(|unit| unit.len() == 2)
+                    .map(|unit| if big_endian {
+                        (unit[0] as u16) << 8 | unit[1] as u16
+                    } else {
+                        (unit[1] as u16) << 8 | unit[0] as u16
+                    })
+                    .collect::<Vec<_>>();
+                // A high surrogate at the end may be followed by a low
+                // surrogate in the next chunk.
+                let split = units.last().map_or(false, |&unit| unit >= 0xd800 && unit < 0xdc00);
+                if split && !self.eof {
+                    units.pop();
+                }
+                output.extend(char::decode_utf16(units.iter().cloned())
+                    .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)));
+                if self.eof && self.input.len() % 2 == 1 {
+                    output.push(char::REPLACEMENT_CHARACTER);
+                    self.input.len()
+                } else {
+                    units.len() * 2
+                }
+            }
+            Encoding::Latin1 => {
+                output.extend(self.input.iter().map(|&b| b as char));
+                self.input.len()
+            }
+            Encoding::Windows1252 => {
+                output.extend(self.input.iter().map(|&b| if b >= 0x80 && b < 0xa0 {
+                    char::from_u32(WINDOWS_1252[b as usize - 0x80] as u32).unwrap()
+                } else {
+                    b as char
+                }));
+                self.input.len()
+            }
+        };
+        self.output.extend_from_slice(output.as_bytes());
+        self.input.drain(..used);
+    }
+}
+
+impl<R: Read> Read for DecodingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.checked_bom {
+            self.check_bom()?;
+        }
+        while self.pos == self.output.len() {
+            self.output.clear();
+            self.pos = 0;
+            if self.input.is_empty() && self.eof {
+                return Ok(0);
+            }
+            if !self.eof {
+                self.fill_input()?;
+            }
+            self.convert();
+        }
+        let n = buf.len().min(self.output.len() - self.pos);
+        buf[..n].copy_from_slice(&self.output[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// A writer that converts UTF-8 CSV data to an encoding.
+///
+/// Characters that can't be represented in Latin-1 or Windows-1252, and
+/// invalid UTF-8 when converting to another encoding, are an error.
+pub struct EncodingWriter<W: Write> {
+    wtr: W,
+    encoding: Encoding,
+    /// Whether to write a byte order mark before the data.
+    bom: bool,
+    /// The start of a UTF-8 sequence that was split between writes.
+    partial: Vec<u8>,
+}
+
+impl<W: Write> EncodingWriter<W> {
+    /// Creates a writer that converts data from UTF-8 to `encoding` and writes
+    /// it to `wtr`.
+    ///
+    /// If `bom` is `true`, a byte order mark is written before the data. This
+    /// is ignored for Latin-1 and Windows-1252, which don't have one.
+    pub fn new(wtr: W, encoding: Encoding, bom: bool) -> EncodingWriter<W> {
+        EncodingWriter {
+            wtr: wtr,
+            encoding: encoding,
+            bom: bom,
+            partial: Vec::new(),
+        }
+    }
+
+    /// Consumes the writer and returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.wtr
+    }
+
+    /// Converts a UTF-8 string to the encoding.
+    fn encode(&self, s: &str) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(s.len());
+        match self.encoding {
+            Encoding::Utf8 => bytes.extend_from_slice(s.as_bytes()),
+            Encoding::Utf16Le => {
+                for unit in s.encode_utf16() {
+                    bytes.extend_from_slice(&[unit as u8, (unit >> 8) as u8]);
+                }
+            }
+            Encoding::Utf16Be => {
+                for unit in s.encode_utf16() {
+                    bytes.extend_from_slice(&[(unit >> 8) as u8, unit as u8]);
+                }
+            }
+            Encoding::Latin1 | Encoding::Windows1252 => {
+                for c in s.chars() {
+                    bytes.push(self.encode_byte(c)?);
+                }
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Converts a character to Latin-1 or Windows-1252.
+    fn encode_byte(&self, c: char) -> io::Result<u8> {
+        let code = c as u32;
+        if self.encoding == Encoding::Windows1252 {
+            if let Some(i) = WINDOWS_1252.iter().position(|&w| w as u32 == code) {
+                return Ok(0x80 + i as u8);
+            }
+            if code >= 0x80 && code < 0xa0 {
+                return Err(unencodable(c, self.encoding));
+            }
+        }
+        if code < 0x100 {
+            Ok(code as u8)
+        } else {
+            Err(unencodable(c, self.encoding))
+        }
+    }
+}
+
+/// Returns the error for a character that can't be encoded.
+fn unencodable(c: char, encoding: Encoding) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData,
+                   format!("The character {:?} can't be encoded in {:?}", c, encoding))
+}
+
+impl<W: Write> Write for EncodingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.bom {
+            self.bom = false;
+            let bom = match self.encoding {
+                Encoding::Utf8 | Encoding::Utf16Le | Encoding::Utf16Be => self.encode("\u{feff}")?,
+                Encoding::Latin1 | Encoding::Windows1252 => Vec::new(),
+            };
+            self.wtr.write_all(&bom)?;
+        }
+        if self.encoding == Encoding::Utf8 {
+            return self.wtr.write(buf);
+        }
+        let mut data = self.partial.split_off(0);
+        data.extend_from_slice(buf);
+        let valid_len = match str::from_utf8(&data) {
+            Ok(s) => s.len(),
+            // An incomplete sequence at the end is kept for the next write.
+            Err(ref err) if err.error_len().is_none() => err.valid_up_to(),
+            Err(_) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          "Invalid UTF-8 can't be converted to another encoding"))
+            }
+        };
+        let encoded = self.encode(str::from_utf8(&data[..valid_len]).unwrap())?;
+        self.wtr.write_all(&encoded)?;
+        self.partial = data[valid_len..].to_vec();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.wtr.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DecodingReader, Encoding, EncodingWriter};
+    use std::io::{self, Read, Write};
+
+    /// A reader that returns one byte at a time.
+    struct ByteReader<'a>(&'a [u8]);
+
+    impl<'a> Read for ByteReader<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() || buf.is_empty() {
+                return Ok(0);
+            }
+            buf[0] = self.0[0];
+            self.0 = &self.0[1..];
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn test_decode_split_surrogate_pair() {
+        let data = b"\xfe\xff\xd8\x3d\xdc\x27\x00a";
+        let mut rdr = DecodingReader::new(ByteReader(data), Encoding::Utf8);
+        let mut s = String::new();
+        rdr.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "\u{1f427}a");
+        assert_eq!(rdr.encoding(), Encoding::Utf16Be);
+    }
+
+    #[test]
+    fn test_decode_invalid_utf16() {
+        let mut rdr = DecodingReader::new(&b"\x3d\xd8a\x00b"[..], Encoding::Utf16Le);
+        let mut s = String::new();
+        rdr.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "\u{fffd}a\u{fffd}");
+    }
+
+    #[test]
+    fn test_encode_split_utf8() {
+        let mut wtr = EncodingWriter::new(Vec::new(), Encoding::Windows1252, true);
+        let bytes = "€a".as_bytes();
+        wtr.write_all(&bytes[..1]).unwrap();
+        wtr.write_all(&bytes[1..]).unwrap();
+        assert_eq!(wtr.into_inner(), b"\x80a");
+    }
+}
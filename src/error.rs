@@ -0,0 +1,267 @@
+use csv::{self, ByteString};
+use std::error;
+use std::fmt;
+use std::result;
+
+/// A type alias for `Result<T, typed_csv::Error>`.
+pub type Result<T> = result::Result<T, Error>;
+
+/// An error that can occur when reading or writing typed CSV data.
+#[derive(Debug)]
+pub enum Error {
+    /// An error from the [`csv`][csv] crate, which includes parse, I/O,
+    /// encoding, and decoding errors.
+    ///
+    /// [csv]: https://github.com/BurntSushi/rust-csv
+    Csv(csv::Error),
+    /// A field of a record couldn't be decoded.
+    Decode(DecodeError),
+    /// The headers don't match the field names of the record type.
+    HeaderMismatch(HeaderMismatch),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Csv(ref err) => err.fmt(f),
+            Error::Decode(ref err) => err.fmt(f),
+            Error::HeaderMismatch(ref err) => err.fmt(f),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Csv(ref err) => err.description(),
+            Error::Decode(ref err) => err.description(),
+            Error::HeaderMismatch(_) => "headers don't match field names",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Csv(ref err) => Some(err),
+            Error::Decode(_) | Error::HeaderMismatch(_) => None,
+        }
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(err: csv::Error) -> Error {
+        Error::Csv(err)
+    }
+}
+
+/// Converts the error back into a `csv::Error`, so that code written for
+/// the `csv` crate can keep using `?` on results from this crate. Decoding
+/// errors and header mismatches become `csv::Error::Decode` with the
+/// description of the error.
+impl From<Error> for csv::Error {
+    fn from(err: Error) -> csv::Error {
+        match err {
+            Error::Csv(err) => err,
+            err => csv::Error::Decode(err.to_string()),
+        }
+    }
+}
+
+impl From<DecodeError> for Error {
+    fn from(err: DecodeError) -> Error {
+        Error::Decode(err)
+    }
+}
+
+impl From<HeaderMismatch> for Error {
+    fn from(err: HeaderMismatch) -> Error {
+        Error::HeaderMismatch(err)
+    }
+}
+
+/// A description of how the headers don't match the field names of the
+/// record type.
+///
+/// Headers and field names are referred to by their (0-based) index. The
+/// `Display` implementation prints the expected field names and actual
+/// headers, followed by one line for each problem.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HeaderMismatch {
+    /// The headers in the CSV data.
+    pub headers: Vec<String>,
+    /// The field names of the record type.
+    pub field_names: Vec<String>,
+    /// Fields that don't have a matching header.
+    pub missing_fields: Vec<MissingField>,
+    /// Headers that don't match any field name.
+    pub unexpected_headers: Vec<UnexpectedHeader>,
+    /// Headers that are identical to an earlier header and aren't matched to
+    /// a field name.
+    pub duplicate_headers: Vec<DuplicateHeader>,
+    /// Headers that match a field name, but not in the same order as the
+    /// fields.
+    pub out_of_order: Vec<OutOfOrder>,
+}
+
+/// A field that doesn't have a matching header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MissingField {
+    pub field_index: usize,
+    pub field_name: String,
+    /// Other headers that would have been accepted for the field, from the
+    /// reader's alias table.
+    pub aliases: Vec<String>,
+}
+
+/// A header that doesn't match any field name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnexpectedHeader {
+    pub header_index: usize,
+    pub header: String,
+}
+
+/// A header that is identical to an earlier header and isn't matched to a
+/// field name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DuplicateHeader {
+    pub header_index: usize,
+    /// Index of the first header with the same name.
+    pub first_index: usize,
+    pub header: String,
+}
+
+/// A header that matches a field name, but not in the same order as the
+/// fields.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutOfOrder {
+    pub header_index: usize,
+    pub field_index: usize,
+    pub header: String,
+}
+
+impl HeaderMismatch {
+    /// Creates a new `HeaderMismatch` with no problems.
+    pub fn new(headers: &[ByteString], field_names: &[ByteString]) -> HeaderMismatch {
+        fn to_strings(names: &[ByteString]) -> Vec<String> {
+            names.iter().map(|name| String::from_utf8_lossy(name).into_owned()).collect()
+        }
+        HeaderMismatch {
+            headers: to_strings(headers),
+            field_names: to_strings(field_names),
+            missing_fields: Vec::new(),
+            unexpected_headers: Vec::new(),
+            duplicate_headers: Vec::new(),
+            out_of_order: Vec::new(),
+        }
+    }
+
+    /// Returns `true` if there are no problems.
+    pub fn is_empty(&self) -> bool {
+        self.missing_fields.is_empty() && self.unexpected_headers.is_empty() &&
+        self.duplicate_headers.is_empty() && self.out_of_order.is_empty()
+    }
+}
+
+impl fmt::Display for HeaderMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Headers don't match field names")?;
+        writeln!(f, "  expected: {}", self.field_names.join(","))?;
+        write!(f, "  actual:   {}", self.headers.join(","))?;
+        for missing in &self.missing_fields {
+            write!(f,
+                   "\n  - missing field '{}' (field {}",
+                   missing.field_name,
+                   missing.field_index)?;
+            if !missing.aliases.is_empty() {
+                let aliases = missing.aliases
+                    .iter()
+                    .map(|alias| format!("'{}'", alias))
+                    .collect::<Vec<_>>();
+                write!(f, ", aliases {}", aliases.join(", "))?;
+            }
+            write!(f, ")")?;
+        }
+        for unexpected in &self.unexpected_headers {
+            write!(f,
+                   "\n  + unexpected header '{}' (header {})",
+                   unexpected.header,
+                   unexpected.header_index)?;
+        }
+        for duplicate in &self.duplicate_headers {
+            write!(f,
+                   "\n  + duplicate header '{}' (header {}, first at header {})",
+                   duplicate.header,
+                   duplicate.header_index,
+                   duplicate.first_index)?;
+        }
+        for out_of_order in &self.out_of_order {
+            write!(f,
+                   "\n  ~ out-of-order header '{}' (header {}, field {})",
+                   out_of_order.header,
+                   out_of_order.header_index,
+                   out_of_order.field_index)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for HeaderMismatch {
+    fn description(&self) -> &str {
+        "headers don't match field names"
+    }
+}
+
+/// An error decoding a record, along with the position of the record and the
+/// column that couldn't be decoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodeError {
+    /// The number of the record (starting at 1), not counting the header row.
+    pub record: u64,
+    /// The line that the record starts on (starting at 1).
+    ///
+    /// This is counted from the records and the line breaks in their fields,
+    /// so it assumes that each record ends with a line break.
+    pub line: u64,
+    /// The byte offset of the start of the record.
+    pub byte: u64,
+    /// The index of the column (starting at 0), if the error is caused by a
+    /// single column.
+    pub column: Option<usize>,
+    /// The header of the column.
+    pub header: Option<String>,
+    /// The field name matched to the column. This is `None` for columns in the
+    /// catch-all map.
+    pub field_name: Option<String>,
+    /// The raw bytes of the field.
+    pub value: Option<Vec<u8>>,
+    /// Description of the error.
+    pub message: String,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "CSV decode error in record {} (line {}, byte {})",
+               self.record,
+               self.line,
+               self.byte)?;
+        if let Some(column) = self.column {
+            write!(f, ", column {}", column)?;
+        }
+        if let Some(ref header) = self.header {
+            write!(f, ", header '{}'", header)?;
+        }
+        if let Some(ref field_name) = self.field_name {
+            write!(f, ", field '{}'", field_name)?;
+        }
+        if let Some(ref value) = self.value {
+            write!(f, ", value {:?}", String::from_utf8_lossy(value))?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl error::Error for DecodeError {
+    fn description(&self) -> &str {
+        "CSV decode error"
+    }
+}
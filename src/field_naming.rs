@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// Options that determine the field names of values nested in structs,
+/// arrays, and tuples, and the headers for those field names.
+///
+/// This is shared by the reader and the writer so that both sides derive the
+/// same headers from a record type.
+#[derive(Clone)]
+pub struct FieldNaming {
+    /// Separator used to join the names of nested struct fields.
+    separator: String,
+    /// Pattern for the field names of the elements of arrays and tuples.
+    /// `{name}` is replaced with the field name of the array or tuple, and
+    /// `{index}` is replaced with the index of the element.
+    indexed_pattern: String,
+    /// Headers for specific field names.
+    renames: HashMap<String, String>,
+    /// Function that returns the header for field names that aren't in
+    /// `renames`.
+    rename_fn: Option<Arc<Fn(&str) -> String + Send + Sync>>,
+}
+
+impl fmt::Debug for FieldNaming {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FieldNaming")
+            .field("separator", &self.separator)
+            .field("indexed_pattern", &self.indexed_pattern)
+            .field("renames", &self.renames)
+            .field("rename_fn", &self.rename_fn.as_ref().map(|_| "Fn(&str) -> String"))
+            .finish()
+    }
+}
+
+/// An element of the path from a record to one of its scalars.
+#[derive(Clone, Debug)]
+pub enum PathElement {
+    /// A struct field with the given name.
+    Field(String),
+    /// The element of an array or tuple with the given index.
+    Index(usize),
+}
+
+impl FieldNaming {
+    /// Creates the default naming, which produces field names like `p.a` for
+    /// nested structs and `a_0` for elements of arrays and tuples.
+    pub fn new() -> FieldNaming {
+        FieldNaming {
+            separator: ".".to_string(),
+            indexed_pattern: "{name}_{index}".to_string(),
+            renames: HashMap::new(),
+            rename_fn: None,
+        }
+    }
+
+    pub fn set_separator(&mut self, separator: String) {
+        self.separator = separator;
+    }
+
+    pub fn set_indexed_pattern(&mut self, pattern: String) {
+        self.indexed_pattern = pattern;
+    }
+
+    pub fn rename(&mut self, field_name: String, header: String) {
+        self.renames.insert(field_name, header);
+    }
+
+    pub fn set_rename_fn(&mut self, rename_fn: Arc<Fn(&str) -> String + Send + Sync>) {
+        self.rename_fn = Some(rename_fn);
+    }
+
+    /// Returns the header for the given field name.
+    ///
+    /// A header given with `rename` takes precedence over the rename
+    /// function. Without either, the header is the field name.
+    pub fn header(&self, field_name: &[u8]) -> Vec<u8> {
+        if self.renames.is_empty() && self.rename_fn.is_none() {
+            return field_name.to_vec();
+        }
+        let field_name = String::from_utf8_lossy(field_name);
+        match (self.renames.get(&*field_name), self.rename_fn.as_ref()) {
+            (Some(header), _) => header.clone().into_bytes(),
+            (None, Some(rename_fn)) => rename_fn(&field_name).into_bytes(),
+            (None, None) => field_name.into_owned().into_bytes(),
+        }
+    }
+
+    /// Returns the field name of the scalar at the given path.
+    ///
+    /// Returns `None` if the path doesn't contain any struct fields, since
+    /// such scalars don't have a field name.
+    pub fn field_name(&self, path: &[PathElement]) -> Option<String> {
+        let mut name: Option<String> = None;
+        for element in path {
+            name = match (name, element) {
+                (None, &PathElement::Field(ref field)) => Some(field.clone()),
+                (Some(name), &PathElement::Field(ref field)) => {
+                    Some(name + &self.separator + field)
+                }
+                (None, &PathElement::Index(_)) => None,
+                (Some(name), &PathElement::Index(index)) => {
+                    Some(self.indexed_pattern
+                        .replace("{name}", &name)
+                        .replace("{index}", &index.to_string()))
+                }
+            };
+        }
+        name
+    }
+}
@@ -1,7 +1,8 @@
 //! This crate provides streaming CSV (comma separated values) wrappers for the
 //! reader and writer in the [`csv`][csv] crate. It works with the
 //! [`rustc_serialize`][rustc_serialize] crate to do type based encoding and
-//! decoding.
+//! decoding. If the `serde` feature is enabled, it also works with the
+//! [`serde`][serde] crate.
 //!
 //! *Unlike the [`csv`][csv] crate*, the reader requires that the header names
 //! match the field names in the decoded record type, and the writer
@@ -12,7 +13,21 @@
 //! If the ordering of the headers in the file doesn't matter for your use
 //! case, you can ask the reader to reorder the columns to match the headers to
 //! the corresponding field names. You also ask the reader to ignore unused
-//! columns or specify an arbitrary predicate to match headers to field names.
+//! columns, normalize headers and field names before comparing them (see
+//! [`Normalization`](struct.Normalization.html)), or specify an arbitrary
+//! predicate to match headers to field names.
+//! Records that can't be decoded can be skipped instead of stopping the
+//! iteration; see [`ErrorPolicy`](enum.ErrorPolicy.html).
+//! Metadata and comment rows before the header row can be skipped and
+//! retrieved; see [`Reader::skip_rows`](struct.Reader.html#method.skip_rows).
+//! The same CSV formatting options can be applied to a reader and a writer
+//! with a [`Dialect`](struct.Dialect.html).
+//! A UTF-8 byte order mark at the start of the CSV data is removed, and data
+//! in UTF-16 or Latin-1 can be read and written; see
+//! [`Encoding`](enum.Encoding.html).
+//! With the `gzip`, `zstd`, or `bzip2` features, compressed files are read
+//! and written transparently by `from_file`; see
+//! [`Compression`](enum.Compression.html).
 //!
 //! If you don't care at all if the headers match the field names (when
 //! reading) or you want to write your own headers (when writing), see the
@@ -40,13 +55,42 @@
 //!
 //! [csv]: https://github.com/BurntSushi/rust-csv
 //! [rustc_serialize]: https://doc.rust-lang.org/rustc-serialize/rustc_serialize/index.html
+//! [serde]: https://serde.rs/
 
 extern crate csv;
+#[cfg(feature = "bzip2")]
+extern crate dep_bzip2 as bzip2;
+#[cfg(feature = "zstd")]
+extern crate dep_zstd as zstd;
+#[cfg(feature = "gzip")]
+extern crate flate2;
+#[macro_use]
+extern crate log;
 extern crate rustc_serialize;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+#[macro_use]
+extern crate serde_derive;
 
+mod compression;
+mod dialect;
+mod encoding;
+mod error;
+mod field_naming;
+mod normalization;
 mod reader;
 mod writer;
 
-pub use csv::{Error, LocatableError, ParseError, QuoteStyle, RecordTerminator, Result};
-pub use reader::{DecodedRecords, Reader};
+pub use compression::{CompressingWriter, Compression, DecompressingReader};
+pub use csv::{LocatableError, ParseError, QuoteStyle, RecordTerminator};
+pub use dialect::{Dialect, ParseDialectError};
+pub use encoding::{DecodingReader, Encoding, EncodingWriter};
+pub use error::{DecodeError, DuplicateHeader, Error, HeaderMismatch, MissingField, OutOfOrder,
+                Result, UnexpectedHeader};
+pub use normalization::Normalization;
+pub use reader::{ColumnMapping, ColumnTarget, DecodedRecords, ErrorPolicy, MappedColumn, Reader,
+                 RejectedRecord};
+#[cfg(feature = "serde")]
+pub use reader::DeserializedRecords;
 pub use writer::Writer;
@@ -0,0 +1,108 @@
+use std::borrow::Cow;
+use std::ops::{BitOr, BitOrAssign};
+
+/// Normalization applied to headers and field names before they're compared.
+///
+/// Normalizations can be combined with `|`. For example,
+/// `Normalization::TRIM | Normalization::ASCII_CASE_INSENSITIVE` ignores
+/// surrounding whitespace and ASCII case. They're applied in this order:
+/// `TRIM`, `WORD_CASE`, `SEPARATORS`, and then `ASCII_CASE_INSENSITIVE` and
+/// `UNICODE_CASE_FOLD`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Normalization {
+    bits: u8,
+}
+
+impl Normalization {
+    /// Compare headers and field names exactly. This is the default.
+    pub const NONE: Normalization = Normalization { bits: 0 };
+    /// Ignore leading and trailing whitespace.
+    pub const TRIM: Normalization = Normalization { bits: 1 };
+    /// Ignore ASCII case, e.g. `Count` and `COUNT` match `count`.
+    pub const ASCII_CASE_INSENSITIVE: Normalization = Normalization { bits: 1 << 1 };
+    /// Treat `_`, `-`, and space as equal, e.g. `animal-count` and
+    /// `animal count` match `animal_count`.
+    pub const SEPARATORS: Normalization = Normalization { bits: 1 << 2 };
+    /// Ignore the word case convention, e.g. `animalCount`, `AnimalCount`,
+    /// `Animal Count`, and `ANIMAL-COUNT` match `animal_count`.
+    ///
+    /// Words are separated by `_`, `-`, space, and a lowercase letter or digit
+    /// followed by an uppercase letter. The words are compared in lowercase.
+    pub const WORD_CASE: Normalization = Normalization { bits: 1 << 3 };
+    /// Ignore case for all of Unicode, e.g. `ÉTÉ` matches `été`.
+    ///
+    /// This uses the lowercase mapping of each character, which differs from
+    /// full case folding for a few characters (such as `ß`).
+    pub const UNICODE_CASE_FOLD: Normalization = Normalization { bits: 1 << 4 };
+
+    /// Returns `true` if all of the normalizations in `other` are in `self`.
+    pub fn contains(self, other: Normalization) -> bool {
+        self.bits & other.bits == other.bits
+    }
+
+    /// Applies the normalizations to a header or field name.
+    pub fn apply<'a>(self, name: &'a [u8]) -> Cow<'a, [u8]> {
+        if self == Normalization::NONE {
+            return Cow::Borrowed(name);
+        }
+        let mut name = String::from_utf8_lossy(name).into_owned();
+        if self.contains(Normalization::TRIM) {
+            name = name.trim().to_string();
+        }
+        if self.contains(Normalization::WORD_CASE) {
+            name = split_words(&name)
+                .iter()
+                .map(|word| word.to_lowercase())
+                .collect::<Vec<_>>()
+                .join("_");
+        }
+        if self.contains(Normalization::SEPARATORS) {
+            name = name.replace(|c| c == '-' || c == ' ', "_");
+        }
+        if self.contains(Normalization::ASCII_CASE_INSENSITIVE) {
+            name = name.to_ascii_lowercase();
+        }
+        if self.contains(Normalization::UNICODE_CASE_FOLD) {
+            name = name.to_lowercase();
+        }
+        Cow::Owned(name.into_bytes())
+    }
+}
+
+impl BitOr for Normalization {
+    type Output = Normalization;
+
+    fn bitor(self, other: Normalization) -> Normalization {
+        Normalization { bits: self.bits | other.bits }
+    }
+}
+
+impl BitOrAssign for Normalization {
+    fn bitor_assign(&mut self, other: Normalization) {
+        self.bits |= other.bits;
+    }
+}
+
+/// Splits a name into words for `Normalization::WORD_CASE`.
+fn split_words(name: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = 0;
+    let mut prev: Option<char> = None;
+    for (i, c) in name.char_indices() {
+        if c == '_' || c == '-' || c == ' ' {
+            if start < i {
+                words.push(&name[start..i]);
+            }
+            start = i + c.len_utf8();
+        } else if c.is_uppercase() &&
+                  prev.map_or(false, |p| p.is_lowercase() || p.is_numeric()) {
+            words.push(&name[start..i]);
+            start = i;
+        }
+        prev = Some(c);
+    }
+    if start < name.len() {
+        words.push(&name[start..]);
+    }
+    words
+}
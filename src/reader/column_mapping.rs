@@ -0,0 +1,62 @@
+use std::fmt;
+
+/// The mapping of columns to fields, determined from the header row.
+///
+/// The `Display` implementation prints one line for each column, which is
+/// useful for logging.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ColumnMapping {
+    /// The columns, in order.
+    pub columns: Vec<MappedColumn>,
+    /// Field names that don't have a matching column, because they're
+    /// optional.
+    pub unmatched_fields: Vec<String>,
+}
+
+/// A column and what it's used for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MappedColumn {
+    pub header: String,
+    pub target: ColumnTarget,
+}
+
+/// What a column is used for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ColumnTarget {
+    /// The column is decoded into the field with the given index and name.
+    Field { index: usize, name: String },
+    /// The column is collected into the catch-all map.
+    CatchAll,
+    /// The column is ignored.
+    Ignored,
+}
+
+impl ColumnMapping {
+    /// Returns the indices of the columns that are ignored.
+    pub fn ignored_columns(&self) -> Vec<usize> {
+        self.columns
+            .iter()
+            .enumerate()
+            .filter(|&(_, column)| column.target == ColumnTarget::Ignored)
+            .map(|(index, _)| index)
+            .collect()
+    }
+}
+
+impl fmt::Display for ColumnMapping {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut lines = Vec::new();
+        for (index, column) in self.columns.iter().enumerate() {
+            let target = match column.target {
+                ColumnTarget::Field { ref name, .. } => format!("field '{}'", name),
+                ColumnTarget::CatchAll => "catch-all map".to_string(),
+                ColumnTarget::Ignored => "ignored".to_string(),
+            };
+            lines.push(format!("{}: '{}' -> {}", index, column.header, target));
+        }
+        for name in &self.unmatched_fields {
+            lines.push(format!("no column -> field '{}'", name));
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
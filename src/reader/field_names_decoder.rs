@@ -1,29 +1,240 @@
-use csv::{ByteString, Error, Result};
-use rustc_serialize::Decoder;
+use super::RecordFields;
+use field_naming::{FieldNaming, PathElement};
+
+use csv::{Error, Result};
+use rustc_serialize::{Decodable, Decoder};
+use std::collections::HashMap;
 use std::default::Default;
 
+/// The largest array length supported by `rustc_serialize`.
+const MAX_ARRAY_LEN: usize = 32;
+
+/// Returns the fields of the decodable type `D`. `var_lens` are the lengths
+/// of the sequences of variable length (such as `Vec`s) with field names, in
+/// the order they're read; any other such sequences are empty.
+///
+/// `rustc_serialize` doesn't provide the length of fixed-size arrays to the
+/// decoder. Instead, the decoder provides a length, and the array returns an
+/// error before reading any elements if the length is wrong. So, we try the
+/// lengths 0, 1, 2, ... for each sequence until the type decodes
+/// successfully. A sequence that accepts both 0 and 1 is a sequence of
+/// variable length. The lengths are shared by the sequences at the same
+/// position in the type (e.g. the arrays in the elements of another array),
+/// so they're only determined once.
+pub fn field_names<D: Decodable>(naming: &FieldNaming, var_lens: &[usize]) -> Result<RecordFields> {
+    let mut seqs = HashMap::new();
+    loop {
+        let mut field_names_decoder = FieldNamesDecoder::new(naming, var_lens, seqs);
+        let result = D::decode(&mut field_names_decoder);
+        let retry = field_names_decoder.retry;
+        let bad_seq = field_names_decoder.bad_seq.take();
+        match (result, bad_seq) {
+            (Ok(_), _) if !retry => return Ok(field_names_decoder.into_record_fields()),
+            (Err(err), None) if !retry => return Err(err),
+            (result, bad_seq) => {
+                seqs = field_names_decoder.seqs;
+                // Try the next length for the sequence that rejected its
+                // length.
+                if let Some(key) = bad_seq {
+                    let next = match seqs[&key] {
+                        SeqLen::Guess(len) if len < MAX_ARRAY_LEN => SeqLen::Guess(len + 1),
+                        SeqLen::Probe => SeqLen::Fixed(0),
+                        _ => {
+                            let err = result.err().map_or(String::new(), |err| err.to_string());
+                            let msg = format!("The length of a sequence can't be determined: {}",
+                                              err);
+                            return Err(Error::Decode(msg));
+                        }
+                    };
+                    seqs.insert(key, next);
+                }
+            }
+        }
+    }
+}
+
+/// An element of the path from a record to a sequence, which identifies the
+/// sequences that have the same length.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum KeyElement {
+    /// A struct field with the given name.
+    Field(String),
+    /// The argument of a tuple or enum variant with the given index.
+    Arg(usize),
+    /// Any element of a sequence.
+    Element,
+}
+
+/// What is known about the length of a sequence.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SeqLen {
+    /// The sequence hasn't accepted this length yet.
+    Guess(usize),
+    /// The sequence has accepted a length of 0, and is being given a length
+    /// of 1 to tell a sequence of variable length from a `[T; 0]`.
+    Probe,
+    /// The sequence is an array of this length.
+    Fixed(usize),
+    /// The sequence accepts any length.
+    Variable,
+}
+
 /// Decoder to extract field names from types that implement
 /// `rustc_serialize::Decodable`.
+///
+/// There is one field name for each scalar value (including enums and
+/// `Option`s) in a struct field. The field name of a scalar is determined by
+/// the path of struct fields and array/tuple indices leading to it; see
+/// `FieldNaming`.
+///
+/// Sequences and tuples with a single element are not indexed, so a field of
+/// type `[T; 1]` or `Vec<T>` with one element has the same field name as a
+/// field of type `T`.
+///
+/// A map doesn't have any field names. Instead, it's the catch-all for the
+/// columns that aren't matched to field names.
 #[derive(Debug)]
-pub struct FieldNamesDecoder {
-    field_names: Vec<ByteString>,
+pub struct FieldNamesDecoder<'a> {
+    field_names: Vec<String>,
+    /// Whether each field is an `Option`.
+    optional: Vec<bool>,
+    naming: &'a FieldNaming,
+    /// Path to the current value.
+    path: Vec<PathElement>,
+    /// Path to the current value that doesn't depend on the lengths of the
+    /// sequences, used as the key of `seqs`.
+    key: Vec<KeyElement>,
+    /// Whether the current value is inside a scalar (e.g. the argument of an
+    /// enum variant).
+    in_scalar: bool,
+    /// Whether the elements of each sequence or tuple containing the current
+    /// value are indexed, from outermost to innermost.
+    indexed: Vec<bool>,
+    /// What is known about the lengths of the sequences, by their keys.
+    seqs: HashMap<Vec<KeyElement>, SeqLen>,
+    /// Lengths of the sequences of variable length with field names.
+    var_lens: &'a [usize],
+    /// Lengths of the sequences in the order they're read, or `None` for a
+    /// sequence that consumes the rest of the record.
+    seq_lens: Vec<Option<usize>>,
+    /// Ranges (in `field_names`) of the field names of the sequences of
+    /// variable length with field names.
+    var_seqs: Vec<(usize, usize)>,
+    /// Keys of the sequences containing the current value, and whether any
+    /// of their elements have been read.
+    open_seqs: Vec<(Vec<KeyElement>, bool)>,
+    /// Key of the sequence that rejected its length.
+    bad_seq: Option<Vec<KeyElement>>,
+    /// Whether the type has to be decoded again, because more is known about
+    /// the length of a sequence.
+    retry: bool,
+    /// Index in `field_names` where the catch-all map is, if it has been
+    /// read.
+    catch_all: Option<usize>,
 }
 
-impl FieldNamesDecoder {
-    /// Creates a new `FieldNamesDecoder` from a record of byte strings.
-    pub fn new() -> FieldNamesDecoder {
-        FieldNamesDecoder { field_names: Vec::new() }
+impl<'a> FieldNamesDecoder<'a> {
+    /// Creates a new `FieldNamesDecoder` that provides the lengths in `seqs`
+    /// and `var_lens` to sequences.
+    fn new(naming: &'a FieldNaming,
+               var_lens: &'a [usize],
+               seqs: HashMap<Vec<KeyElement>, SeqLen>)
+               -> FieldNamesDecoder<'a> {
+        FieldNamesDecoder {
+            field_names: Vec::new(),
+            optional: Vec::new(),
+            naming: naming,
+            path: Vec::new(),
+            key: Vec::new(),
+            in_scalar: false,
+            indexed: Vec::new(),
+            seqs: seqs,
+            var_lens: var_lens,
+            seq_lens: Vec::new(),
+            var_seqs: Vec::new(),
+            open_seqs: Vec::new(),
+            bad_seq: None,
+            retry: false,
+            catch_all: None,
+        }
+    }
+
+    fn into_record_fields(self) -> RecordFields {
+        RecordFields {
+            names: self.field_names.into_iter().map(String::into_bytes).collect(),
+            seq_lens: self.seq_lens,
+            var_seqs: self.var_seqs,
+            optional: self.optional,
+            catch_all: self.catch_all,
+        }
+    }
+
+    /// Adds the field name for a scalar at the current path. `optional`
+    /// indicates whether the scalar is an `Option`.
+    ///
+    /// Scalars that aren't in a struct field don't have a field name.
+    fn push_field_name(&mut self, optional: bool) {
+        if !self.in_scalar {
+            if let Some(field_name) = self.naming.field_name(&self.path) {
+                self.field_names.push(field_name);
+                self.optional.push(optional);
+            }
+        }
+    }
+
+    fn read_scalar<T: Default>(&mut self) -> Result<T> {
+        self.push_field_name(false);
+        Ok(Default::default())
+    }
+
+    /// Calls `f` for a sequence or tuple of length `len`.
+    fn read_elements<T, F>(&mut self, len: usize, f: F) -> Result<T>
+        where F: FnOnce(&mut Self, usize) -> Result<T>
+    {
+        // The elements are indexed only if there is more than one of them and
+        // they're in a struct field.
+        let indexed = len > 1 && !self.in_scalar && !self.path.is_empty();
+        self.indexed.push(indexed);
+        let result = f(self, len);
+        self.indexed.pop();
+        result
     }
 
-    pub fn into_field_names(self) -> Vec<ByteString> {
-        self.field_names
+    /// Calls `f` for the element of a sequence or tuple at index `idx`.
+    fn read_element<T, F>(&mut self, idx: usize, f: F) -> Result<T>
+        where F: FnOnce(&mut Self) -> Result<T>
+    {
+        if self.indexed.last() == Some(&true) {
+            self.path.push(PathElement::Index(idx));
+            let result = f(self);
+            self.path.pop();
+            result
+        } else {
+            f(self)
+        }
+    }
+
+    /// Calls `f` with `element` added to the key of the current value.
+    fn with_key<T, F>(&mut self, element: KeyElement, f: F) -> Result<T>
+        where F: FnOnce(&mut Self) -> Result<T>
+    {
+        self.key.push(element);
+        let result = f(self);
+        self.key.pop();
+        result
     }
 }
 
-impl Decoder for FieldNamesDecoder {
+impl<'a> Decoder for FieldNamesDecoder<'a> {
     type Error = Error;
 
     fn error(&mut self, err: &str) -> Error {
+        // Arrays return an error from inside `read_seq`, before reading any
+        // elements, if the length is wrong. Any other error can't be fixed by
+        // changing the length.
+        if let Some(&(ref key, false)) = self.open_seqs.last() {
+            self.bad_seq = Some(key.clone());
+        }
         Error::Decode(err.into())
     }
 
@@ -32,69 +243,76 @@ impl Decoder for FieldNamesDecoder {
     }
 
     fn read_usize(&mut self) -> Result<usize> {
-        Ok(Default::default())
+        self.read_scalar()
     }
 
     fn read_u64(&mut self) -> Result<u64> {
-        Ok(Default::default())
+        self.read_scalar()
     }
 
     fn read_u32(&mut self) -> Result<u32> {
-        Ok(Default::default())
+        self.read_scalar()
     }
 
     fn read_u16(&mut self) -> Result<u16> {
-        Ok(Default::default())
+        self.read_scalar()
     }
 
     fn read_u8(&mut self) -> Result<u8> {
-        Ok(Default::default())
+        self.read_scalar()
     }
 
     fn read_isize(&mut self) -> Result<isize> {
-        Ok(Default::default())
+        self.read_scalar()
     }
 
     fn read_i64(&mut self) -> Result<i64> {
-        Ok(Default::default())
+        self.read_scalar()
     }
 
     fn read_i32(&mut self) -> Result<i32> {
-        Ok(Default::default())
+        self.read_scalar()
     }
 
     fn read_i16(&mut self) -> Result<i16> {
-        Ok(Default::default())
+        self.read_scalar()
     }
 
     fn read_i8(&mut self) -> Result<i8> {
-        Ok(Default::default())
+        self.read_scalar()
     }
 
     fn read_bool(&mut self) -> Result<bool> {
-        Ok(Default::default())
+        self.read_scalar()
     }
 
     fn read_f64(&mut self) -> Result<f64> {
-        Ok(Default::default())
+        self.read_scalar()
     }
 
     fn read_f32(&mut self) -> Result<f32> {
-        Ok(Default::default())
+        self.read_scalar()
     }
 
     fn read_char(&mut self) -> Result<char> {
-        Ok(Default::default())
+        self.read_scalar()
     }
 
     fn read_str(&mut self) -> Result<String> {
-        Ok(Default::default())
+        self.read_scalar()
     }
 
     fn read_enum<T, F>(&mut self, _: &str, f: F) -> Result<T>
         where F: FnOnce(&mut Self) -> Result<T>
     {
-        f(self)
+        // An enum is a single scalar, regardless of the arguments of the
+        // variant.
+        self.push_field_name(false);
+        let was_in_scalar = self.in_scalar;
+        self.in_scalar = true;
+        let result = f(self);
+        self.in_scalar = was_in_scalar;
+        result
     }
 
     fn read_enum_variant<T, F>(&mut self, _: &[&str], mut f: F) -> Result<T>
@@ -103,10 +321,10 @@ impl Decoder for FieldNamesDecoder {
         f(self, 0)
     }
 
-    fn read_enum_variant_arg<T, F>(&mut self, _: usize, f: F) -> Result<T>
+    fn read_enum_variant_arg<T, F>(&mut self, a_idx: usize, f: F) -> Result<T>
         where F: FnOnce(&mut Self) -> Result<T>
     {
-        f(self)
+        self.with_key(KeyElement::Arg(a_idx), f)
     }
 
     fn read_enum_struct_variant<T, F>(&mut self, names: &[&str], f: F) -> Result<T>
@@ -133,22 +351,25 @@ impl Decoder for FieldNamesDecoder {
         // Heuristic to ignore field names in tuple structs.
         // See https://github.com/rust-lang/rust/issues/15659
         // and https://github.com/rust-lang/rust/issues/17158
-        if f_name != format!("_field{}", f_idx) {
-            self.field_names.push(f_name.to_owned().into_bytes());
+        if f_name == format!("_field{}", f_idx) {
+            return self.with_key(KeyElement::Arg(f_idx), f);
         }
-        f(self)
+        self.path.push(PathElement::Field(f_name.to_owned()));
+        let result = self.with_key(KeyElement::Field(f_name.to_owned()), f);
+        self.path.pop();
+        result
     }
 
-    fn read_tuple<T, F>(&mut self, _: usize, f: F) -> Result<T>
+    fn read_tuple<T, F>(&mut self, len: usize, f: F) -> Result<T>
         where F: FnOnce(&mut Self) -> Result<T>
     {
-        f(self)
+        self.read_elements(len, |d, _| f(d))
     }
 
-    fn read_tuple_arg<T, F>(&mut self, _: usize, f: F) -> Result<T>
+    fn read_tuple_arg<T, F>(&mut self, a_idx: usize, f: F) -> Result<T>
         where F: FnOnce(&mut Self) -> Result<T>
     {
-        f(self)
+        self.with_key(KeyElement::Arg(a_idx), |d| d.read_element(a_idx, f))
     }
 
     fn read_tuple_struct<T, F>(&mut self, _: &str, _: usize, _: F) -> Result<T>
@@ -166,36 +387,87 @@ impl Decoder for FieldNamesDecoder {
     fn read_option<T, F>(&mut self, mut f: F) -> Result<T>
         where F: FnMut(&mut Self, bool) -> Result<T>
     {
+        self.push_field_name(true);
         f(self, false)
     }
 
-    fn read_seq<T, F>(&mut self, _: F) -> Result<T>
+    fn read_seq<T, F>(&mut self, f: F) -> Result<T>
         where F: FnOnce(&mut Self, usize) -> Result<T>
     {
-        unimplemented!();
+        let key = self.key.clone();
+        let seq_len = *self.seqs.entry(key.clone()).or_insert(SeqLen::Guess(0));
+        // Elements of sequences of variable length only have field names if
+        // they're in a struct field.
+        let named = !self.in_scalar && !self.path.is_empty();
+        let var_seq = self.var_seqs.len();
+        let len = match seq_len {
+            SeqLen::Guess(len) | SeqLen::Fixed(len) => len,
+            SeqLen::Probe => 1,
+            SeqLen::Variable if named => {
+                let start = self.field_names.len();
+                self.var_seqs.push((start, start));
+                self.var_lens.get(var_seq).cloned().unwrap_or(0)
+            }
+            SeqLen::Variable => 0,
+        };
+        let seq = self.seq_lens.len();
+        self.seq_lens.push(Some(len));
+        self.open_seqs.push((key.clone(), false));
+        let result = self.with_key(KeyElement::Element, |d| d.read_elements(len, f));
+        let (_, started) = self.open_seqs.pop().unwrap();
+        match seq_len {
+            // A sequence of variable length accepts a length of 0, so it's
+            // tried with 1 to tell it from a `[T; 0]`.
+            SeqLen::Guess(0) if result.is_ok() => {
+                self.seqs.insert(key, SeqLen::Probe);
+                self.retry = true;
+            }
+            SeqLen::Guess(len) if started => {
+                self.seqs.insert(key, SeqLen::Fixed(len));
+            }
+            SeqLen::Variable if named => self.var_seqs[var_seq].1 = self.field_names.len(),
+            SeqLen::Variable => self.seq_lens[seq] = None,
+            _ => (),
+        }
+        result
     }
 
-    fn read_seq_elt<T, F>(&mut self, _: usize, _: F) -> Result<T>
+    fn read_seq_elt<T, F>(&mut self, idx: usize, f: F) -> Result<T>
         where F: FnOnce(&mut Self) -> Result<T>
     {
-        unimplemented!();
+        if let Some(&mut (ref key, ref mut started)) = self.open_seqs.last_mut() {
+            *started = true;
+            // A `[T; 0]` would have rejected the element, so the sequence has
+            // a variable length. There's no need to read the element.
+            if self.seqs[key] == SeqLen::Probe {
+                self.seqs.insert(key.clone(), SeqLen::Variable);
+                self.retry = true;
+                return Err(Error::Decode("Sequence of variable length".to_string()));
+            }
+        }
+        self.read_element(idx, f)
     }
 
-    fn read_map<T, F>(&mut self, _: F) -> Result<T>
+    fn read_map<T, F>(&mut self, f: F) -> Result<T>
         where F: FnOnce(&mut Self, usize) -> Result<T>
     {
-        unimplemented!()
+        if self.catch_all.is_some() {
+            return Err(Error::Decode("The decodable type has more than one catch-all map"
+                .to_string()));
+        }
+        self.catch_all = Some(self.field_names.len());
+        f(self, 0)
     }
 
-    fn read_map_elt_key<T, F>(&mut self, _: usize, _: F) -> Result<T>
+    fn read_map_elt_key<T, F>(&mut self, _: usize, f: F) -> Result<T>
         where F: FnOnce(&mut Self) -> Result<T>
     {
-        unimplemented!()
+        f(self)
     }
 
-    fn read_map_elt_val<T, F>(&mut self, _: usize, _: F) -> Result<T>
+    fn read_map_elt_val<T, F>(&mut self, _: usize, f: F) -> Result<T>
         where F: FnOnce(&mut Self) -> Result<T>
     {
-        unimplemented!()
+        f(self)
     }
 }
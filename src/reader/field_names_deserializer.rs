@@ -0,0 +1,333 @@
+use super::RecordFields;
+use super::record_deserializer::{DeError, DeResult};
+use field_naming::{FieldNaming, PathElement};
+
+use csv::{Error, Result};
+use serde::de::{self, DeserializeOwned, DeserializeSeed, Deserializer, EnumAccess,
+                IntoDeserializer, SeqAccess, VariantAccess, Visitor};
+use serde::de::value::MapDeserializer;
+use std::iter;
+
+/// Returns the fields of the deserializable type `D`. `var_lens` are the
+/// lengths of the sequences of variable length (such as `Vec`s) with field
+/// names, in the order they're deserialized; any other such sequences are
+/// empty.
+pub fn field_names<D: DeserializeOwned>(naming: &FieldNaming,
+                                        var_lens: &[usize])
+                                        -> Result<RecordFields> {
+    let mut field_names_deserializer = FieldNamesDeserializer::new(naming, var_lens);
+    D::deserialize(&mut field_names_deserializer)
+        .map_err(|err| Error::Decode(err.into_message()))?;
+    Ok(field_names_deserializer.into_record_fields())
+}
+
+/// Deserializer to extract field names from types that implement
+/// `serde::Deserialize`.
+///
+/// The field names come from the struct metadata provided by `serde`, and
+/// every scalar is deserialized to a default value. Like
+/// `FieldNamesDecoder`, the field name of a scalar is determined by the path
+/// of struct fields and tuple indices leading to it; see `FieldNaming`. A map
+/// doesn't have any field names, since it's the catch-all for the columns
+/// that aren't matched to field names.
+#[derive(Debug)]
+pub struct FieldNamesDeserializer<'a> {
+    field_names: Vec<String>,
+    /// Whether each field is an `Option`.
+    optional: Vec<bool>,
+    naming: &'a FieldNaming,
+    /// Path to the current value.
+    path: Vec<PathElement>,
+    /// Whether the current value is inside a scalar (e.g. the argument of an
+    /// enum variant).
+    in_scalar: bool,
+    /// Lengths of the sequences of variable length with field names.
+    var_lens: &'a [usize],
+    /// Lengths of the sequences of variable length, in the order they're
+    /// deserialized, or `None` for a sequence that consumes the rest of the
+    /// record.
+    seq_lens: Vec<Option<usize>>,
+    /// Ranges (in `field_names`) of the field names of the sequences of
+    /// variable length with field names.
+    var_seqs: Vec<(usize, usize)>,
+    /// Index in `field_names` where the catch-all map is, if it has been
+    /// deserialized.
+    catch_all: Option<usize>,
+}
+
+impl<'a> FieldNamesDeserializer<'a> {
+    /// Creates a new `FieldNamesDeserializer` that names fields according to
+    /// `naming` and provides the lengths in `var_lens` to sequences of
+    /// variable length.
+    pub fn new(naming: &'a FieldNaming, var_lens: &'a [usize]) -> FieldNamesDeserializer<'a> {
+        FieldNamesDeserializer {
+            field_names: Vec::new(),
+            optional: Vec::new(),
+            naming: naming,
+            path: Vec::new(),
+            in_scalar: false,
+            var_lens: var_lens,
+            seq_lens: Vec::new(),
+            var_seqs: Vec::new(),
+            catch_all: None,
+        }
+    }
+
+    fn into_record_fields(self) -> RecordFields {
+        RecordFields {
+            names: self.field_names.into_iter().map(String::into_bytes).collect(),
+            seq_lens: self.seq_lens,
+            var_seqs: self.var_seqs,
+            optional: self.optional,
+            catch_all: self.catch_all,
+        }
+    }
+
+    /// Adds the field name for a scalar at the current path. `optional`
+    /// indicates whether the scalar is an `Option`.
+    ///
+    /// Scalars that aren't in a struct field don't have a field name.
+    fn push_field_name(&mut self, optional: bool) {
+        if !self.in_scalar {
+            if let Some(field_name) = self.naming.field_name(&self.path) {
+                self.field_names.push(field_name);
+                self.optional.push(optional);
+            }
+        }
+    }
+}
+
+macro_rules! deserialize_default {
+    ($($method:ident => $visit:ident($value:expr),)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+                self.push_field_name(false);
+                visitor.$visit($value)
+            }
+        )*
+    }
+}
+
+impl<'de, 'a, 'b> Deserializer<'de> for &'a mut FieldNamesDeserializer<'b> {
+    type Error = DeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _: V) -> DeResult<V::Value> {
+        Err(de::Error::custom("Field names cannot be determined for self-describing types"))
+    }
+
+    deserialize_default! {
+        deserialize_bool => visit_bool(false),
+        deserialize_i8 => visit_i8(0),
+        deserialize_i16 => visit_i16(0),
+        deserialize_i32 => visit_i32(0),
+        deserialize_i64 => visit_i64(0),
+        deserialize_u8 => visit_u8(0),
+        deserialize_u16 => visit_u16(0),
+        deserialize_u32 => visit_u32(0),
+        deserialize_u64 => visit_u64(0),
+        deserialize_f32 => visit_f32(0.),
+        deserialize_f64 => visit_f64(0.),
+        deserialize_char => visit_char('\0'),
+        deserialize_str => visit_str(""),
+        deserialize_string => visit_str(""),
+        deserialize_bytes => visit_bytes(&[]),
+        deserialize_byte_buf => visit_bytes(&[]),
+        deserialize_identifier => visit_str(""),
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        self.push_field_name(true);
+        visitor.visit_none()
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        self.push_field_name(false);
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self,
+                                                _: &'static str,
+                                                visitor: V)
+                                                -> DeResult<V::Value> {
+        self.push_field_name(false);
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self,
+                                                   _: &'static str,
+                                                   visitor: V)
+                                                   -> DeResult<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        // Elements of sequences of variable length only have field names if
+        // they're in a struct field. Otherwise, the sequence consumes the
+        // rest of the record.
+        if self.in_scalar || self.path.is_empty() {
+            self.seq_lens.push(None);
+            return visitor.visit_seq(Fields {
+                de: self,
+                names: None,
+                indexed: false,
+                index: 0,
+                len: 0,
+            });
+        }
+        let var_seq = self.var_seqs.len();
+        let len = self.var_lens.get(var_seq).cloned().unwrap_or(0);
+        let start = self.field_names.len();
+        self.var_seqs.push((start, start));
+        self.seq_lens.push(Some(len));
+        let result = visitor.visit_seq(Fields {
+            de: &mut *self,
+            names: None,
+            indexed: len > 1,
+            index: 0,
+            len: len,
+        });
+        self.var_seqs[var_seq].1 = self.field_names.len();
+        result
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> DeResult<V::Value> {
+        // The elements are indexed only if there is more than one of them and
+        // they're in a struct field.
+        let indexed = len > 1 && !self.in_scalar && !self.path.is_empty();
+        visitor.visit_seq(Fields {
+            de: self,
+            names: None,
+            indexed: indexed,
+            index: 0,
+            len: len,
+        })
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self,
+                                                 _: &'static str,
+                                                 len: usize,
+                                                 visitor: V)
+                                                 -> DeResult<V::Value> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        if self.catch_all.is_some() {
+            return Err(de::Error::custom("The deserializable type has more than one catch-all \
+                                          map"));
+        }
+        self.catch_all = Some(self.field_names.len());
+        visitor.visit_map(MapDeserializer::new(iter::empty::<((), ())>()))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self,
+                                           _: &'static str,
+                                           fields: &'static [&'static str],
+                                           visitor: V)
+                                           -> DeResult<V::Value> {
+        visitor.visit_seq(Fields {
+            de: self,
+            names: Some(fields),
+            indexed: false,
+            index: 0,
+            len: fields.len(),
+        })
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self,
+                                         _: &'static str,
+                                         _: &'static [&'static str],
+                                         visitor: V)
+                                         -> DeResult<V::Value> {
+        // An enum is a single scalar, regardless of the contents of the
+        // variant.
+        self.push_field_name(false);
+        let was_in_scalar = self.in_scalar;
+        self.in_scalar = true;
+        let result = visitor.visit_enum(&mut *self);
+        self.in_scalar = was_in_scalar;
+        result
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        visitor.visit_unit()
+    }
+}
+
+impl<'de, 'a, 'b> EnumAccess<'de> for &'a mut FieldNamesDeserializer<'b> {
+    type Error = DeError;
+    type Variant = Self;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> DeResult<(S::Value, Self)> {
+        // Always pick the first variant.
+        let variant = seed.deserialize(IntoDeserializer::<DeError>::into_deserializer(0u32))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de, 'a, 'b> VariantAccess<'de> for &'a mut FieldNamesDeserializer<'b> {
+    type Error = DeError;
+
+    fn unit_variant(self) -> DeResult<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> DeResult<S::Value> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> DeResult<V::Value> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self,
+                                       fields: &'static [&'static str],
+                                       visitor: V)
+                                       -> DeResult<V::Value> {
+        self.deserialize_tuple(fields.len(), visitor)
+    }
+}
+
+/// Access to the fields of a struct or the elements of a tuple.
+///
+/// If `names` is `Some`, the name of each field is added to the path while
+/// the field is deserialized. Otherwise, if `indexed` is true, the index of
+/// each element is added to the path.
+struct Fields<'a, 'b: 'a> {
+    de: &'a mut FieldNamesDeserializer<'b>,
+    names: Option<&'static [&'static str]>,
+    indexed: bool,
+    index: usize,
+    len: usize,
+}
+
+impl<'de, 'a, 'b> SeqAccess<'de> for Fields<'a, 'b> {
+    type Error = DeError;
+
+    fn next_element_seed<S>(&mut self, seed: S) -> DeResult<Option<S::Value>>
+        where S: DeserializeSeed<'de>
+    {
+        if self.index == self.len {
+            return Ok(None);
+        }
+        let element = match self.names {
+            Some(names) => Some(PathElement::Field(names[self.index].to_owned())),
+            None if self.indexed => Some(PathElement::Index(self.index)),
+            None => None,
+        };
+        self.index += 1;
+        match element {
+            Some(element) => {
+                self.de.path.push(element);
+                let result = seed.deserialize(&mut *self.de);
+                self.de.path.pop();
+                result.map(Some)
+            }
+            None => seed.deserialize(&mut *self.de).map(Some),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len - self.index)
+    }
+}
@@ -1,19 +1,46 @@
+mod column_mapping;
 mod field_names_decoder;
+#[cfg(feature = "serde")]
+mod field_names_deserializer;
+mod record_decoder;
+#[cfg(feature = "serde")]
+mod record_deserializer;
 
-use self::field_names_decoder::FieldNamesDecoder;
+pub use self::column_mapping::{ColumnMapping, ColumnTarget, MappedColumn};
+use self::record_decoder::{ErrorField, RecordDecoder};
+#[cfg(feature = "serde")]
+use self::record_deserializer::RecordDeserializer;
 
-use csv::{self, ByteString, Decoded, Error, NextField, RecordTerminator, Result};
+use csv::{self, ByteString, NextField, RecordTerminator};
+use compression::{Compression, DecompressingReader, detect_compression};
+use dialect::Dialect;
+use encoding::{DecodingReader, Encoding, UTF8_BOM};
+use error::{DecodeError, DuplicateHeader, Error, HeaderMismatch, MissingField, OutOfOrder, Result,
+            UnexpectedHeader};
+use field_naming::FieldNaming;
+use normalization::Normalization;
 use rustc_serialize::Decodable;
+#[cfg(feature = "serde")]
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{Cursor, Read};
 use std::marker::PhantomData;
+use std::mem;
 use std::path::Path;
+use std::result;
+use std::sync::Arc;
 
 /// A CSV reader that checks the headers.
 ///
 /// The lifetime parameter `'a` refers to the lifetime of the predicate used
-/// for comparing headers to field names. The `R` type parameter refers to the
-/// type of the underlying reader.
+/// for comparing headers to field names, if it's borrowed (see
+/// [`headers_match_by`](#method.headers_match_by)). The `R` type parameter
+/// refers to the type of the underlying reader. The `P` type parameter is the
+/// type of the predicate, which is `Fn(&[u8], &[u8]) -> bool + Send + Sync`
+/// for a predicate given to
+/// [`headers_match_by_owned`](#method.headers_match_by_owned). Only in that
+/// case can the reader be sent to other threads.
 ///
 /// This reader parses CSV data and exposes records via iterators that decode
 /// into types that implement [`rustc_serialize::Decodable`][Decodable]. This
@@ -100,25 +127,168 @@ use std::path::Path;
 ///
 /// [csv]: https://github.com/BurntSushi/rust-csv
 /// [Decodable]: https://doc.rust-lang.org/rustc-serialize/rustc_serialize/trait.Decodable.html
-pub struct Reader<'a, R: Read> {
+pub struct Reader<'a, R: Read, P: ?Sized + 'a = Fn(&[u8], &[u8]) -> bool + 'a> {
     csv: csv::Reader<R>,
+    has_headers: bool,
     reorder_columns: bool,
     ignore_unused_columns: bool,
-    headers_match_by: &'a Fn(&[u8], &[u8]) -> bool,
+    headers_match_by: HeadersMatchBy<'a, P>,
+    normalization: Normalization,
+    field_naming: FieldNaming,
+    error_policy: ErrorPolicy,
+    /// Values of the fields that are allowed to not have a column, by field
+    /// name.
+    field_defaults: HashMap<ByteString, ByteString>,
+    /// Other headers accepted for fields, by field name.
+    field_aliases: HashMap<ByteString, Vec<ByteString>>,
+    flexible: bool,
+    /// Number of rows to skip before the header row.
+    skip_rows: usize,
+    /// Predicate for the header row; the rows before it are skipped.
+    skip_until: Option<Box<Fn(&[ByteString]) -> bool + Send + Sync>>,
+    /// Prefix of the first field of rows that are comments.
+    comment_prefix: Option<ByteString>,
+    /// Number of header rows, including the one matched to field names.
+    header_rows: usize,
+    /// The header row and the rows before it, once they've been read.
+    header_row: Option<HeaderRow>,
+    /// Whether no rows have been read yet, so that a byte order mark at the
+    /// start of the first field must be removed.
+    at_start: bool,
+}
+
+/// The header row and the rows before it.
+struct HeaderRow {
+    /// The skipped rows before the header row.
+    preamble: Vec<Vec<ByteString>>,
+    /// The headers, or the first record if there is no header row. This is
+    /// empty if the CSV data contains no records.
+    headers: Vec<ByteString>,
+    /// The header rows after the first one.
+    extra_headers: Vec<Vec<ByteString>>,
+    /// Number of lines before the first record.
+    lines: u64,
+    /// The byte offset and fields of the first record, if it has been read
+    /// along with the preamble because there is no header row.
+    first_record: Option<(u64, Vec<ByteString>)>,
+}
+
+/// The predicate used for comparing headers to field names.
+enum HeadersMatchBy<'a, P: ?Sized + 'a> {
+    Borrowed(&'a P),
+    Owned(Box<P>),
+}
+
+impl<'a, P: ?Sized> HeadersMatchBy<'a, P> {
+    fn get(&self) -> &P {
+        match *self {
+            HeadersMatchBy::Borrowed(pred) => pred,
+            HeadersMatchBy::Owned(ref pred) => &**pred,
+        }
+    }
+}
+
+/// What to do with a record that can't be decoded.
+///
+/// This only applies to errors that are specific to a single record, such as
+/// a record with the wrong number of columns or a field that can't be
+/// decoded. Other errors, such as I/O errors and headers that don't match the
+/// field names, always stop the iteration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Return the error and stop the iteration. This is the default.
+    FailFast,
+    /// Skip the record and log the error with the [`log`][log] crate at the
+    /// `warn` level.
+    ///
+    /// [log]: https://doc.rust-lang.org/log/log/index.html
+    SkipAndLog,
+    /// Skip the record and keep it, along with the error, so that it can be
+    /// retrieved with `rejected` once the iteration has finished.
+    Collect,
+}
+
+/// A record that was skipped because it couldn't be decoded.
+#[derive(Debug)]
+pub struct RejectedRecord {
+    /// The raw fields of the record, in the order of the columns.
+    pub fields: Vec<ByteString>,
+    /// The error that occurred while decoding the record.
+    pub error: Error,
 }
 
 impl<R: Read> Reader<'static, R> {
     /// Creates a new typed CSV reader from a normal CSV reader.
     ///
-    /// *Do not make this public!* If it was public, a CSV reader with
-    /// `flexible = true` or `has_headers = false` could be passed in.
-    fn from_csv_reader(csv: csv::Reader<R>) -> Reader<'static, R> {
+    /// This lets you specify options to the underlying CSV reader that this
+    /// crate doesn't provide. For example, with `flexible(true)`, records may
+    /// have fewer columns than the header row; the fields of the missing
+    /// columns are decoded from empty fields.
+    ///
+    /// The first row is always compared to the field names, even if the CSV
+    /// reader has `has_headers(false)`. In that case, the first row is also
+    /// read as a record. To read CSV data without a header row, use
+    /// [`has_headers(false)`](#method.has_headers) on this reader instead.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate csv;
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// #[derive(Debug, PartialEq, RustcDecodable)]
+    /// struct Record {
+    ///     count: usize,
+    ///     animal: String,
+    ///     description: Option<String>,
+    /// }
+    ///
+    /// let data = "\
+    /// count,animal,description
+    /// 7,penguin,happy
+    /// 10,cheetah
+    /// ";
+    ///
+    /// let csv_rdr = csv::Reader::from_string(data).flexible(true);
+    /// let rdr = typed_csv::Reader::from_csv_reader(csv_rdr);
+    /// let rows = rdr.decode().collect::<typed_csv::Result<Vec<Record>>>().unwrap();
+    ///
+    /// assert_eq!(rows,
+    ///            vec![Record {
+    ///                     count: 7,
+    ///                     animal: "penguin".to_string(),
+    ///                     description: Some("happy".to_string()),
+    ///                 },
+    ///                 Record {
+    ///                     count: 10,
+    ///                     animal: "cheetah".to_string(),
+    ///                     description: None,
+    ///                 }]);
+    /// # }
+    /// ```
+    pub fn from_csv_reader(csv: csv::Reader<R>) -> Reader<'static, R> {
         static F: fn(&[u8], &[u8]) -> bool = <[u8]>::eq;
+        let eq: &'static Fn(&[u8], &[u8]) -> bool = &F;
         Reader {
             csv: csv,
+            has_headers: true,
             reorder_columns: false,
             ignore_unused_columns: false,
-            headers_match_by: &F,
+            headers_match_by: HeadersMatchBy::Borrowed(eq),
+            normalization: Normalization::NONE,
+            field_naming: FieldNaming::new(),
+            error_policy: ErrorPolicy::FailFast,
+            field_defaults: HashMap::new(),
+            field_aliases: HashMap::new(),
+            flexible: false,
+            skip_rows: 0,
+            skip_until: None,
+            comment_prefix: None,
+            header_rows: 1,
+            header_row: None,
+            at_start: true,
         }
     }
 
@@ -130,10 +300,124 @@ impl<R: Read> Reader<'static, R> {
     }
 }
 
-impl Reader<'static, File> {
-    /// Creates a new CSV reader for the data at the file path given.
-    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Reader<'static, File>> {
-        Ok(Reader::from_csv_reader(csv::Reader::from_file(path)?))
+impl<R: Read> Reader<'static, DecompressingReader<R>> {
+    /// Creates a new CSV reader from an arbitrary `io::Read` with data in the
+    /// given compression format.
+    ///
+    /// The data is decompressed while it's read. See
+    /// [`Compression`](enum.Compression.html) for the formats that are
+    /// available.
+    pub fn from_reader_with_compression(r: R,
+                                        compression: Compression)
+                                        -> Result<Reader<'static, DecompressingReader<R>>> {
+        let rdr = DecompressingReader::new(r, compression).map_err(csv::Error::Io)?;
+        Ok(Reader::from_reader(rdr))
+    }
+}
+
+impl Reader<'static, DecompressingReader<File>> {
+    /// Creates a new CSV reader for the data at the file path given, which is
+    /// decompressed while it's read if it's compressed.
+    ///
+    /// The compression format is determined from the extension of the path
+    /// (`gz`, `zst`, or `bz2`) or, if that isn't one of those, from the first
+    /// bytes of the file. If the cargo feature of the format isn't enabled,
+    /// the error is an I/O error. See [`Compression`](enum.Compression.html).
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// #[derive(RustcDecodable)]
+    /// struct Record {
+    ///     count: usize,
+    ///     animal: String,
+    /// }
+    ///
+    /// // With the `gzip` feature, this file is decompressed.
+    /// let rdr = typed_csv::Reader::from_file("zoo.csv.gz").unwrap();
+    /// for record in rdr.decode() {
+    ///     let record: Record = record.unwrap();
+    ///     println!("{}: {}", record.animal, record.count);
+    /// }
+    /// # }
+    /// ```
+    pub fn from_file<P>(path: P) -> Result<Reader<'static, DecompressingReader<File>>>
+        where P: AsRef<Path>
+    {
+        let mut file = File::open(&path).map_err(csv::Error::Io)?;
+        let compression = detect_compression(path.as_ref(), &mut file).map_err(csv::Error::Io)?;
+        Reader::from_reader_with_compression(file, compression)
+    }
+
+    /// Creates a new CSV reader for the data at the file path given, in the
+    /// given compression format, regardless of the extension of the path.
+    pub fn from_file_with_compression<P>(path: P,
+                                         compression: Compression)
+                                         -> Result<Reader<'static, DecompressingReader<File>>>
+        where P: AsRef<Path>
+    {
+        let file = File::open(path).map_err(csv::Error::Io)?;
+        Reader::from_reader_with_compression(file, compression)
+    }
+}
+
+impl<R: Read> Reader<'static, DecodingReader<R>> {
+    /// Creates a new CSV reader from an arbitrary `io::Read` with data in the
+    /// given encoding.
+    ///
+    /// If the data starts with a UTF-8 or UTF-16 byte order mark, it's read
+    /// in the encoding of the byte order mark instead. See
+    /// [`DecodingReader`](struct.DecodingReader.html).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    /// use std::io::Cursor;
+    /// use typed_csv::Encoding;
+    ///
+    /// #[derive(RustcDecodable)]
+    /// struct Record {
+    ///     count: usize,
+    ///     animal: String,
+    /// }
+    ///
+    /// // "count,animal\n7,pingüino\n" in Latin-1.
+    /// let data = b"count,animal\n7,ping\xfcino\n".to_vec();
+    ///
+    /// let rdr = typed_csv::Reader::from_reader_with_encoding(Cursor::new(data),
+    ///                                                        Encoding::Latin1);
+    /// for record in rdr.decode() {
+    ///     let record: Record = record.unwrap();
+    ///     assert_eq!(record.animal, "pingüino");
+    /// }
+    /// # }
+    /// ```
+    pub fn from_reader_with_encoding(r: R,
+                                     encoding: Encoding)
+                                     -> Reader<'static, DecodingReader<R>> {
+        Reader::from_reader(DecodingReader::new(r, encoding))
+    }
+}
+
+impl Reader<'static, DecodingReader<File>> {
+    /// Creates a new CSV reader for the data at the file path given, in the
+    /// given encoding.
+    ///
+    /// See [`from_reader_with_encoding`](#method.from_reader_with_encoding).
+    pub fn from_file_with_encoding<P>(path: P,
+                                      encoding: Encoding)
+                                      -> Result<Reader<'static, DecodingReader<File>>>
+        where P: AsRef<Path>
+    {
+        let file = File::open(path).map_err(csv::Error::Io)?;
+        Ok(Reader::from_reader_with_encoding(file, encoding))
     }
 }
 
@@ -153,7 +437,7 @@ impl Reader<'static, Cursor<Vec<u8>>> {
     }
 }
 
-impl<'a, R: Read> Reader<'a, R> {
+impl<'a, R: Read, P: ?Sized + Fn(&[u8], &[u8]) -> bool> Reader<'a, R, P> {
     /// Uses type-based decoding to read a single record from CSV data.
     ///
     /// The type that is being decoded into should correspond to *one full CSV
@@ -162,11 +446,32 @@ impl<'a, R: Read> Reader<'a, R> {
     /// strings, single-element tuple structs containing a scalar type, and
     /// enums with 0 or 1 scalar arguments) are fields in structs.
     ///
+    /// Struct fields that are themselves structs are flattened, so their
+    /// scalars must have headers like `outer.inner`. See
+    /// [`nested_field_separator`](#method.nested_field_separator). Struct
+    /// fields that are fixed-size arrays or tuples with more than one element
+    /// are expanded into one column per element, with headers like `field_0`.
+    /// See [`indexed_field_pattern`](#method.indexed_field_pattern). A struct
+    /// field that is a `Vec` has one element for each of the consecutive
+    /// headers `field_0`, `field_1`, and so on, or a single element for a
+    /// header `field`, which is how `Writer` writes it.
+    ///
+    /// One field of the record type may be a map (e.g. `HashMap<String,
+    /// String>` or `BTreeMap<String, String>`). It doesn't have a field name;
+    /// instead, it collects the header and value of every column that isn't
+    /// matched to a field name. This implies that unused columns are allowed.
+    ///
+    /// Fields of type `Option<T>` don't need a matching header; they're
+    /// decoded as `None` if there isn't one. Other fields can be made optional
+    /// with [`field_default`](#method.field_default).
+    ///
     /// If the headers don't match the field names or a record cannot be
     /// decoded into the type requested, an error is returned. See the
     /// [`reorder_columns`](method.reorder_columns) method if you'd like for
     /// the reader to automatically reorder columns to match headers to field
-    /// names.
+    /// names. A record that cannot be decoded results in an
+    /// [`Error::Decode`](enum.Error.html), which reports the record, line,
+    /// column, header, field name, and raw value of the field that failed.
     ///
     /// Enums are supported in a limited way. Namely, its variants must have
     /// exactly `1` parameter each. Each variant decodes based on its
@@ -281,156 +586,136 @@ impl<'a, R: Read> Reader<'a, R> {
     ///                  Part2 { size: 3 })]);
     /// # }
     /// ```
-    pub fn decode<D: Decodable>(self) -> DecodedRecords<'a, R, D> {
-        DecodedRecords {
-            p: self.csv,
-            reorder_columns: self.reorder_columns,
-            ignore_unused_columns: self.ignore_unused_columns,
-            headers_match_by: self.headers_match_by,
-            done_first: false,
-            done: false,
-            column_mapping: Vec::new(),
-            field_count: 0,
-            record_type: PhantomData,
-        }
-    }
-}
-
-impl<'a, R: Read> Reader<'a, R> {
-    /// Allow the reader to reorder columns to match headers to field names.
-    ///
-    /// By default, the headers must match the field names of the decodable
-    /// type exactly, including the order. However, the ordering of field names
-    /// may not be relevant to your data type, so this option is available.
-    ///
-    /// In the case of duplicate field names, the ordering of columns
-    /// corresponding to those fields will be preserved.
     ///
-    /// # Examples
-    ///
-    /// This is a simple example that demonstrates reordering the columns to
-    /// match headers to field names:
+    /// A map field collects the columns that aren't matched to field names,
+    /// which is useful if new columns may be added to the data:
     ///
     /// ```rust
     /// extern crate rustc_serialize;
     /// # extern crate typed_csv;
     /// # fn main() {
     ///
+    /// use std::collections::BTreeMap;
+    ///
     /// #[derive(Debug, PartialEq, RustcDecodable)]
     /// struct Record {
     ///     count: usize,
     ///     animal: String,
-    ///     description: String,
+    ///     other: BTreeMap<String, String>,
     /// }
     ///
     /// let data = "\
-    /// count,description,animal
-    /// 7,happy,penguin
-    /// 10,fast,cheetah
-    /// 4,armored,armadillo
+    /// count,pattern,animal,legs
+    /// 7,tuxedo,penguin,2
     /// ";
     ///
     /// let rdr = typed_csv::Reader::from_string(data);
-    /// let rows = rdr.reorder_columns(true)
-    ///     .decode()
-    ///     .collect::<typed_csv::Result<Vec<Record>>>()
-    ///     .unwrap();
+    /// let rows = rdr.decode().collect::<typed_csv::Result<Vec<Record>>>().unwrap();
     ///
+    /// let mut other = BTreeMap::new();
+    /// other.insert("pattern".to_string(), "tuxedo".to_string());
+    /// other.insert("legs".to_string(), "2".to_string());
     /// assert_eq!(rows,
     ///            vec![Record {
     ///                     count: 7,
     ///                     animal: "penguin".to_string(),
-    ///                     description: "happy".to_string(),
-    ///                 },
-    ///                 Record {
-    ///                     count: 10,
-    ///                     animal: "cheetah".to_string(),
-    ///                     description: "fast".to_string(),
-    ///                 },
-    ///                 Record {
-    ///                     count: 4,
-    ///                     animal: "armadillo".to_string(),
-    ///                     description: "armored".to_string(),
+    ///                     other: other,
     ///                 }]);
     /// # }
     /// ```
+    pub fn decode<D: Decodable>(self) -> DecodedRecords<'a, R, D, P> {
+        DecodedRecords {
+            records: MappedRecords::new(self, field_names_decoder::field_names::<D>),
+            record_type: PhantomData,
+        }
+    }
+
+    /// Reads the header row and checks that it matches the fields of the
+    /// decodable type `D`, with the current options of the reader.
     ///
-    /// Duplicate field names in decodable types are fine, and ordering of
-    /// columns with duplicate headers is preserved:
+    /// No records are read, and the reader can still be used to decode the
+    /// records afterward. If the headers don't match, the error is an
+    /// `Error::HeaderMismatch` describing each problem. CSV data without a
+    /// header row matches any type, since it contains no records.
+    ///
+    /// # Example
     ///
     /// ```rust
     /// extern crate rustc_serialize;
     /// # extern crate typed_csv;
     /// # fn main() {
     ///
-    /// #[derive(Debug, PartialEq, RustcDecodable)]
-    /// struct Animal {
+    /// #[derive(RustcDecodable)]
+    /// struct Record {
     ///     count: usize,
     ///     animal: String,
     /// }
     ///
-    /// let data = "\
-    /// count,animal,animal,count
-    /// 7,penguin,\"red panda\",2
-    /// 10,cheetah,fennec,9
-    /// 4,armadillo,quokka,3
-    /// ";
-    ///
-    /// type Record = (Animal, Animal);
-    ///
-    /// let rdr = typed_csv::Reader::from_string(data);
-    /// let rows = rdr.reorder_columns(true)
-    ///     .decode()
-    ///     .collect::<typed_csv::Result<Vec<Record>>>()
-    ///     .unwrap();
-    ///
-    /// assert_eq!(rows,
-    ///            vec![(Animal { count: 7, animal: "penguin".to_string() },
-    ///                  Animal { count: 2, animal: "red panda".to_string() }),
-    ///                 (Animal { count: 10, animal: "cheetah".to_string() },
-    ///                  Animal { count: 9, animal: "fennec".to_string() }),
-    ///                 (Animal { count: 4, animal: "armadillo".to_string() },
-    ///                  Animal { count: 3, animal: "quokka".to_string() })]);
+    /// let mut rdr = typed_csv::Reader::from_string("count,animal,legs\n7,penguin,2\n");
+    /// match rdr.check_headers::<Record>() {
+    ///     Err(typed_csv::Error::HeaderMismatch(mismatch)) => {
+    ///         assert_eq!(mismatch.unexpected_headers[0].header, "legs");
+    ///     }
+    ///     _ => panic!("expected a header mismatch"),
+    /// }
     /// # }
     /// ```
-    pub fn reorder_columns(mut self, yes: bool) -> Reader<'a, R> {
-        self.reorder_columns = yes;
-        self
+    pub fn check_headers<D: Decodable>(&mut self) -> Result<()> {
+        self.check_headers_with(field_names_decoder::field_names::<D>)
     }
 
-    /// Allow the reader to ignore unused columns.
+    /// Reads the header row and checks that it matches the fields of the
+    /// deserializable type `D`, with the current options of the reader.
     ///
-    /// By default, the headers must match the field names of the decodable
-    /// type exactly with no extra columns. However, you may not care if there
-    /// are extra, unused, columns in the CSV file, so this option is
-    /// available.
+    /// This is the equivalent of [`check_headers`](#method.check_headers)
+    /// for types that implement `serde::Deserialize`.
+    #[cfg(feature = "serde")]
+    pub fn check_deserialize_headers<D: DeserializeOwned>(&mut self) -> Result<()> {
+        self.check_headers_with(field_names_deserializer::field_names::<D>)
+    }
+
+    /// Uses `serde` to deserialize each record from CSV data.
     ///
-    /// # Example
+    /// This is the equivalent of [`decode`](#method.decode) for types that
+    /// implement `serde::Deserialize`. The field names are taken from the
+    /// struct metadata provided by `serde`, and the headers are checked
+    /// exactly like they are for `decode`, including the
+    /// [`reorder_columns`](#method.reorder_columns),
+    /// [`ignore_unused_columns`](#method.ignore_unused_columns), and
+    /// [`headers_match_by`](#method.headers_match_by) options.
     ///
-    /// In this example, the `pattern` column is unused and ignored:
+    /// Field attributes such as `#[serde(rename = "...")]` are respected, so
+    /// the header must match the renamed field name. `Option` fields decode
+    /// to `None` when the column is empty. Enums are supported only if all of
+    /// their variants are unit variants, in which case the column must
+    /// contain the name of the variant.
+    ///
+    /// This method is only available if the `serde` feature is enabled.
+    ///
+    /// # Example
     ///
     /// ```rust
-    /// extern crate rustc_serialize;
+    /// #[macro_use]
+    /// extern crate serde_derive;
     /// # extern crate typed_csv;
     /// # fn main() {
     ///
-    /// #[derive(Debug, PartialEq, RustcDecodable)]
+    /// #[derive(Debug, PartialEq, Deserialize)]
     /// struct Record {
     ///     count: usize,
     ///     animal: String,
-    ///     description: String,
+    ///     description: Option<String>,
     /// }
     ///
     /// let data = "\
-    /// count,animal,description,pattern
-    /// 7,penguin,happy,tuxedo
-    /// 10,cheetah,fast,spotted
-    /// 4,armadillo,armored,scaly
+    /// count,description,animal
+    /// 7,happy,penguin
+    /// 10,,cheetah
     /// ";
     ///
     /// let rdr = typed_csv::Reader::from_string(data);
-    /// let rows = rdr.ignore_unused_columns(true)
-    ///     .decode()
+    /// let rows = rdr.reorder_columns(true)
+    ///     .deserialize()
     ///     .collect::<typed_csv::Result<Vec<Record>>>()
     ///     .unwrap();
     ///
@@ -438,498 +723,3531 @@ impl<'a, R: Read> Reader<'a, R> {
     ///            vec![Record {
     ///                     count: 7,
     ///                     animal: "penguin".to_string(),
-    ///                     description: "happy".to_string(),
+    ///                     description: Some("happy".to_string()),
     ///                 },
     ///                 Record {
     ///                     count: 10,
     ///                     animal: "cheetah".to_string(),
-    ///                     description: "fast".to_string(),
-    ///                 },
-    ///                 Record {
-    ///                     count: 4,
-    ///                     animal: "armadillo".to_string(),
-    ///                     description: "armored".to_string(),
+    ///                     description: None,
     ///                 }]);
     /// # }
     /// ```
-    pub fn ignore_unused_columns(mut self, yes: bool) -> Reader<'a, R> {
-        self.ignore_unused_columns = yes;
-        self
+    #[cfg(feature = "serde")]
+    pub fn deserialize<D: DeserializeOwned>(self) -> DeserializedRecords<'a, R, D, P> {
+        DeserializedRecords {
+            records: MappedRecords::new(self, field_names_deserializer::field_names::<D>),
+            record_type: PhantomData,
+        }
     }
+}
 
-    /// When matching headers to field names, use the given predicate.
+impl<'a, R: Read, P: ?Sized + Fn(&[u8], &[u8]) -> bool> Reader<'a, R, P> {
+    /// Returns a reference to the underlying CSV reader.
+    pub fn as_csv_reader(&self) -> &csv::Reader<R> {
+        &self.csv
+    }
+
+    /// Consumes the reader and returns the underlying CSV reader.
+    pub fn into_inner(self) -> csv::Reader<R> {
+        self.csv
+    }
+
+    /// Whether the CSV data has a header row.
     ///
-    /// The default is `<[u8]>::eq`. The first argument to the predicate is the
-    /// header, and the second argument is the field name.
+    /// By default, the first row is the header row, which is matched to the
+    /// field names of the decodable type. When this is disabled, the first row
+    /// is a record, and the columns are matched to the fields by position, as
+    /// if the header row contained the field names in order. The number of
+    /// columns must be the same as the number of fields, or larger if
+    /// [`ignore_unused_columns`](#method.ignore_unused_columns) is enabled.
+    /// The record type can't have a catch-all map.
     ///
     /// # Example
     ///
-    /// This is an example of using a case-insensitive (ASCII) match:
-    ///
     /// ```rust
     /// extern crate rustc_serialize;
     /// # extern crate typed_csv;
     /// # fn main() {
     ///
-    /// use std::ascii::AsciiExt;
-    ///
     /// #[derive(Debug, PartialEq, RustcDecodable)]
     /// struct Record {
     ///     count: usize,
     ///     animal: String,
-    ///     description: String,
     /// }
     ///
     /// let data = "\
-    /// COUNT,animal,DeScRiPtIoN
-    /// 7,penguin,happy
-    /// 10,cheetah,fast
-    /// 4,armadillo,armored
+    /// 7,penguin
+    /// 10,cheetah
     /// ";
     ///
-    /// let rdr = typed_csv::Reader::from_string(data);
-    /// let rows = rdr.headers_match_by(&<[u8]>::eq_ignore_ascii_case)
-    ///     .decode()
-    ///     .collect::<typed_csv::Result<Vec<Record>>>()
-    ///     .unwrap();
+    /// let rdr = typed_csv::Reader::from_string(data).has_headers(false);
+    /// let rows = rdr.decode().collect::<typed_csv::Result<Vec<Record>>>().unwrap();
     ///
     /// assert_eq!(rows,
-    ///            vec![Record {
-    ///                     count: 7,
-    ///                     animal: "penguin".to_string(),
-    ///                     description: "happy".to_string(),
-    ///                 },
-    ///                 Record {
-    ///                     count: 10,
-    ///                     animal: "cheetah".to_string(),
-    ///                     description: "fast".to_string(),
-    ///                 },
-    ///                 Record {
-    ///                     count: 4,
-    ///                     animal: "armadillo".to_string(),
-    ///                     description: "armored".to_string(),
-    ///                 }]);
+    ///            vec![Record { count: 7, animal: "penguin".to_string() },
+    ///                 Record { count: 10, animal: "cheetah".to_string() }]);
     /// # }
     /// ```
-    // See https://github.com/Manishearth/rust-clippy/issues/740#issuecomment-277837213
-    #[allow(unknown_lints)]
-    #[allow(needless_lifetimes)]
-    pub fn headers_match_by<'b, P>(self, pred: &'b P) -> Reader<'b, R>
-        where P: Fn(&[u8], &[u8]) -> bool
-    {
-        Reader {
-            csv: self.csv,
-            reorder_columns: self.reorder_columns,
-            ignore_unused_columns: self.ignore_unused_columns,
-            headers_match_by: pred,
-        }
+    pub fn has_headers(mut self, yes: bool) -> Reader<'a, R, P> {
+        let skips_rows = self.skips_rows();
+        self.csv = self.csv.has_headers(yes && !skips_rows);
+        self.has_headers = yes;
+        self
     }
 
-    /// The delimiter to use when reading CSV data.
+    /// Allow records to have a different number of columns than the header
+    /// row.
     ///
-    /// Since the CSV reader is meant to be mostly encoding agnostic, you must
-    /// specify the delimiter as a single ASCII byte. For example, to read
-    /// tab-delimited data, you would use `b'\t'`.
+    /// Records with fewer columns are decoded as if the missing columns were
+    /// empty, and records with more columns are an error.
     ///
-    /// The default value is `b','`.
-    pub fn delimiter(mut self, delimiter: u8) -> Reader<'a, R> {
-        self.csv = self.csv.delimiter(delimiter);
+    /// By default, this is disabled, and a record with a different number of
+    /// columns is a parse error.
+    pub fn flexible(mut self, yes: bool) -> Reader<'a, R, P> {
+        let skips_rows = self.skips_rows();
+        self.csv = self.csv.flexible(yes || skips_rows);
+        self.flexible = yes;
         self
     }
 
-    /// Set the record terminator to use when reading CSV data.
+    /// Skip the first `n` rows, which come before the header row.
     ///
-    /// In the vast majority of situations, you'll want to use the default
-    /// value, `RecordTerminator::CRLF`, which automatically handles `\r`,
-    /// `\n` or `\r\n` as record terminators. (Notably, this is a special
-    /// case since two characters can correspond to a single terminator token.)
+    /// The skipped rows are the preamble, which can be retrieved with
+    /// [`preamble`](#method.preamble). If this is combined with
+    /// [`skip_rows_until`](#method.skip_rows_until), the predicate is only
+    /// applied to the rows after the first `n`.
     ///
-    /// However, you may use `RecordTerminator::Any` to specify any ASCII
-    /// character to use as the record terminator. For example, you could
-    /// use `RecordTerminator::Any(b'\n')` to only accept line feeds as
-    /// record terminators, or `b'\x1e'` for the ASCII record separator.
-    pub fn record_terminator(mut self, term: RecordTerminator) -> Reader<'a, R> {
-        self.csv = self.csv.record_terminator(term);
+    /// Rows are parsed as CSV records, so a row is the same as a line unless
+    /// it contains a quoted field with a line break. Rows may have any number
+    /// of columns. When rows are skipped, the CSV reader given to
+    /// [`from_csv_reader`](#method.from_csv_reader) is made flexible, and the
+    /// number of columns of each record is checked by this reader instead,
+    /// according to [`flexible`](#method.flexible).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// #[derive(RustcDecodable)]
+    /// struct Record {
+    ///     time: f64,
+    ///     speed: f64,
+    /// }
+    ///
+    /// let data = "\
+    /// Instrument: anemometer
+    /// Started: 2017-06-01 12:00
+    /// time,speed
+    /// 0.0,3.5
+    /// 0.5,4.25
+    /// ";
+    ///
+    /// let mut rdr = typed_csv::Reader::from_string(data).skip_rows(2);
+    /// assert_eq!(rdr.preamble().unwrap()[1], vec![b"Started: 2017-06-01 12:00".to_vec()]);
+    ///
+    /// let rows = rdr.decode().collect::<typed_csv::Result<Vec<Record>>>().unwrap();
+    /// assert_eq!(rows[1].speed, 4.25);
+    /// # }
+    /// ```
+    pub fn skip_rows(mut self, n: usize) -> Reader<'a, R, P> {
+        self.skip_rows = n;
+        self.read_rows_directly()
+    }
+
+    /// Skip the rows before the first row for which `is_header_row` returns
+    /// `true`, which is the header row.
+    ///
+    /// The predicate is given the fields of each row. The skipped rows are
+    /// the preamble, as with [`skip_rows`](#method.skip_rows).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// #[derive(RustcDecodable)]
+    /// struct Record {
+    ///     time: f64,
+    ///     speed: f64,
+    /// }
+    ///
+    /// let data = "\
+    /// Instrument: anemometer
+    /// Units: s,m/s
+    /// time,speed
+    /// 0.0,3.5
+    /// ";
+    ///
+    /// let rdr = typed_csv::Reader::from_string(data)
+    ///     .skip_rows_until(|row| row.first().map_or(false, |field| field == b"time"));
+    /// let rows = rdr.decode().collect::<typed_csv::Result<Vec<Record>>>().unwrap();
+    /// assert_eq!(rows[0].speed, 3.5);
+    /// # }
+    /// ```
+    pub fn skip_rows_until<F>(mut self, is_header_row: F) -> Reader<'a, R, P>
+        where F: Fn(&[ByteString]) -> bool + Send + Sync + 'static
+    {
+        self.skip_until = Some(Box::new(is_header_row));
+        self.read_rows_directly()
+    }
+
+    /// Skip the rows whose first field starts with `prefix`, anywhere in the
+    /// CSV data.
+    ///
+    /// Comment rows before the header row are part of the preamble, as with
+    /// [`skip_rows`](#method.skip_rows). Comment rows after the header row
+    /// are discarded. Since rows are parsed as CSV records, a comment that
+    /// contains the delimiter is split into several fields, and a field of a
+    /// comment that starts with a quote continues until the closing quote.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// #[derive(RustcDecodable)]
+    /// struct Record {
+    ///     time: f64,
+    ///     speed: f64,
+    /// }
+    ///
+    /// let data = "\
+    /// # anemometer
+    /// time,speed
+    /// 0.0,3.5
+    /// # gust
+    /// 0.5,9.0
+    /// ";
+    ///
+    /// let mut rdr = typed_csv::Reader::from_string(data).comment_prefix("#");
+    /// assert_eq!(rdr.preamble().unwrap(), &[vec![b"# anemometer".to_vec()]]);
+    ///
+    /// let rows = rdr.decode().collect::<typed_csv::Result<Vec<Record>>>().unwrap();
+    /// assert_eq!(rows.len(), 2);
+    /// # }
+    /// ```
+    pub fn comment_prefix<S: Into<String>>(mut self, prefix: S) -> Reader<'a, R, P> {
+        self.comment_prefix = Some(prefix.into().into_bytes());
+        self.read_rows_directly()
+    }
+
+    /// Set the number of header rows, which is `1` by default.
+    ///
+    /// Only the first header row is matched to the field names. The other
+    /// header rows, such as units or descriptions of the columns, can be
+    /// retrieved with [`extra_header_rows`](#method.extra_header_rows). This
+    /// has no effect if [`has_headers`](#method.has_headers) is disabled, and
+    /// `0` header rows is the same as disabling it.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// #[derive(RustcDecodable)]
+    /// struct Record {
+    ///     time: f64,
+    ///     speed: f64,
+    /// }
+    ///
+    /// let data = "\
+    /// time,speed
+    /// s,m/s
+    /// 0.0,3.5
+    /// ";
+    ///
+    /// let mut rdr = typed_csv::Reader::from_string(data).header_rows(2);
+    /// assert_eq!(rdr.extra_header_rows().unwrap()[0][1], b"m/s");
+    ///
+    /// let rows = rdr.decode().collect::<typed_csv::Result<Vec<Record>>>().unwrap();
+    /// assert_eq!(rows[0].speed, 3.5);
+    /// # }
+    /// ```
+    pub fn header_rows(mut self, n: usize) -> Reader<'a, R, P> {
+        if n == 0 {
+            return self.has_headers(false);
+        }
+        self.header_rows = n;
         self
     }
 
-    /// Set the quote character to use when reading CSV data.
+    /// Reads the header rows (if they haven't been read yet) and returns the
+    /// ones after the first, with one field for each column.
     ///
-    /// Since the CSV reader is meant to be mostly encoding agnostic, you must
-    /// specify the quote as a single ASCII byte. For example, to read
-    /// single quoted data, you would use `b'\''`.
+    /// This doesn't read any records. The rows are empty unless
+    /// [`header_rows`](#method.header_rows) is greater than `1`.
+    pub fn extra_header_rows(&mut self) -> Result<&[Vec<ByteString>]> {
+        Ok(&self.read_header_row()?.extra_headers)
+    }
+
+    /// Reads the rows before the header row (if they haven't been read yet)
+    /// and returns the ones that were skipped, with the fields of each row.
     ///
-    /// The default value is `b'"'`.
+    /// This doesn't read any records, so the reader can still be used to
+    /// decode the records afterward. The preamble is empty unless rows are
+    /// skipped with [`skip_rows`](#method.skip_rows),
+    /// [`skip_rows_until`](#method.skip_rows_until), or
+    /// [`comment_prefix`](#method.comment_prefix).
+    pub fn preamble(&mut self) -> Result<&[Vec<ByteString>]> {
+        Ok(&self.read_header_row()?.preamble)
+    }
+
+    /// Allow the reader to reorder columns to match headers to field names.
     ///
-    /// If `quote` is `None`, then no quoting will be used.
-    pub fn quote(mut self, quote: u8) -> Reader<'a, R> {
-        self.csv = self.csv.quote(quote);
+    /// By default, the headers must match the field names of the decodable
+    /// type exactly, including the order. However, the ordering of field names
+    /// may not be relevant to your data type, so this option is available.
+    ///
+    /// In the case of duplicate field names, the ordering of columns
+    /// corresponding to those fields will be preserved.
+    ///
+    /// # Examples
+    ///
+    /// This is a simple example that demonstrates reordering the columns to
+    /// match headers to field names:
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// #[derive(Debug, PartialEq, RustcDecodable)]
+    /// struct Record {
+    ///     count: usize,
+    ///     animal: String,
+    ///     description: String,
+    /// }
+    ///
+    /// let data = "\
+    /// count,description,animal
+    /// 7,happy,penguin
+    /// 10,fast,cheetah
+    /// 4,armored,armadillo
+    /// ";
+    ///
+    /// let rdr = typed_csv::Reader::from_string(data);
+    /// let rows = rdr.reorder_columns(true)
+    ///     .decode()
+    ///     .collect::<typed_csv::Result<Vec<Record>>>()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(rows,
+    ///            vec![Record {
+    ///                     count: 7,
+    ///                     animal: "penguin".to_string(),
+    ///                     description: "happy".to_string(),
+    ///                 },
+    ///                 Record {
+    ///                     count: 10,
+    ///                     animal: "cheetah".to_string(),
+    ///                     description: "fast".to_string(),
+    ///                 },
+    ///                 Record {
+    ///                     count: 4,
+    ///                     animal: "armadillo".to_string(),
+    ///                     description: "armored".to_string(),
+    ///                 }]);
+    /// # }
+    /// ```
+    ///
+    /// Duplicate field names in decodable types are fine, and ordering of
+    /// columns with duplicate headers is preserved:
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// #[derive(Debug, PartialEq, RustcDecodable)]
+    /// struct Animal {
+    ///     count: usize,
+    ///     animal: String,
+    /// }
+    ///
+    /// let data = "\
+    /// count,animal,animal,count
+    /// 7,penguin,\"red panda\",2
+    /// 10,cheetah,fennec,9
+    /// 4,armadillo,quokka,3
+    /// ";
+    ///
+    /// type Record = (Animal, Animal);
+    ///
+    /// let rdr = typed_csv::Reader::from_string(data);
+    /// let rows = rdr.reorder_columns(true)
+    ///     .decode()
+    ///     .collect::<typed_csv::Result<Vec<Record>>>()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(rows,
+    ///            vec![(Animal { count: 7, animal: "penguin".to_string() },
+    ///                  Animal { count: 2, animal: "red panda".to_string() }),
+    ///                 (Animal { count: 10, animal: "cheetah".to_string() },
+    ///                  Animal { count: 9, animal: "fennec".to_string() }),
+    ///                 (Animal { count: 4, animal: "armadillo".to_string() },
+    ///                  Animal { count: 3, animal: "quokka".to_string() })]);
+    /// # }
+    /// ```
+    pub fn reorder_columns(mut self, yes: bool) -> Reader<'a, R, P> {
+        self.reorder_columns = yes;
         self
     }
 
-    /// Set the escape character to use when reading CSV data.
-    ///
-    /// Since the CSV reader is meant to be mostly encoding agnostic, you must
-    /// specify the escape as a single ASCII byte.
-    ///
-    /// When set to `None` (which is the default), the "doubling" escape
-    /// is used for quote character.
-    ///
-    /// When set to something other than `None`, it is used as the escape
-    /// character for quotes. (e.g., `b'\\'`.)
-    pub fn escape(mut self, escape: Option<u8>) -> Reader<'a, R> {
-        self.csv = self.csv.escape(escape);
-        self
+    /// Allow the reader to ignore unused columns.
+    ///
+    /// By default, the headers must match the field names of the decodable
+    /// type exactly with no extra columns. However, you may not care if there
+    /// are extra, unused, columns in the CSV file, so this option is
+    /// available.
+    ///
+    /// # Example
+    ///
+    /// In this example, the `pattern` column is unused and ignored:
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// #[derive(Debug, PartialEq, RustcDecodable)]
+    /// struct Record {
+    ///     count: usize,
+    ///     animal: String,
+    ///     description: String,
+    /// }
+    ///
+    /// let data = "\
+    /// count,animal,description,pattern
+    /// 7,penguin,happy,tuxedo
+    /// 10,cheetah,fast,spotted
+    /// 4,armadillo,armored,scaly
+    /// ";
+    ///
+    /// let rdr = typed_csv::Reader::from_string(data);
+    /// let rows = rdr.ignore_unused_columns(true)
+    ///     .decode()
+    ///     .collect::<typed_csv::Result<Vec<Record>>>()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(rows,
+    ///            vec![Record {
+    ///                     count: 7,
+    ///                     animal: "penguin".to_string(),
+    ///                     description: "happy".to_string(),
+    ///                 },
+    ///                 Record {
+    ///                     count: 10,
+    ///                     animal: "cheetah".to_string(),
+    ///                     description: "fast".to_string(),
+    ///                 },
+    ///                 Record {
+    ///                     count: 4,
+    ///                     animal: "armadillo".to_string(),
+    ///                     description: "armored".to_string(),
+    ///                 }]);
+    /// # }
+    /// ```
+    pub fn ignore_unused_columns(mut self, yes: bool) -> Reader<'a, R, P> {
+        self.ignore_unused_columns = yes;
+        self
+    }
+
+    /// When matching headers to field names, use the given predicate.
+    ///
+    /// The default is `<[u8]>::eq`. The first argument to the predicate is the
+    /// header, and the second argument is the field name.
+    ///
+    /// The reader borrows the predicate, so it isn't `Send`. See
+    /// [`headers_match_by_owned`](#method.headers_match_by_owned) for a reader
+    /// that owns it instead.
+    ///
+    /// # Example
+    ///
+    /// This is an example of using a case-insensitive (ASCII) match:
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// use std::ascii::AsciiExt;
+    ///
+    /// #[derive(Debug, PartialEq, RustcDecodable)]
+    /// struct Record {
+    ///     count: usize,
+    ///     animal: String,
+    ///     description: String,
+    /// }
+    ///
+    /// let data = "\
+    /// COUNT,animal,DeScRiPtIoN
+    /// 7,penguin,happy
+    /// 10,cheetah,fast
+    /// 4,armadillo,armored
+    /// ";
+    ///
+    /// let rdr = typed_csv::Reader::from_string(data);
+    /// let rows = rdr.headers_match_by(&<[u8]>::eq_ignore_ascii_case)
+    ///     .decode()
+    ///     .collect::<typed_csv::Result<Vec<Record>>>()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(rows,
+    ///            vec![Record {
+    ///                     count: 7,
+    ///                     animal: "penguin".to_string(),
+    ///                     description: "happy".to_string(),
+    ///                 },
+    ///                 Record {
+    ///                     count: 10,
+    ///                     animal: "cheetah".to_string(),
+    ///                     description: "fast".to_string(),
+    ///                 },
+    ///                 Record {
+    ///                     count: 4,
+    ///                     animal: "armadillo".to_string(),
+    ///                     description: "armored".to_string(),
+    ///                 }]);
+    /// # }
+    /// ```
+    // See https://github.com/Manishearth/rust-clippy/issues/740#issuecomment-277837213
+    #[allow(unknown_lints)]
+    #[allow(needless_lifetimes)]
+    pub fn headers_match_by<'b, Q>(self, pred: &'b Q) -> Reader<'b, R>
+        where Q: Fn(&[u8], &[u8]) -> bool
+    {
+        let pred: &'b Fn(&[u8], &[u8]) -> bool = pred;
+        self.set_headers_match_by(HeadersMatchBy::Borrowed(pred))
+    }
+
+    /// When matching headers to field names, use the given predicate, which
+    /// is owned by the reader.
+    ///
+    /// This is like [`headers_match_by`](#method.headers_match_by), except
+    /// that the reader isn't tied to the lifetime of the predicate, so it can
+    /// be stored anywhere. Since the predicate must be `Send + Sync`, the
+    /// reader (and the iterators created from it) can be sent to other
+    /// threads if the underlying reader can.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// use std::ascii::AsciiExt;
+    /// use std::thread;
+    ///
+    /// #[derive(Debug, PartialEq, RustcDecodable)]
+    /// struct Record {
+    ///     count: usize,
+    ///     animal: String,
+    /// }
+    ///
+    /// let data = "\
+    /// COUNT,Animal
+    /// 7,penguin
+    /// ";
+    ///
+    /// let rdr = typed_csv::Reader::from_string(data)
+    ///     .headers_match_by_owned(<[u8]>::eq_ignore_ascii_case);
+    /// let rows = thread::spawn(move || {
+    ///         rdr.decode().collect::<typed_csv::Result<Vec<Record>>>().unwrap()
+    ///     })
+    ///     .join()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(rows,
+    ///            vec![Record {
+    ///                     count: 7,
+    ///                     animal: "penguin".to_string(),
+    ///                 }]);
+    /// # }
+    /// ```
+    pub fn headers_match_by_owned<Q>(self,
+                                     pred: Q)
+                                     -> Reader<'static, R, Fn(&[u8], &[u8]) -> bool + Send + Sync>
+        where Q: Fn(&[u8], &[u8]) -> bool + Send + Sync + 'static
+    {
+        let pred: Box<Fn(&[u8], &[u8]) -> bool + Send + Sync> = Box::new(pred);
+        self.set_headers_match_by(HeadersMatchBy::Owned(pred))
+    }
+
+    fn set_headers_match_by<'b, Q>(self,
+                                   headers_match_by: HeadersMatchBy<'b, Q>)
+                                   -> Reader<'b, R, Q>
+        where Q: ?Sized
+    {
+        Reader {
+            csv: self.csv,
+            has_headers: self.has_headers,
+            reorder_columns: self.reorder_columns,
+            ignore_unused_columns: self.ignore_unused_columns,
+            headers_match_by: headers_match_by,
+            normalization: self.normalization,
+            field_naming: self.field_naming,
+            error_policy: self.error_policy,
+            field_defaults: self.field_defaults,
+            field_aliases: self.field_aliases,
+            flexible: self.flexible,
+            skip_rows: self.skip_rows,
+            skip_until: self.skip_until,
+            comment_prefix: self.comment_prefix,
+            header_rows: self.header_rows,
+            header_row: self.header_row,
+            at_start: self.at_start,
+        }
+    }
+
+    /// Normalize headers and field names (and aliases) before comparing them.
+    ///
+    /// The normalized values are compared with the predicate given to
+    /// [`headers_match_by`](#method.headers_match_by), both when the columns
+    /// must be in order and when they can be reordered. Error messages
+    /// contain the headers and field names as they are, not normalized.
+    ///
+    /// The default is `Normalization::NONE`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// use typed_csv::Normalization;
+    ///
+    /// #[derive(Debug, PartialEq, RustcDecodable)]
+    /// struct Record {
+    ///     animal_count: usize,
+    ///     animal: String,
+    /// }
+    ///
+    /// let data = "\
+    /// Animal Count , AnimalName
+    /// 7,penguin
+    /// ";
+    ///
+    /// let rdr = typed_csv::Reader::from_string(data);
+    /// let rows = rdr.normalize_headers(Normalization::TRIM | Normalization::WORD_CASE)
+    ///     .rename_field("animal", "animal_name")
+    ///     .decode()
+    ///     .collect::<typed_csv::Result<Vec<Record>>>()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(rows,
+    ///            vec![Record {
+    ///                     animal_count: 7,
+    ///                     animal: "penguin".to_string(),
+    ///                 }]);
+    /// # }
+    /// ```
+    pub fn normalize_headers(mut self, normalization: Normalization) -> Reader<'a, R, P> {
+        self.normalization = normalization;
+        self
+    }
+
+    /// Accept any of the given headers, in addition to the field name, for
+    /// the field with the given name.
+    ///
+    /// The aliases are compared to headers with the same predicate as field
+    /// names (see [`headers_match_by`](#method.headers_match_by)), both when
+    /// the columns must be in order and when they can be reordered. Calling
+    /// this more than once for the same field adds to its aliases.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// #[derive(Debug, PartialEq, RustcDecodable)]
+    /// struct Record {
+    ///     count: usize,
+    ///     animal: String,
+    /// }
+    ///
+    /// let data = "\
+    /// cnt,animal
+    /// 7,penguin
+    /// ";
+    ///
+    /// let rdr = typed_csv::Reader::from_string(data);
+    /// let rows = rdr.field_aliases("count", &["cnt", "n"])
+    ///     .decode()
+    ///     .collect::<typed_csv::Result<Vec<Record>>>()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(rows,
+    ///            vec![Record {
+    ///                     count: 7,
+    ///                     animal: "penguin".to_string(),
+    ///                 }]);
+    /// # }
+    /// ```
+    pub fn field_aliases<S, I>(mut self, field_name: S, aliases: I) -> Reader<'a, R, P>
+        where S: Into<String>,
+              I: IntoIterator,
+              I::Item: AsRef<str>
+    {
+        self.field_aliases
+            .entry(field_name.into().into_bytes())
+            .or_insert_with(Vec::new)
+            .extend(aliases.into_iter().map(|alias| alias.as_ref().as_bytes().to_vec()));
+        self
+    }
+
+    /// Expect the given header instead of the field name `field_name`.
+    ///
+    /// This is the counterpart of `Writer::rename_field`, so a file written
+    /// with a renamed field can be read with the same renaming. The field
+    /// name is the name after flattening nested structs and expanding arrays
+    /// and tuples, e.g. `position_0` or `size.width`. This takes precedence
+    /// over [`rename_fields_with`](#method.rename_fields_with).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// #[derive(Debug, PartialEq, RustcDecodable)]
+    /// struct Record {
+    ///     count: usize,
+    ///     animal: String,
+    /// }
+    ///
+    /// let data = "\
+    /// Animal Count,animal
+    /// 7,penguin
+    /// ";
+    ///
+    /// let rdr = typed_csv::Reader::from_string(data);
+    /// let rows = rdr.rename_field("count", "Animal Count")
+    ///     .decode()
+    ///     .collect::<typed_csv::Result<Vec<Record>>>()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(rows,
+    ///            vec![Record {
+    ///                     count: 7,
+    ///                     animal: "penguin".to_string(),
+    ///                 }]);
+    /// # }
+    /// ```
+    pub fn rename_field<S, T>(mut self, field_name: S, header: T) -> Reader<'a, R, P>
+        where S: Into<String>,
+              T: Into<String>
+    {
+        self.field_naming.rename(field_name.into(), header.into());
+        self
+    }
+
+    /// Expect the header returned by `rename` instead of each field name.
+    ///
+    /// This is the counterpart of `Writer::rename_fields_with`.
+    pub fn rename_fields_with<F>(mut self, rename: F) -> Reader<'a, R, P>
+        where F: Fn(&str) -> String + Send + Sync + 'static
+    {
+        self.field_naming.set_rename_fn(Arc::new(rename));
+        self
+    }
+
+    /// Set the separator used to join the field names of nested structs.
+    ///
+    /// A struct field that is itself a struct is flattened into one column
+    /// per field of the inner struct. The header of each of those columns
+    /// must match the name of the outer field and the name of the inner
+    /// field, joined with this separator.
+    ///
+    /// The default value is `"."`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// #[derive(Debug, PartialEq, RustcDecodable)]
+    /// struct Point {
+    ///     x: i32,
+    ///     y: i32,
+    /// }
+    ///
+    /// #[derive(Debug, PartialEq, RustcDecodable)]
+    /// struct Record {
+    ///     name: String,
+    ///     position: Point,
+    /// }
+    ///
+    /// let data = "\
+    /// name,position_x,position_y
+    /// origin,0,0
+    /// corner,3,-4
+    /// ";
+    ///
+    /// let rdr = typed_csv::Reader::from_string(data);
+    /// let rows = rdr.nested_field_separator("_")
+    ///     .decode()
+    ///     .collect::<typed_csv::Result<Vec<Record>>>()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(rows,
+    ///            vec![Record {
+    ///                     name: "origin".to_string(),
+    ///                     position: Point { x: 0, y: 0 },
+    ///                 },
+    ///                 Record {
+    ///                     name: "corner".to_string(),
+    ///                     position: Point { x: 3, y: -4 },
+    ///                 }]);
+    /// # }
+    /// ```
+    pub fn nested_field_separator<S: Into<String>>(mut self, separator: S) -> Reader<'a, R, P> {
+        self.field_naming.set_separator(separator.into());
+        self
+    }
+
+    /// Set the pattern used to name the elements of arrays and tuples.
+    ///
+    /// A struct field that is a fixed-size array or a tuple with more than
+    /// one element is expanded into one column per element. The header of
+    /// each of those columns must match this pattern, with `{name}` replaced
+    /// by the field name and `{index}` replaced by the index of the element
+    /// (starting at `0`).
+    ///
+    /// The default value is `"{name}_{index}"`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// #[derive(Debug, PartialEq, RustcDecodable)]
+    /// struct Record {
+    ///     name: String,
+    ///     position: [i32; 2],
+    ///     size: (u32, u32),
+    /// }
+    ///
+    /// let data = "\
+    /// name,position[0],position[1],size[0],size[1]
+    /// square,3,-4,10,10
+    /// ";
+    ///
+    /// let rdr = typed_csv::Reader::from_string(data);
+    /// let rows = rdr.indexed_field_pattern("{name}[{index}]")
+    ///     .decode()
+    ///     .collect::<typed_csv::Result<Vec<Record>>>()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(rows,
+    ///            vec![Record {
+    ///                     name: "square".to_string(),
+    ///                     position: [3, -4],
+    ///                     size: (10, 10),
+    ///                 }]);
+    /// # }
+    /// ```
+    pub fn indexed_field_pattern<S: Into<String>>(mut self, pattern: S) -> Reader<'a, R, P> {
+        self.field_naming.set_indexed_pattern(pattern.into());
+        self
+    }
+
+    /// Allow the field with the given name to not have a matching column,
+    /// in which case it's decoded from an empty field.
+    ///
+    /// Fields of type `Option<T>` are always allowed to not have a matching
+    /// column, and they're decoded as `None` if there isn't one. This method
+    /// is useful for other types that can be decoded from an empty field,
+    /// such as `String`. It's equivalent to `field_default(field_name, "")`.
+    pub fn optional_field<S: Into<String>>(self, field_name: S) -> Reader<'a, R, P> {
+        self.field_default(field_name, "")
+    }
+
+    /// Allow the field with the given name to not have a matching column,
+    /// in which case it's decoded from `value`.
+    ///
+    /// The field name is the name after flattening nested structs and
+    /// expanding arrays and tuples, e.g. `position_0` or `size.width`.
+    ///
+    /// # Example
+    ///
+    /// This example reads data that predates the `legs` column:
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// #[derive(Debug, PartialEq, RustcDecodable)]
+    /// struct Record {
+    ///     count: usize,
+    ///     animal: String,
+    ///     legs: usize,
+    ///     description: Option<String>,
+    /// }
+    ///
+    /// let data = "\
+    /// count,animal
+    /// 7,penguin
+    /// ";
+    ///
+    /// let rdr = typed_csv::Reader::from_string(data);
+    /// let rows = rdr.field_default("legs", "4")
+    ///     .decode()
+    ///     .collect::<typed_csv::Result<Vec<Record>>>()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(rows,
+    ///            vec![Record {
+    ///                     count: 7,
+    ///                     animal: "penguin".to_string(),
+    ///                     legs: 4,
+    ///                     description: None,
+    ///                 }]);
+    /// # }
+    /// ```
+    pub fn field_default<S, V>(mut self, field_name: S, value: V) -> Reader<'a, R, P>
+        where S: Into<String>,
+              V: Into<Vec<u8>>
+    {
+        self.field_defaults.insert(field_name.into().into_bytes(), value.into());
+        self
+    }
+
+    /// Set what to do with records that can't be decoded.
+    ///
+    /// By default (`ErrorPolicy::FailFast`), the iteration stops at the
+    /// first error. With `ErrorPolicy::SkipAndLog` or `ErrorPolicy::Collect`,
+    /// records that can't be decoded are skipped instead. The skipped records
+    /// are available from the `rejected` method of the iterator if the policy
+    /// is `ErrorPolicy::Collect`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// use typed_csv::ErrorPolicy;
+    ///
+    /// #[derive(Debug, PartialEq, RustcDecodable)]
+    /// struct Record {
+    ///     count: usize,
+    ///     animal: String,
+    /// }
+    ///
+    /// let data = "\
+    /// count,animal
+    /// 7,penguin
+    /// many,ant
+    /// 4,armadillo
+    /// ";
+    ///
+    /// let rdr = typed_csv::Reader::from_string(data);
+    /// let mut records = rdr.error_policy(ErrorPolicy::Collect).decode();
+    /// let rows = records.by_ref().collect::<typed_csv::Result<Vec<Record>>>().unwrap();
+    ///
+    /// assert_eq!(rows,
+    ///            vec![Record {
+    ///                     count: 7,
+    ///                     animal: "penguin".to_string(),
+    ///                 },
+    ///                 Record {
+    ///                     count: 4,
+    ///                     animal: "armadillo".to_string(),
+    ///                 }]);
+    /// let rejected = records.rejected();
+    /// assert_eq!(rejected.len(), 1);
+    /// assert_eq!(rejected[0].fields, vec![b"many".to_vec(), b"ant".to_vec()]);
+    /// # }
+    /// ```
+    pub fn error_policy(mut self, policy: ErrorPolicy) -> Reader<'a, R, P> {
+        self.error_policy = policy;
+        self
+    }
+
+    /// The delimiter to use when reading CSV data.
+    ///
+    /// Since the CSV reader is meant to be mostly encoding agnostic, you must
+    /// specify the delimiter as a single ASCII byte. For example, to read
+    /// tab-delimited data, you would use `b'\t'`.
+    ///
+    /// The default value is `b','`.
+    pub fn delimiter(mut self, delimiter: u8) -> Reader<'a, R, P> {
+        self.csv = self.csv.delimiter(delimiter);
+        self
+    }
+
+    /// Set the record terminator to use when reading CSV data.
+    ///
+    /// In the vast majority of situations, you'll want to use the default
+    /// value, `RecordTerminator::CRLF`, which automatically handles `\r`,
+    /// `\n` or `\r\n` as record terminators. (Notably, this is a special
+    /// case since two characters can correspond to a single terminator token.)
+    ///
+    /// However, you may use `RecordTerminator::Any` to specify any ASCII
+    /// character to use as the record terminator. For example, you could
+    /// use `RecordTerminator::Any(b'\n')` to only accept line feeds as
+    /// record terminators, or `b'\x1e'` for the ASCII record separator.
+    pub fn record_terminator(mut self, term: RecordTerminator) -> Reader<'a, R, P> {
+        self.csv = self.csv.record_terminator(term);
+        self
+    }
+
+    /// Set the quote character to use when reading CSV data.
+    ///
+    /// Since the CSV reader is meant to be mostly encoding agnostic, you must
+    /// specify the quote as a single ASCII byte. For example, to read
+    /// single quoted data, you would use `b'\''`.
+    ///
+    /// The default value is `b'"'`.
+    ///
+    /// If `quote` is `None`, then no quoting will be used.
+    pub fn quote(mut self, quote: u8) -> Reader<'a, R, P> {
+        self.csv = self.csv.quote(quote);
+        self
+    }
+
+    /// Set the escape character to use when reading CSV data.
+    ///
+    /// Since the CSV reader is meant to be mostly encoding agnostic, you must
+    /// specify the escape as a single ASCII byte.
+    ///
+    /// When set to `None` (which is the default), the "doubling" escape
+    /// is used for quote character.
+    ///
+    /// When set to something other than `None`, it is used as the escape
+    /// character for quotes. (e.g., `b'\\'`.)
+    pub fn escape(mut self, escape: Option<u8>) -> Reader<'a, R, P> {
+        self.csv = self.csv.escape(escape);
+        self
+    }
+
+    /// Enable double quote escapes.
+    ///
+    /// When disabled, doubled quotes are not interpreted as escapes.
+    pub fn double_quote(mut self, yes: bool) -> Reader<'a, R, P> {
+        self.csv = self.csv.double_quote(yes);
+        self
+    }
+
+    /// A convenience method for reading ASCII delimited text.
+    ///
+    /// This sets the delimiter and record terminator to the ASCII unit
+    /// separator (`\x1f`) and record separator (`\x1e`), respectively.
+    ///
+    /// Since ASCII delimited text is meant to be unquoted, this also sets
+    /// `quote` to `None`.
+    pub fn ascii(mut self) -> Reader<'a, R, P> {
+        self.csv = self.csv.ascii();
+        self
+    }
+
+    /// Set the delimiter, quote, escape, and record terminator options from
+    /// a `Dialect`.
+    ///
+    /// The quoting style of the dialect is only used when writing.
+    pub fn dialect(self, dialect: Dialect) -> Reader<'a, R, P> {
+        self.delimiter(dialect.delimiter)
+            .quote(dialect.quote)
+            .escape(dialect.escape)
+            .double_quote(dialect.double_quote)
+            .record_terminator(dialect.record_terminator)
+    }
+}
+
+/// An iterator of decoded records.
+///
+/// The lifetime parameter `'a` refers to the lifetime of the predicate used
+/// for comparing headers to field names, if it's borrowed. The `R` type
+/// parameter refers to the type of the underlying reader. The `D` type
+/// parameter refers to the decoded type. The `P` type parameter is the type
+/// of the predicate, as for [`Reader`](struct.Reader.html).
+pub struct DecodedRecords<'a, R, D, P: ?Sized + 'a = Fn(&[u8], &[u8]) -> bool + 'a>
+    where R: Read,
+          D: Decodable
+{
+    records: MappedRecords<'a, R, P>,
+    record_type: PhantomData<D>,
+}
+
+impl<'a, R: Read, D: Decodable, P: ?Sized + Fn(&[u8], &[u8]) -> bool> DecodedRecords<'a, R, D, P> {
+    /// Returns the records that have been skipped because they couldn't be
+    /// decoded.
+    ///
+    /// This is always empty unless the error policy is
+    /// `ErrorPolicy::Collect`.
+    pub fn rejected(&self) -> &[RejectedRecord] {
+        &self.records.rejected
+    }
+
+    /// Consumes the iterator and returns the records that have been skipped
+    /// because they couldn't be decoded.
+    pub fn into_rejected(self) -> Vec<RejectedRecord> {
+        self.records.rejected
+    }
+
+    /// Returns a reference to the underlying CSV reader.
+    pub fn as_csv_reader(&self) -> &csv::Reader<R> {
+        &self.records.rdr.csv
+    }
+
+    /// Consumes the iterator and returns the underlying CSV reader, which is
+    /// positioned after the last record that was read.
+    pub fn into_inner(self) -> csv::Reader<R> {
+        self.records.rdr.csv
+    }
+
+    /// Returns the rows that were skipped before the header row, reading
+    /// them if they haven't been read yet.
+    ///
+    /// See [`Reader::preamble`](struct.Reader.html#method.preamble).
+    pub fn preamble(&mut self) -> Result<&[Vec<ByteString>]> {
+        self.records.rdr.preamble()
+    }
+
+    /// Returns the header rows after the first, reading them if they haven't
+    /// been read yet.
+    ///
+    /// See [`Reader::extra_header_rows`](struct.Reader.html#method.extra_header_rows).
+    pub fn extra_header_rows(&mut self) -> Result<&[Vec<ByteString>]> {
+        self.records.rdr.extra_header_rows()
+    }
+
+    /// Reads the header row (if it hasn't been read yet), checks it against
+    /// the field names, and returns the mapping of columns to fields.
+    ///
+    /// This doesn't read any records. If the headers don't match the field
+    /// names, the error is returned and the iterator won't return any
+    /// records.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// #[derive(RustcDecodable)]
+    /// struct Record {
+    ///     count: usize,
+    ///     animal: String,
+    /// }
+    ///
+    /// let data = "\
+    /// animal,notes,count
+    /// penguin,happy,7
+    /// ";
+    ///
+    /// let rdr = typed_csv::Reader::from_string(data);
+    /// let mut records = rdr.reorder_columns(true).ignore_unused_columns(true).decode::<Record>();
+    /// let mapping = records.column_mapping().unwrap();
+    ///
+    /// assert_eq!(mapping.to_string(), "\
+    /// 0: 'animal' -> field 'animal'
+    /// 1: 'notes' -> ignored
+    /// 2: 'count' -> field 'count'");
+    /// assert_eq!(mapping.ignored_columns(), vec![1]);
+    /// # }
+    /// ```
+    pub fn column_mapping(&mut self) -> Result<ColumnMapping> {
+        self.records.column_mapping()
+    }
+}
+
+impl<'a, R, D, P> Iterator for DecodedRecords<'a, R, D, P>
+    where R: Read,
+          D: Decodable,
+          P: ?Sized + Fn(&[u8], &[u8]) -> bool
+{
+    type Item = Result<D>;
+
+    fn next(&mut self) -> Option<Result<D>> {
+        self.records.next_with(|record, records| {
+            let position = record.position;
+            let mut decoder = RecordDecoder::new(record, &records.fields.seq_lens);
+            Decodable::decode(&mut decoder).map_err(|err| match err {
+                csv::Error::Decode(message) => {
+                    records.decode_error(position, message, decoder.error_field())
+                }
+                err => Error::from(err),
+            })
+        })
+    }
+}
+
+/// An iterator of deserialized records.
+///
+/// This is the equivalent of [`DecodedRecords`](struct.DecodedRecords.html)
+/// for types that implement `serde::Deserialize`.
+///
+/// The lifetime parameter `'a` refers to the lifetime of the predicate used
+/// for comparing headers to field names, if it's borrowed. The `R` type
+/// parameter refers to the type of the underlying reader. The `D` type
+/// parameter refers to the deserialized type. The `P` type parameter is the
+/// type of the predicate, as for [`Reader`](struct.Reader.html).
+#[cfg(feature = "serde")]
+pub struct DeserializedRecords<'a, R, D, P: ?Sized + 'a = Fn(&[u8], &[u8]) -> bool + 'a>
+    where R: Read,
+          D: DeserializeOwned
+{
+    records: MappedRecords<'a, R, P>,
+    record_type: PhantomData<D>,
+}
+
+#[cfg(feature = "serde")]
+impl<'a, R, D, P> DeserializedRecords<'a, R, D, P>
+    where R: Read,
+          D: DeserializeOwned,
+          P: ?Sized + Fn(&[u8], &[u8]) -> bool
+{
+    /// Returns the records that have been skipped because they couldn't be
+    /// deserialized.
+    ///
+    /// This is always empty unless the error policy is
+    /// `ErrorPolicy::Collect`.
+    pub fn rejected(&self) -> &[RejectedRecord] {
+        &self.records.rejected
+    }
+
+    /// Consumes the iterator and returns the records that have been skipped
+    /// because they couldn't be deserialized.
+    pub fn into_rejected(self) -> Vec<RejectedRecord> {
+        self.records.rejected
+    }
+
+    /// Returns a reference to the underlying CSV reader.
+    pub fn as_csv_reader(&self) -> &csv::Reader<R> {
+        &self.records.rdr.csv
+    }
+
+    /// Consumes the iterator and returns the underlying CSV reader, which is
+    /// positioned after the last record that was read.
+    pub fn into_inner(self) -> csv::Reader<R> {
+        self.records.rdr.csv
+    }
+
+    /// Returns the rows that were skipped before the header row, reading
+    /// them if they haven't been read yet.
+    ///
+    /// See [`Reader::preamble`](struct.Reader.html#method.preamble).
+    pub fn preamble(&mut self) -> Result<&[Vec<ByteString>]> {
+        self.records.rdr.preamble()
+    }
+
+    /// Returns the header rows after the first, reading them if they haven't
+    /// been read yet.
+    ///
+    /// See [`Reader::extra_header_rows`](struct.Reader.html#method.extra_header_rows).
+    pub fn extra_header_rows(&mut self) -> Result<&[Vec<ByteString>]> {
+        self.records.rdr.extra_header_rows()
+    }
+
+    /// Reads the header row (if it hasn't been read yet), checks it against
+    /// the field names, and returns the mapping of columns to fields.
+    ///
+    /// This doesn't read any records. If the headers don't match the field
+    /// names, the error is returned and the iterator won't return any
+    /// records.
+    pub fn column_mapping(&mut self) -> Result<ColumnMapping> {
+        self.records.column_mapping()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'a, R, D, P> Iterator for DeserializedRecords<'a, R, D, P>
+    where R: Read,
+          D: DeserializeOwned,
+          P: ?Sized + Fn(&[u8], &[u8]) -> bool
+{
+    type Item = Result<D>;
+
+    fn next(&mut self) -> Option<Result<D>> {
+        self.records.next_with(|record, records| {
+            let position = record.position;
+            let mut deserializer = RecordDeserializer::new(record, &records.fields.seq_lens);
+            loop {
+                match D::deserialize(&mut deserializer) {
+                    Ok(value) => return Ok(value),
+                    Err(_) if deserializer.retry() => (),
+                    Err(err) => {
+                        return Err(records.decode_error(position,
+                                                        err.into_message(),
+                                                        deserializer.error_field()))
+                    }
+                }
+            }
+        })
+    }
+}
+
+impl<'a, R: Read, P: ?Sized + Fn(&[u8], &[u8]) -> bool> Reader<'a, R, P> {
+    /// Determines the mapping of columns to fields according to the headers,
+    /// the fields of the record type, and the options of the reader.
+    fn map_columns(&self,
+                   headers: &[ByteString],
+                   fields: &RecordFields)
+                   -> Result<Vec<Option<usize>>> {
+        // Without a header row, the columns are matched to the fields by
+        // position.
+        if !self.has_headers {
+            let len = fields.names.len();
+            if fields.catch_all.is_some() {
+                let msg = "A catch-all map requires a header row".to_string();
+                return Err(Error::Csv(csv::Error::Decode(msg)));
+            }
+            if headers.len() < len || (headers.len() > len && !self.ignore_unused_columns) {
+                let msg = format!("The first record has {} columns, but the record type has {} \
+                                   fields",
+                                  headers.len(),
+                                  len);
+                return Err(Error::Csv(csv::Error::Decode(msg)));
+            }
+            return Ok((0..headers.len()).map(|index| Some(index).filter(|&i| i < len)).collect());
+        }
+
+        // Fields that are `Option`s or have a default value are allowed to
+        // not have a matching header.
+        let optional = fields.names
+            .iter()
+            .zip(&fields.optional)
+            .map(|(name, &optional)| optional || self.field_defaults.contains_key(name))
+            .collect::<Vec<_>>();
+
+        let aliases = fields.names
+            .iter()
+            .map(|name| self.field_aliases.get(name).map_or(&[][..], |a| &a[..]))
+            .collect::<Vec<_>>();
+
+        // The headers are matched against the renamed field names.
+        let renamed = fields.names
+            .iter()
+            .map(|name| self.field_naming.header(name))
+            .collect::<Vec<_>>();
+
+        // A catch-all map receives the unused columns instead of them being
+        // an error.
+        let mapping = map_headers(headers,
+                                  &renamed,
+                                  &aliases,
+                                  &optional,
+                                  self.normalization,
+                                  self.reorder_columns,
+                                  self.ignore_unused_columns || fields.catch_all.is_some(),
+                                  self.headers_match_by.get())?;
+        Ok(mapping)
+    }
+
+    /// Returns the fields of the record type, with the lengths of the
+    /// sequences of variable length (such as `Vec`s) determined by the
+    /// headers.
+    ///
+    /// Each such sequence has as many elements as there are consecutive
+    /// indexed headers for them, like `a_0,a_1,a_2`. A sequence with one
+    /// element has the unindexed header `a`, as written by `Writer`. Without
+    /// a header row, the sequences are empty.
+    fn record_fields(&self,
+                     headers: &[ByteString],
+                     field_names: FieldNamesFn)
+                     -> Result<RecordFields> {
+        let mut var_lens = Vec::new();
+        let mut fields = field_names(&self.field_naming, &var_lens)?;
+        if !self.has_headers {
+            return Ok(fields);
+        }
+        while var_lens.len() < fields.var_seqs.len() {
+            let index = var_lens.len();
+            var_lens.push(2);
+            let mut matched = None;
+            loop {
+                let candidate = field_names(&self.field_naming, &var_lens)?;
+                if !self.has_var_seq_headers(headers, &candidate, index) {
+                    break;
+                }
+                matched = Some(candidate);
+                var_lens[index] += 1;
+            }
+            if let Some(matched) = matched {
+                var_lens[index] -= 1;
+                fields = matched;
+                continue;
+            }
+            // The elements only have indexed field names if there are at
+            // least two of them.
+            var_lens[index] = 1;
+            let candidate = field_names(&self.field_naming, &var_lens)?;
+            if self.has_var_seq_headers(headers, &candidate, index) {
+                fields = candidate;
+            } else {
+                var_lens[index] = 0;
+            }
+        }
+        Ok(fields)
+    }
+
+    /// Returns `true` if every field name of the sequence of variable length
+    /// with the given index has a matching header, according to the same
+    /// rules as `map_columns`.
+    fn has_var_seq_headers(&self,
+                           headers: &[ByteString],
+                           fields: &RecordFields,
+                           index: usize)
+                           -> bool {
+        let (start, end) = fields.var_seqs[index];
+        if start == end {
+            return false;
+        }
+        let names = &fields.names[start..end];
+        let aliases = names.iter()
+            .map(|name| self.field_aliases.get(name).map_or(&[][..], |a| &a[..]))
+            .collect::<Vec<_>>();
+        let renamed = names.iter().map(|name| self.field_naming.header(name)).collect::<Vec<_>>();
+        map_headers(headers,
+                    &renamed,
+                    &aliases,
+                    &vec![false; names.len()],
+                    self.normalization,
+                    true,
+                    true,
+                    self.headers_match_by.get())
+            .is_ok()
+    }
+
+    /// Reads the header row and checks it against the fields of a record
+    /// type, without consuming the header row.
+    fn check_headers_with(&mut self,
+                          field_names: FieldNamesFn)
+                          -> Result<()> {
+        let headers = self.read_header_row()?.headers.clone();
+        // If the header row is empty, then the CSV data contains no records.
+        if headers.is_empty() {
+            return Ok(());
+        }
+        let fields = self.record_fields(&headers, field_names)?;
+        self.map_columns(&headers, &fields)?;
+        Ok(())
+    }
+
+    /// Returns `true` if rows are skipped before the header row.
+    ///
+    /// In that case, the header row is read by this reader instead of by the
+    /// CSV reader, which is flexible and doesn't have a header row.
+    fn skips_rows(&self) -> bool {
+        self.skip_rows > 0 || self.skip_until.is_some() || self.comment_prefix.is_some()
+    }
+
+    /// Configures the CSV reader so that this reader can read the rows before
+    /// the header row.
+    fn read_rows_directly(mut self) -> Reader<'a, R, P> {
+        self.csv = self.csv.has_headers(false).flexible(true);
+        self
+    }
+
+    /// Returns `true` if `row` is a comment.
+    fn is_comment(&self, row: &[ByteString]) -> bool {
+        match (&self.comment_prefix, row.first()) {
+            (&Some(ref prefix), Some(field)) => field.starts_with(prefix),
+            _ => false,
+        }
+    }
+
+    /// Reads the fields of the next row. The row is empty at the end of the
+    /// CSV data.
+    fn read_row(&mut self) -> Result<Vec<ByteString>> {
+        let mut row = Vec::new();
+        self.read_row_into(&mut row)?;
+        Ok(row)
+    }
+
+    /// Reads the fields of the next row into `row`, which is empty at the end
+    /// of the CSV data.
+    ///
+    /// If there is an error, `row` contains the fields that were read before
+    /// it. The CSV reader returns a parse error instead of the end of a row
+    /// that has a different number of fields than the first row, so in that
+    /// case, `row` is complete and the next row can still be read.
+    fn read_row_into(&mut self, row: &mut Vec<ByteString>) -> Result<()> {
+        let result = loop {
+            match self.csv.next_bytes() {
+                NextField::EndOfRecord | NextField::EndOfCsv => break Ok(()),
+                NextField::Error(err) => break Err(Error::from(err)),
+                NextField::Data(field) => row.push(field.to_vec()),
+            }
+        };
+        if self.at_start {
+            strip_bom(row);
+            self.at_start = false;
+        }
+        result
+    }
+
+    /// Reads the header row and the rows before it, if they haven't been read
+    /// yet.
+    fn read_header_row(&mut self) -> Result<&HeaderRow> {
+        if self.header_row.is_none() {
+            let header_row = if self.skips_rows() {
+                self.read_preamble()?
+            } else {
+                // If headers have been read before this point, then this is
+                // equivalent to a harmless clone (and no parser progression).
+                let mut headers = self.csv.byte_headers()?;
+                strip_bom(&mut headers);
+                // Without a header row, the first record is read again and
+                // its byte order mark must also be removed.
+                self.at_start = !self.has_headers;
+                let lines = if self.has_headers { count_row_lines(&headers) } else { 0 };
+                HeaderRow {
+                    preamble: Vec::new(),
+                    headers: headers,
+                    extra_headers: Vec::new(),
+                    lines: lines,
+                    first_record: None,
+                }
+            };
+            self.header_row = Some(header_row);
+            self.read_extra_headers()?;
+        }
+        Ok(self.header_row.as_ref().unwrap())
+    }
+
+    /// Reads the header rows after the first one.
+    fn read_extra_headers(&mut self) -> Result<()> {
+        let has_header_row = self.has_headers &&
+                             self.header_row.as_ref().map_or(false, |h| !h.headers.is_empty());
+        if !has_header_row {
+            return Ok(());
+        }
+        for _ in 1..self.header_rows {
+            let mut row = self.read_row()?;
+            while self.is_comment(&row) {
+                self.header_row.as_mut().unwrap().lines += count_row_lines(&row);
+                row = self.read_row()?;
+            }
+            if row.is_empty() {
+                break;
+            }
+            let header_row = self.header_row.as_mut().unwrap();
+            header_row.lines += count_row_lines(&row);
+            header_row.extra_headers.push(row);
+        }
+        Ok(())
+    }
+
+    /// Reads the skipped rows and the header row.
+    fn read_preamble(&mut self) -> Result<HeaderRow> {
+        let mut header_row = HeaderRow {
+            preamble: Vec::new(),
+            headers: Vec::new(),
+            extra_headers: Vec::new(),
+            lines: 0,
+            first_record: None,
+        };
+        loop {
+            let byte = self.csv.byte_offset();
+            let row = self.read_row()?;
+            if row.is_empty() {
+                return Ok(header_row);
+            }
+            let skip = header_row.preamble.len() < self.skip_rows || self.is_comment(&row) ||
+                       self.skip_until.as_ref().map_or(false, |is_header_row| !is_header_row(&row));
+            if skip {
+                header_row.lines += count_row_lines(&row);
+                header_row.preamble.push(row);
+            } else if self.has_headers {
+                header_row.lines += count_row_lines(&row);
+                header_row.headers = row;
+                return Ok(header_row);
+            } else {
+                // Without a header row, this is the first record.
+                header_row.headers = row.clone();
+                header_row.first_record = Some((byte, row));
+                return Ok(header_row);
+            }
+        }
+    }
+}
+
+/// Determinines mapping of columns to fields according to headers and field names.
+///
+/// The mapping is a `Vec` of indices, where the indices of the `Vec` are the
+/// column indices, and the values of the `Vec` are the (optional) field indices.
+///
+/// The first argument to the predicate is the header, and the second argument
+/// is the field name, both normalized with `normalization`. Headers that match
+/// one of the `aliases` of a field are also matched to it. Fields for which
+/// `optional` is `true` are allowed to not have a matching header.
+pub fn map_headers<P>(headers: &[ByteString],
+                      field_names: &[ByteString],
+                      aliases: &[&[ByteString]],
+                      optional: &[bool],
+                      normalization: Normalization,
+                      reorder: bool,
+                      ignore_unused_columns: bool,
+                      predicate: &P)
+                      -> result::Result<Vec<Option<usize>>, HeaderMismatch>
+    where P: ?Sized + Fn(&[u8], &[u8]) -> bool
+{
+    let mut mismatch = HeaderMismatch::new(headers, field_names);
+    let normalize = |names: &[ByteString]| -> Vec<ByteString> {
+        names.iter().map(|name| normalization.apply(name).into_owned()).collect()
+    };
+    let field_names = normalize(field_names);
+    let aliases = aliases.iter().map(|aliases| normalize(aliases)).collect::<Vec<_>>();
+    let headers = normalize(headers);
+    let headers = &headers[..];
+    let mut mapping = vec![None; headers.len()];
+    // Cursor to keep track of starting position in `headers` slice. It stays
+    // at zero if the order doesn't matter.
+    let mut cursor = 0;
+    for (field_index, field_name) in field_names.iter().enumerate() {
+        // Search for the first matching header that hasn't been used yet,
+        // starting from `cursor`. Headers before `cursor` are only considered
+        // in order to report them as out of order.
+        let found = {
+            let matches = |header_index: &usize| {
+                let header = &headers[*header_index];
+                mapping[*header_index].is_none() &&
+                (predicate(header, field_name) ||
+                 aliases[field_index].iter().any(|alias| predicate(header, alias)))
+            };
+            (cursor..headers.len()).find(&matches).or_else(|| (0..cursor).find(&matches))
+        };
+        match found {
+            Some(header_index) => {
+                mapping[header_index] = Some(field_index);
+                // If the order doesn't matter, `cursor` stays at zero.
+                if reorder {
+                    continue;
+                }
+                if header_index >= cursor {
+                    cursor = header_index + 1;
+                } else {
+                    mismatch.out_of_order.push(OutOfOrder {
+                        header_index: header_index,
+                        field_index: field_index,
+                        header: mismatch.headers[header_index].clone(),
+                    });
+                }
+            }
+            None if optional[field_index] => (),
+            None => {
+                mismatch.missing_fields.push(MissingField {
+                    field_index: field_index,
+                    field_name: mismatch.field_names[field_index].clone(),
+                    aliases: aliases[field_index]
+                        .iter()
+                        .map(|alias| String::from_utf8_lossy(alias).into_owned())
+                        .collect(),
+                });
+            }
+        }
+    }
+    if !ignore_unused_columns {
+        for (header_index, header) in headers.iter().enumerate() {
+            if mapping[header_index].is_some() {
+                continue;
+            }
+            match headers[..header_index].iter().position(|h| h == header) {
+                Some(first_index) => {
+                    mismatch.duplicate_headers.push(DuplicateHeader {
+                        header_index: header_index,
+                        first_index: first_index,
+                        header: mismatch.headers[header_index].clone(),
+                    });
+                }
+                None => {
+                    mismatch.unexpected_headers.push(UnexpectedHeader {
+                        header_index: header_index,
+                        header: mismatch.headers[header_index].clone(),
+                    });
+                }
+            }
+        }
+    }
+    if mismatch.is_empty() {
+        Ok(mapping)
+    } else {
+        Err(mismatch)
+    }
+}
+
+/// Returns the field names of the decodable type `D` and the index of its
+/// catch-all map, if it has one. These are the same field names that the
+/// reader expects, so `Writer` uses them to write the headers of a type
+/// without a record.
+pub fn decodable_field_names<D>(naming: &FieldNaming)
+                                -> Result<(Vec<ByteString>, Option<usize>)>
+    where D: Decodable
+{
+    type_field_names(field_names_decoder::field_names::<D>, naming)
+}
+
+/// Returns the field names of the deserializable type `D` and the index of
+/// its catch-all map, if it has one.
+///
+/// This is the equivalent of `decodable_field_names` for types that
+/// implement `serde::Deserialize`.
+#[cfg(feature = "serde")]
+pub fn deserializable_field_names<D>(naming: &FieldNaming)
+                                     -> Result<(Vec<ByteString>, Option<usize>)>
+    where D: DeserializeOwned
+{
+    type_field_names(field_names_deserializer::field_names::<D>, naming)
+}
+
+fn type_field_names(field_names: FieldNamesFn,
+                    naming: &FieldNaming)
+                    -> Result<(Vec<ByteString>, Option<usize>)> {
+    let fields = field_names(naming, &[])?;
+    // The number of columns of a `Vec` is only known from a record.
+    if !fields.var_seqs.is_empty() {
+        let msg = "The headers of a record type with a sequence of variable length can't be \
+                   determined without a record"
+            .to_string();
+        return Err(Error::Csv(csv::Error::Encode(msg)));
+    }
+    Ok((fields.names, fields.catch_all))
+}
+
+/// Function that returns the fields of a record type given the field naming
+/// options and the lengths of the sequences of variable length.
+type FieldNamesFn = fn(&FieldNaming, &[usize]) -> csv::Result<RecordFields>;
+
+/// The fields of a record type, as determined by a field names extractor.
+#[derive(Debug)]
+struct RecordFields {
+    /// Field names, in the order the scalars are decoded.
+    names: Vec<ByteString>,
+    /// Whether each field is an `Option`, which is allowed to not have a
+    /// matching header.
+    optional: Vec<bool>,
+    /// Lengths of the sequences in the record type, in the order they're
+    /// decoded, or `None` for a sequence that consumes the rest of the
+    /// record. For `rustc_serialize`, which doesn't provide the lengths of
+    /// fixed-size arrays to the decoder, this includes every sequence. For
+    /// `serde`, this only includes sequences of variable length.
+    seq_lens: Vec<Option<usize>>,
+    /// Ranges (in `names`) of the field names of the elements of each
+    /// sequence of variable length (such as a `Vec`), in the order they're
+    /// decoded. The lengths of these sequences are determined by the
+    /// headers.
+    var_seqs: Vec<(usize, usize)>,
+    /// Index in `names` where the record type has a map that collects the
+    /// columns that aren't matched to field names, if it has one.
+    catch_all: Option<usize>,
+}
+
+/// The position of a record in the CSV data.
+#[derive(Clone, Copy, Debug)]
+struct RecordPosition {
+    /// The number of the record (starting at 1), not counting the header row.
+    record: u64,
+    /// The line that the record starts on (starting at 1).
+    line: u64,
+    /// The byte offset of the start of the record.
+    byte: u64,
+}
+
+/// A raw record with the fields in the order of the field names.
+#[derive(Debug)]
+struct MappedRecord {
+    position: RecordPosition,
+    fields: Vec<ByteString>,
+    /// Headers and values of the columns that aren't matched to field names,
+    /// if the record type has a catch-all map.
+    catch_all: Vec<(ByteString, ByteString)>,
+    /// The fields in the order of the columns. This is only kept if the
+    /// error policy is `ErrorPolicy::Collect`.
+    raw: Vec<ByteString>,
+}
+
+/// An error reading a raw record.
+enum RecordError {
+    /// An error that finishes the iteration, such as an I/O error or headers
+    /// that don't match the field names.
+    Fatal(Error),
+    /// An error in a single row, which is handled according to the error
+    /// policy, with the fields of the row in the order of the columns.
+    Row(Error, Vec<ByteString>),
+}
+
+/// An iterator of raw records with the columns rearranged to match the fields
+/// of the record type.
+///
+/// This contains the logic shared by `DecodedRecords` and
+/// `DeserializedRecords`. The only thing that depends on the record type is
+/// the `field_names` function, which returns the fields of the record type
+/// given the field naming options.
+struct MappedRecords<'a, R: Read, P: ?Sized + 'a> {
+    rdr: Reader<'a, R, P>,
+    field_names: FieldNamesFn,
+    done_first: bool,
+    /// Finished reading records or encountered an error.
+    done: bool,
+    /// Indices are column indices and values are the (optional) field indices.
+    column_mapping: Vec<Option<usize>>,
+    headers: Vec<ByteString>,
+    fields: RecordFields,
+    /// The fields of a record before any columns are read, which is where
+    /// fields without a matching column get their default value.
+    default_fields: Vec<ByteString>,
+    /// Number of records read so far.
+    record_count: u64,
+    /// The line that the next record starts on.
+    line: u64,
+    /// Records that have been skipped because of `ErrorPolicy::Collect`.
+    rejected: Vec<RejectedRecord>,
+}
+
+/// Returns the number of line breaks in a field.
+fn count_lines(field: &[u8]) -> u64 {
+    field.iter().filter(|&&b| b == b'\n').count() as u64
+}
+
+/// Returns the number of lines of a row.
+fn count_row_lines(row: &[ByteString]) -> u64 {
+    1 + row.iter().map(|field| count_lines(field)).sum::<u64>()
+}
+
+/// Removes a UTF-8 byte order mark from the start of the first field of a row.
+pub fn strip_bom(row: &mut [ByteString]) {
+    if let Some(field) = row.first_mut() {
+        if field.starts_with(UTF8_BOM) {
+            field.drain(..UTF8_BOM.len());
+        }
+    }
+}
+
+impl<'a, R: Read, P: ?Sized + Fn(&[u8], &[u8]) -> bool> MappedRecords<'a, R, P> {
+    fn new(rdr: Reader<'a, R, P>,
+           field_names: FieldNamesFn)
+           -> MappedRecords<'a, R, P> {
+        MappedRecords {
+            rdr: rdr,
+            field_names: field_names,
+            done_first: false,
+            done: false,
+            column_mapping: Vec::new(),
+            headers: Vec::new(),
+            fields: RecordFields {
+                names: Vec::new(),
+                optional: Vec::new(),
+                seq_lens: Vec::new(),
+                var_seqs: Vec::new(),
+                catch_all: None,
+            },
+            default_fields: Vec::new(),
+            record_count: 0,
+            line: 1,
+            rejected: Vec::new(),
+        }
+    }
+
+    /// Processes the first row, setting `self.done_first, `self.headers`,
+    /// `self.fields`, and `self.column_mapping`.
+    ///
+    /// This method is idempotent and fast on subsequent calls (since it uses
+    /// `self.done_first` to track whether it's been called before).
+    fn process_first_row(&mut self) -> Result<()> {
+        if !self.done_first {
+            self.done_first = true;
+
+            // Always consume the header record.
+            let (headers, lines) = {
+                let header_row = self.rdr.read_header_row()?;
+                (header_row.headers.clone(), header_row.lines)
+            };
+
+            // If the header row is empty, then the CSV data contains no records.
+            if headers.is_empty() {
+                assert!(self.rdr.csv.done());
+                return Ok(());
+            }
+
+            // Get the fields of the record type.
+            let fields = self.rdr.record_fields(&headers, self.field_names)?;
+
+            // Set `column_mapping`, `headers`, and `fields`.
+            self.column_mapping = self.rdr.map_columns(&headers, &fields)?;
+            self.line += lines;
+            let headers = if self.rdr.has_headers {
+                headers
+            } else {
+                // The implied headers are the field names, and the columns
+                // after them are ignored.
+                let mut implied = fields.names.clone();
+                implied.resize(headers.len(), Vec::new());
+                implied
+            };
+            self.default_fields = fields.names
+                .iter()
+                .map(|name| self.rdr.field_defaults.get(name).cloned().unwrap_or_default())
+                .collect();
+            self.headers = headers;
+            self.fields = fields;
+        }
+        Ok(())
+    }
+
+    /// Processes the first row and returns the mapping of columns to fields.
+    ///
+    /// If the first row can't be processed, the iteration is finished.
+    fn column_mapping(&mut self) -> Result<ColumnMapping> {
+        if let Err(err) = self.process_first_row() {
+            self.done = true;
+            return Err(err);
+        }
+        let to_string = |name: &[u8]| String::from_utf8_lossy(name).into_owned();
+        let columns = self.column_mapping
+            .iter()
+            .zip(&self.headers)
+            .map(|(&field_index, header)| {
+                MappedColumn {
+                    header: to_string(header),
+                    target: match field_index {
+                        Some(index) => {
+                            ColumnTarget::Field {
+                                index: index,
+                                name: to_string(&self.fields.names[index]),
+                            }
+                        }
+                        None if self.fields.catch_all.is_some() => ColumnTarget::CatchAll,
+                        None => ColumnTarget::Ignored,
+                    },
+                }
+            })
+            .collect();
+        let unmatched_fields = self.fields
+            .names
+            .iter()
+            .enumerate()
+            .filter(|&(index, _)| !self.column_mapping.contains(&Some(index)))
+            .map(|(_, name)| to_string(name))
+            .collect();
+        Ok(ColumnMapping {
+            columns: columns,
+            unmatched_fields: unmatched_fields,
+        })
+    }
+
+    /// Reads the next record, with the fields in the order of the field names.
+    fn next_record(&mut self) -> Option<result::Result<MappedRecord, RecordError>> {
+        if let Err(err) = self.process_first_row() {
+            return Some(Err(RecordError::Fatal(err)));
+        }
+
+        let (byte, row) = loop {
+            if let Some(first_record) = self.rdr
+                .header_row
+                .as_mut()
+                .and_then(|header_row| header_row.first_record.take()) {
+                break first_record;
+            }
+            if self.rdr.csv.done() {
+                return None;
+            }
+            let byte = self.rdr.csv.byte_offset();
+            let mut row = Vec::new();
+            match self.rdr.read_row_into(&mut row) {
+                Ok(()) => (),
+                Err(err @ Error::Csv(csv::Error::Parse(_))) => {
+                    return Some(self.row_error(err, row));
+                }
+                Err(err) => return Some(Err(RecordError::Fatal(err))),
+            }
+            if self.rdr.is_comment(&row) {
+                self.line += count_row_lines(&row);
+            } else {
+                break (byte, row);
+            }
+        };
+
+        // The CSV reader doesn't check the number of columns if it's only
+        // flexible so that rows can be skipped.
+        if self.rdr.skips_rows() && !self.rdr.flexible && !row.is_empty() &&
+           row.len() != self.headers.len() {
+            let msg = format!("Record has {} columns, but the header row has {}",
+                              row.len(),
+                              self.headers.len());
+            return Some(self.row_error(Error::Csv(csv::Error::Decode(msg)), row));
+        }
+
+        let mut record = MappedRecord {
+            position: RecordPosition {
+                record: self.record_count + 1,
+                line: self.line,
+                byte: byte,
+            },
+            fields: self.default_fields.clone(),
+            catch_all: Vec::new(),
+            raw: Vec::new(),
+        };
+        if row.is_empty() && record.fields.is_empty() {
+            return None;
+        }
+        if row.len() > self.column_mapping.len() {
+            let err = csv::Error::Decode("More data columns than headers".into());
+            return Some(self.row_error(Error::from(err), row));
+        }
+        self.line += count_row_lines(&row);
+        for (column, field) in row.iter().enumerate() {
+            match self.column_mapping[column] {
+                Some(field_index) => record.fields[field_index] = field.clone(),
+                None if self.fields.catch_all.is_some() => {
+                    record.catch_all.push((self.headers[column].clone(), field.clone()));
+                }
+                None => (),
+            }
+        }
+        if self.rdr.error_policy == ErrorPolicy::Collect {
+            record.raw = row;
+        }
+        self.record_count += 1;
+        Some(Ok(record))
+    }
+
+    /// Returns the error for a row that can't be read as a record, and counts
+    /// the row as a record so that the positions of the following records
+    /// are correct.
+    fn row_error(&mut self,
+                 err: Error,
+                 row: Vec<ByteString>)
+                 -> result::Result<MappedRecord, RecordError> {
+        self.line += count_row_lines(&row);
+        self.record_count += 1;
+        Err(RecordError::Row(err, row))
+    }
+
+    /// Returns a decoding error with the position of the record and the
+    /// column that caused the error, as reported by `RecordDecoder` or
+    /// `RecordDeserializer`.
+    fn decode_error(&self,
+                    position: RecordPosition,
+                    message: String,
+                    field: Option<ErrorField>)
+                    -> Error {
+        let mut err = DecodeError {
+            record: position.record,
+            line: position.line,
+            byte: position.byte,
+            column: None,
+            header: None,
+            field_name: None,
+            value: None,
+            message: message,
+        };
+        match field {
+            Some(ErrorField::Field(field_index, value)) => {
+                err.column = self.column_mapping.iter().position(|&f| f == Some(field_index));
+                err.field_name = self.fields
+                    .names
+                    .get(field_index)
+                    .map(|name| String::from_utf8_lossy(name).into_owned());
+                err.value = Some(value.to_vec());
+            }
+            Some(ErrorField::CatchAll(entry, value)) => {
+                err.column = self.column_mapping
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, f)| f.is_none())
+                    .map(|(column, _)| column)
+                    .nth(entry);
+                err.value = Some(value.to_vec());
+            }
+            None => (),
+        }
+        err.header = err.column
+            .map(|column| String::from_utf8_lossy(&self.headers[column]).into_owned());
+        Error::Decode(err)
+    }
+
+    /// Reads the next record and converts it with `convert`, which is also
+    /// given `self` for the fields of the record type and the headers.
+    ///
+    /// Records that can't be converted are handled according to the error
+    /// policy. This ensures that `self.done` is always set properly.
+    fn next_with<T, F>(&mut self, mut convert: F) -> Option<Result<T>>
+        where F: FnMut(MappedRecord, &Self) -> Result<T>
+    {
+        while !self.done {
+            let (err, raw) = match self.next_record() {
+                Some(Ok(mut record)) => {
+                    let raw = mem::replace(&mut record.raw, Vec::new());
+                    match convert(record, &*self) {
+                        Ok(value) => return Some(Ok(value)),
+                        Err(err) => (err, raw),
+                    }
+                }
+                Some(Err(RecordError::Row(err, row))) => (err, row),
+                Some(Err(RecordError::Fatal(err))) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+                None => {
+                    self.done = true;
+                    return None;
+                }
+            };
+            match self.rdr.error_policy {
+                ErrorPolicy::FailFast => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+                ErrorPolicy::SkipAndLog => warn!("Skipping record: {}", err),
+                ErrorPolicy::Collect => {
+                    self.rejected.push(RejectedRecord {
+                        fields: raw,
+                        error: err,
+                    })
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ColumnMapping, ColumnTarget, DecodeError, DuplicateHeader, Error, ErrorPolicy,
+                HeaderMismatch, MappedColumn, MissingField, Normalization, OutOfOrder, Reader,
+                Result, UnexpectedHeader};
+    #[cfg(any(feature = "gzip", feature = "zstd", feature = "bzip2"))]
+    use compression::{CompressingWriter, Compression};
+    use csv;
+    use encoding::Encoding;
+    use rustc_serialize::{Decodable, Decoder};
+    use std::ascii::AsciiExt;
+    use std::cell::Cell;
+    use std::collections::{BTreeMap, HashMap};
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{Cursor, Write};
+    use std::result;
+
+    #[derive(Debug, PartialEq, RustcDecodable)]
+    #[cfg_attr(feature = "serde", derive(Deserialize))]
+    struct SimpleStruct {
+        a: usize,
+        b: usize,
+    }
+
+    fn header_mismatch(err: Error) -> HeaderMismatch {
+        match err {
+            Error::HeaderMismatch(mismatch) => mismatch,
+            err => panic!("Expected a header mismatch, but got {:?}", err),
+        }
+    }
+
+    fn decode_error(err: Error) -> DecodeError {
+        match err {
+            Error::Decode(err) => err,
+            err => panic!("Expected a decode error, but got {:?}", err),
+        }
+    }
+
+    fn missing(field_index: usize, field_name: &str) -> MissingField {
+        MissingField {
+            field_index: field_index,
+            field_name: field_name.to_string(),
+            aliases: Vec::new(),
+        }
+    }
+
+    fn unexpected(header_index: usize, header: &str) -> UnexpectedHeader {
+        UnexpectedHeader {
+            header_index: header_index,
+            header: header.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_struct() {
+        let rdr = Reader::from_string("a,b\n0,1\n2,3\n");
+        let records = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap();
+        assert_eq!(records,
+                   vec![SimpleStruct { a: 0, b: 1 }, SimpleStruct { a: 2, b: 3 }]);
+    }
+
+    #[test]
+    fn test_from_csv_reader() {
+        let csv_rdr = csv::Reader::from_string("a,b\n0,1\n").flexible(true);
+        let rdr = Reader::from_csv_reader(csv_rdr);
+        let records = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap();
+        assert_eq!(records, vec![SimpleStruct { a: 0, b: 1 }]);
+    }
+
+    #[test]
+    fn test_decoded_records_into_inner() {
+        let rdr = Reader::from_string("a,b\n0,1\n2,3\n");
+        assert!(!rdr.as_csv_reader().done());
+        let mut records = rdr.decode::<SimpleStruct>();
+        assert_eq!(records.next().unwrap().unwrap(), SimpleStruct { a: 0, b: 1 });
+        assert_eq!(records.as_csv_reader().byte_offset(), 8);
+        let mut csv_rdr = records.into_inner();
+        let rest = csv_rdr.byte_records().collect::<csv::Result<Vec<_>>>().unwrap();
+        assert_eq!(rest, vec![vec![b"2".to_vec(), b"3".to_vec()]]);
+    }
+
+    #[test]
+    fn test_column_mapping() {
+        let rdr = Reader::from_string("b,c,a\n1,2,0\n");
+        let mut records = rdr.reorder_columns(true)
+            .ignore_unused_columns(true)
+            .decode::<StructWithOption>();
+        let mapping = records.column_mapping().unwrap();
+        assert_eq!(mapping,
+                   ColumnMapping {
+                       columns: vec![MappedColumn {
+                                         header: "b".to_string(),
+                                         target: ColumnTarget::Field {
+                                             index: 1,
+                                             name: "b".to_string(),
+                                         },
+                                     },
+                                     MappedColumn {
+                                         header: "c".to_string(),
+                                         target: ColumnTarget::Ignored,
+                                     },
+                                     MappedColumn {
+                                         header: "a".to_string(),
+                                         target: ColumnTarget::Field {
+                                             index: 0,
+                                             name: "a".to_string(),
+                                         },
+                                     }],
+                       unmatched_fields: vec![],
+                   });
+        assert_eq!(mapping.ignored_columns(), vec![1]);
+        let records = records.collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(records, vec![StructWithOption { a: 0, b: Some(1) }]);
+    }
+
+    #[test]
+    fn test_column_mapping_catch_all_and_unmatched() {
+        #[derive(Debug, RustcDecodable)]
+        struct Record {
+            a: usize,
+            b: Option<usize>,
+            other: BTreeMap<String, String>,
+        }
+        let rdr = Reader::from_string("a,x\n0,1\n");
+        let mut records = rdr.decode::<Record>();
+        let mapping = records.column_mapping().unwrap();
+        assert_eq!(mapping.to_string(),
+                   "0: 'a' -> field 'a'\n1: 'x' -> catch-all map\nno column -> field 'b'"
+                       .to_string());
+    }
+
+    #[test]
+    fn test_column_mapping_mismatch() {
+        let rdr = Reader::from_string("a,c\n0,1\n");
+        let mut records = rdr.decode::<SimpleStruct>();
+        header_mismatch(records.column_mapping().unwrap_err());
+        assert!(records.next().is_none());
+    }
+
+    #[test]
+    fn test_check_headers() {
+        let mut rdr = Reader::from_string("b,a\n0,1\n").reorder_columns(true);
+        rdr.check_headers::<SimpleStruct>().unwrap();
+        let records = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap();
+        assert_eq!(records, vec![SimpleStruct { a: 1, b: 0 }]);
+    }
+
+    #[test]
+    fn test_check_headers_mismatch() {
+        let mut rdr = Reader::from_string("a,c\n0,1\n");
+        let mismatch = header_mismatch(rdr.check_headers::<SimpleStruct>().unwrap_err());
+        assert_eq!(mismatch.missing_fields, vec![missing(1, "b")]);
+        assert_eq!(mismatch.unexpected_headers, vec![unexpected(1, "c")]);
+        rdr.check_headers::<StructWithOption>().unwrap_err();
+        rdr = rdr.ignore_unused_columns(true);
+        rdr.check_headers::<StructWithOption>().unwrap();
+    }
+
+    #[test]
+    fn test_struct_no_headers() {
+        let rdr = Reader::from_string("0,1\n2,3\n").has_headers(false);
+        let records = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap();
+        assert_eq!(records,
+                   vec![SimpleStruct { a: 0, b: 1 }, SimpleStruct { a: 2, b: 3 }]);
+    }
+
+    #[test]
+    fn test_struct_no_headers_column_count() {
+        let rdr = Reader::from_string("0,1,2\n3,4,5\n").has_headers(false);
+        assert!(rdr.decode::<SimpleStruct>().next().unwrap().is_err());
+        let rdr = Reader::from_string("0\n").has_headers(false);
+        assert!(rdr.decode::<SimpleStruct>().next().unwrap().is_err());
+        let rdr = Reader::from_string("0,1,2\n3,4,5\n")
+            .has_headers(false)
+            .ignore_unused_columns(true);
+        let records = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap();
+        assert_eq!(records,
+                   vec![SimpleStruct { a: 0, b: 1 }, SimpleStruct { a: 3, b: 4 }]);
+    }
+
+    #[test]
+    fn test_struct_no_headers_decode_error() {
+        let rdr = Reader::from_string("0,1\n2,x\n").has_headers(false);
+        let err = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap_err();
+        match err {
+            Error::Decode(err) => {
+                assert_eq!((err.record, err.line), (2, 2));
+                assert_eq!(err.header, Some("b".to_string()));
+            }
+            err => panic!("Expected a decode error, but got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_skip_rows() {
+        let mut rdr = Reader::from_string("x\ny,z\na,b\n0,1\n2,3\n").skip_rows(2);
+        assert_eq!(rdr.preamble().unwrap(),
+                   &[vec![b"x".to_vec()], vec![b"y".to_vec(), b"z".to_vec()]]);
+        let records = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap();
+        assert_eq!(records,
+                   vec![SimpleStruct { a: 0, b: 1 }, SimpleStruct { a: 2, b: 3 }]);
+    }
+
+    #[test]
+    fn test_skip_rows_until() {
+        let rdr = Reader::from_string("x\ny,z\na,b\n0,1\n")
+            .skip_rows_until(|row| row.first().map_or(false, |field| field == b"a"));
+        let mut records = rdr.decode::<SimpleStruct>();
+        assert_eq!(records.next().unwrap().unwrap(), SimpleStruct { a: 0, b: 1 });
+        assert_eq!(records.preamble().unwrap().len(), 2);
+        assert!(records.next().is_none());
+    }
+
+    #[test]
+    fn test_comment_prefix() {
+        let rdr = Reader::from_string("# x\na,b\n0,1\n#y,z\n2,x\n").comment_prefix("#");
+        let mut records = rdr.decode::<SimpleStruct>();
+        assert_eq!(records.next().unwrap().unwrap(), SimpleStruct { a: 0, b: 1 });
+        match records.next().unwrap().unwrap_err() {
+            Error::Decode(err) => assert_eq!((err.record, err.line), (2, 5)),
+            err => panic!("Expected a decode error, but got {:?}", err),
+        }
+        assert_eq!(records.preamble().unwrap(), &[vec![b"# x".to_vec()]]);
+    }
+
+    #[test]
+    fn test_skip_rows_column_count() {
+        let rdr = Reader::from_string("x\na,b\n0,1\n2\n").skip_rows(1);
+        let mut records = rdr.decode::<StructWithOption>();
+        records.next().unwrap().unwrap();
+        assert!(records.next().unwrap().is_err());
+        let rdr = Reader::from_string("x\na,b\n0,1\n2\n").skip_rows(1).flexible(true);
+        let records = rdr.decode().collect::<Result<Vec<StructWithOption>>>().unwrap();
+        assert_eq!(records,
+                   vec![StructWithOption { a: 0, b: Some(1) }, StructWithOption { a: 2, b: None }]);
+    }
+
+    #[test]
+    fn test_skip_rows_no_headers() {
+        let mut rdr = Reader::from_string("x\n0,1\n2,3\n").skip_rows(1).has_headers(false);
+        rdr.check_headers::<SimpleStruct>().unwrap();
+        let records = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap();
+        assert_eq!(records,
+                   vec![SimpleStruct { a: 0, b: 1 }, SimpleStruct { a: 2, b: 3 }]);
+    }
+
+    #[test]
+    fn test_header_rows() {
+        let mut rdr = Reader::from_string("a,b\nm,s\n-,-\n0,1\n").header_rows(3);
+        assert_eq!(rdr.extra_header_rows().unwrap(),
+                   &[vec![b"m".to_vec(), b"s".to_vec()], vec![b"-".to_vec(), b"-".to_vec()]]);
+        let records = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap();
+        assert_eq!(records, vec![SimpleStruct { a: 0, b: 1 }]);
+    }
+
+    #[test]
+    fn test_zero_header_rows() {
+        let rdr = Reader::from_string("0,1\n2,3\n").header_rows(0);
+        let records = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap();
+        assert_eq!(records, vec![SimpleStruct { a: 0, b: 1 }, SimpleStruct { a: 2, b: 3 }]);
+    }
+
+    #[test]
+    fn test_header_rows_with_preamble() {
+        let rdr = Reader::from_string("x\na,b\nm,s\n0,y\n").skip_rows(1).header_rows(2);
+        let mut records = rdr.decode::<SimpleStruct>();
+        match records.next().unwrap().unwrap_err() {
+            Error::Decode(err) => assert_eq!((err.record, err.line), (1, 4)),
+            err => panic!("Expected a decode error, but got {:?}", err),
+        }
+        assert_eq!(records.extra_header_rows().unwrap(),
+                   &[vec![b"m".to_vec(), b"s".to_vec()]]);
+    }
+
+    #[test]
+    fn test_utf8_bom() {
+        let rdr = Reader::from_string("\u{feff}a,b\n0,1\n");
+        let records = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap();
+        assert_eq!(records, vec![SimpleStruct { a: 0, b: 1 }]);
+        let rdr = Reader::from_string("\u{feff}0,1\n").has_headers(false);
+        let records = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap();
+        assert_eq!(records, vec![SimpleStruct { a: 0, b: 1 }]);
+        let mut rdr = Reader::from_string("\u{feff}x\na,b\n0,1\n").skip_rows(1);
+        assert_eq!(rdr.preamble().unwrap(), &[vec![b"x".to_vec()]]);
+        let records = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap();
+        assert_eq!(records, vec![SimpleStruct { a: 0, b: 1 }]);
+    }
+
+    #[test]
+    fn test_utf16() {
+        let mut data = vec![0xff, 0xfe];
+        for unit in "a,b\n0,1\n".encode_utf16() {
+            data.extend_from_slice(&[unit as u8, (unit >> 8) as u8]);
+        }
+        let rdr = Reader::from_reader_with_encoding(Cursor::new(data), Encoding::Utf8);
+        let records = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap();
+        assert_eq!(records, vec![SimpleStruct { a: 0, b: 1 }]);
+        let mut data = Vec::new();
+        for unit in "a,b\n0,1\n".encode_utf16() {
+            data.extend_from_slice(&[(unit >> 8) as u8, unit as u8]);
+        }
+        let rdr = Reader::from_reader_with_encoding(Cursor::new(data), Encoding::Utf16Be);
+        let records = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap();
+        assert_eq!(records, vec![SimpleStruct { a: 0, b: 1 }]);
+    }
+
+    #[test]
+    fn test_windows_1252() {
+        let data = b"a\n\x80 \xe9\n".to_vec();
+        let rdr = Reader::from_reader_with_encoding(Cursor::new(data), Encoding::Windows1252);
+        let records = rdr.decode().collect::<Result<Vec<BTreeMap<String, String>>>>().unwrap();
+        assert_eq!(records[0]["a"], "€ é");
+        let data = b"a\n\x80 \xe9\n".to_vec();
+        let rdr = Reader::from_reader_with_encoding(Cursor::new(data), Encoding::Latin1);
+        let records = rdr.decode().collect::<Result<Vec<BTreeMap<String, String>>>>().unwrap();
+        assert_eq!(records[0]["a"], "\u{80} é");
+    }
+
+    #[cfg(any(feature = "gzip", feature = "zstd", feature = "bzip2"))]
+    fn compress(data: &str, compression: Compression) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        {
+            let mut wtr = CompressingWriter::new(&mut bytes, compression).unwrap();
+            wtr.write_all(data.as_bytes()).unwrap();
+        }
+        bytes
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip() {
+        let data = compress("a,b\n0,1\n", Compression::Gzip);
+        let rdr = Reader::from_reader_with_compression(Cursor::new(data), Compression::Gzip)
+            .unwrap();
+        let records = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap();
+        assert_eq!(records, vec![SimpleStruct { a: 0, b: 1 }]);
+    }
+
+    #[test]
+    fn test_from_file_uncompressed() {
+        let path = env::temp_dir().join("typed_csv_from_file_uncompressed.csv");
+        File::create(&path).unwrap().write_all(b"a,b\n0,1\n").unwrap();
+        let records = Reader::from_file(&path)
+            .unwrap()
+            .decode()
+            .collect::<Result<Vec<SimpleStruct>>>()
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(records, vec![SimpleStruct { a: 0, b: 1 }]);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_from_file_detects_compression() {
+        let path = env::temp_dir().join("typed_csv_detect_compression.csv");
+        File::create(&path)
+            .unwrap()
+            .write_all(&compress("a,b\n0,1\n", Compression::Zstd))
+            .unwrap();
+        let records = Reader::from_file(&path)
+            .unwrap()
+            .decode()
+            .collect::<Result<Vec<SimpleStruct>>>()
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(records, vec![SimpleStruct { a: 0, b: 1 }]);
+    }
+
+    #[test]
+    fn test_struct_allow_reorder() {
+        let rdr = Reader::from_string("b,a\n0,1\n2,3\n");
+        let records =
+            rdr.reorder_columns(true).decode().collect::<Result<Vec<SimpleStruct>>>().unwrap();
+        assert_eq!(records,
+                   vec![SimpleStruct { a: 1, b: 0 }, SimpleStruct { a: 3, b: 2 }]);
+    }
+
+    #[test]
+    fn test_struct_ignore_unused_columns() {
+        let rdr = Reader::from_string("a,b,c\n0,1,2\n3,4,5\n");
+        let records = rdr.ignore_unused_columns(true)
+            .decode()
+            .collect::<Result<Vec<SimpleStruct>>>()
+            .unwrap();
+        assert_eq!(records,
+                   vec![SimpleStruct { a: 0, b: 1 }, SimpleStruct { a: 3, b: 4 }]);
+    }
+
+    #[test]
+    fn test_struct_allow_reorder_and_ignore_unused_columns() {
+        let rdr = Reader::from_string("b,c,a\n0,1,2\n3,4,5\n");
+        let records = rdr.reorder_columns(true)
+            .ignore_unused_columns(true)
+            .decode()
+            .collect::<Result<Vec<SimpleStruct>>>()
+            .unwrap();
+        assert_eq!(records,
+                   vec![SimpleStruct { a: 2, b: 0 }, SimpleStruct { a: 5, b: 3 }]);
+    }
+
+    #[test]
+    fn test_struct_headers_match_by_owned() {
+        let suffix = "_field".to_string();
+        let rdr = Reader::from_string("a_field,b_field\n0,1\n")
+            .headers_match_by_owned(move |header: &[u8], field: &[u8]| {
+                header == [field, suffix.as_bytes()].concat().as_slice()
+            });
+        let records = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap();
+        assert_eq!(records, vec![SimpleStruct { a: 0, b: 1 }]);
+    }
+
+    #[test]
+    fn test_struct_headers_match_by_non_sync() {
+        let calls = Cell::new(0);
+        let pred = |header: &[u8], field: &[u8]| {
+            calls.set(calls.get() + 1);
+            header == field
+        };
+        let rdr = Reader::from_string("a,b\n0,1\n").headers_match_by(&pred);
+        let records = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap();
+        assert_eq!(records, vec![SimpleStruct { a: 0, b: 1 }]);
+        assert!(calls.get() > 0);
+    }
+
+    #[test]
+    fn test_reader_is_send() {
+        fn assert_send_static<T: Send + 'static>(_: &T) {}
+        let rdr = Reader::from_string("a,b\n0,1\n").headers_match_by_owned(<[u8]>::eq);
+        assert_send_static(&rdr);
+        let records = rdr.decode::<SimpleStruct>();
+        assert_send_static(&records);
+    }
+
+    #[test]
+    fn test_struct_headers_match_by() {
+        let rdr = Reader::from_string("a,B\n0,1\n2,3\n");
+        let records = rdr.headers_match_by(&<[u8]>::eq_ignore_ascii_case)
+            .decode()
+            .collect::<Result<Vec<SimpleStruct>>>()
+            .unwrap();
+        assert_eq!(records,
+                   vec![SimpleStruct { a: 0, b: 1 }, SimpleStruct { a: 2, b: 3 }]);
+    }
+
+    #[test]
+    fn test_struct_reordered_headers() {
+        let rdr = Reader::from_string("b,a\n0,1\n2,3\n");
+        let err = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap_err();
+        assert_eq!(format!("{}", err),
+                   "Headers don't match field names\n  expected: a,b\n  actual:   b,a\n  ~ \
+                    out-of-order header 'b' (header 0, field 1)"
+                       .to_string());
+        let mismatch = header_mismatch(err);
+        assert_eq!(mismatch.out_of_order,
+                   vec![OutOfOrder {
+                            header_index: 0,
+                            field_index: 1,
+                            header: "b".to_string(),
+                        }]);
+        assert!(mismatch.missing_fields.is_empty());
+        assert!(mismatch.unexpected_headers.is_empty());
+    }
+
+    #[test]
+    fn test_struct_wrong_case() {
+        let rdr = Reader::from_string("a,B\n0,1\n2,3\n");
+        let err = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap_err();
+        let mismatch = header_mismatch(err);
+        assert_eq!(mismatch.missing_fields, vec![missing(1, "b")]);
+        assert_eq!(mismatch.unexpected_headers, vec![unexpected(1, "B")]);
+    }
+
+    #[test]
+    fn test_struct_misnamed_headers() {
+        let rdr = Reader::from_string("c,d\n0,1\n");
+        let err = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap_err();
+        assert_eq!(format!("{}", err),
+                   "Headers don't match field names\n  expected: a,b\n  actual:   c,d\n  - \
+                    missing field 'a' (field 0)\n  - missing field 'b' (field 1)\n  + \
+                    unexpected header 'c' (header 0)\n  + unexpected header 'd' (header 1)"
+                       .to_string());
+    }
+
+    #[test]
+    fn test_struct_missing_header() {
+        let rdr = Reader::from_string("a\n0\n");
+        let err = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap_err();
+        let mismatch = header_mismatch(err);
+        assert_eq!(mismatch.missing_fields, vec![missing(1, "b")]);
+        assert!(mismatch.unexpected_headers.is_empty());
+    }
+
+    #[derive(Debug, PartialEq, RustcDecodable)]
+    #[cfg_attr(feature = "serde", derive(Deserialize))]
+    struct StructWithOption {
+        a: usize,
+        b: Option<usize>,
+    }
+
+    #[test]
+    fn test_struct_with_option_missing_header() {
+        let rdr = Reader::from_string("a\n0\n2\n");
+        let records = rdr.decode().collect::<Result<Vec<StructWithOption>>>().unwrap();
+        assert_eq!(records,
+                   vec![StructWithOption { a: 0, b: None }, StructWithOption { a: 2, b: None }]);
+    }
+
+    #[test]
+    fn test_struct_with_option_present_header() {
+        let rdr = Reader::from_string("b,a\n1,0\n,2\n");
+        let records = rdr.reorder_columns(true)
+            .decode()
+            .collect::<Result<Vec<StructWithOption>>>()
+            .unwrap();
+        assert_eq!(records,
+                   vec![StructWithOption { a: 0, b: Some(1) },
+                        StructWithOption { a: 2, b: None }]);
+    }
+
+    #[test]
+    fn test_struct_field_default() {
+        let rdr = Reader::from_string("a\n0\n2\n");
+        let records = rdr.field_default("b", "7")
+            .decode()
+            .collect::<Result<Vec<SimpleStruct>>>()
+            .unwrap();
+        assert_eq!(records,
+                   vec![SimpleStruct { a: 0, b: 7 }, SimpleStruct { a: 2, b: 7 }]);
+    }
+
+    #[test]
+    fn test_struct_field_default_with_header() {
+        let rdr = Reader::from_string("a,b\n0,1\n");
+        let records = rdr.field_default("b", "7")
+            .decode()
+            .collect::<Result<Vec<SimpleStruct>>>()
+            .unwrap();
+        assert_eq!(records, vec![SimpleStruct { a: 0, b: 1 }]);
+    }
+
+    #[test]
+    fn test_struct_optional_field() {
+        #[derive(Debug, PartialEq, RustcDecodable)]
+        struct Record {
+            a: usize,
+            b: String,
+        }
+        let rdr = Reader::from_string("a\n0\n");
+        let records = rdr.optional_field("b").decode().collect::<Result<Vec<Record>>>().unwrap();
+        assert_eq!(records,
+                   vec![Record {
+                            a: 0,
+                            b: String::new(),
+                        }]);
+    }
+
+    #[test]
+    fn test_struct_field_aliases() {
+        let rdr = Reader::from_string("a,n\n0,1\n");
+        let records = rdr.field_aliases("b", &["cnt", "n"])
+            .decode()
+            .collect::<Result<Vec<SimpleStruct>>>()
+            .unwrap();
+        assert_eq!(records, vec![SimpleStruct { a: 0, b: 1 }]);
+    }
+
+    #[test]
+    fn test_struct_field_aliases_allow_reorder() {
+        let rdr = Reader::from_string("cnt,A\n1,0\n");
+        let records = rdr.field_aliases("a", vec!["A".to_string()])
+            .field_aliases("b", &["cnt"])
+            .reorder_columns(true)
+            .decode()
+            .collect::<Result<Vec<SimpleStruct>>>()
+            .unwrap();
+        assert_eq!(records, vec![SimpleStruct { a: 0, b: 1 }]);
+    }
+
+    #[test]
+    fn test_struct_field_aliases_missing() {
+        let rdr = Reader::from_string("a,c\n0,1\n");
+        let err = rdr.field_aliases("b", &["cnt", "n"])
+            .decode()
+            .collect::<Result<Vec<SimpleStruct>>>()
+            .unwrap_err();
+        assert_eq!(format!("{}", err),
+                   "Headers don't match field names\n  expected: a,b\n  actual:   a,c\n  - \
+                    missing field 'b' (field 1, aliases 'cnt', 'n')\n  + unexpected header 'c' \
+                    (header 1)"
+                       .to_string());
+    }
+
+    #[test]
+    fn test_struct_rename_field() {
+        let rdr = Reader::from_string("Field B,a\n1,0\n");
+        let records = rdr.rename_field("b", "Field B")
+            .reorder_columns(true)
+            .decode()
+            .collect::<Result<Vec<SimpleStruct>>>()
+            .unwrap();
+        assert_eq!(records, vec![SimpleStruct { a: 0, b: 1 }]);
+    }
+
+    #[test]
+    fn test_struct_rename_fields_with() {
+        let rdr = Reader::from_string("A,B\n0,1\n");
+        let records = rdr.rename_fields_with(|name| name.to_uppercase())
+            .decode()
+            .collect::<Result<Vec<SimpleStruct>>>()
+            .unwrap();
+        assert_eq!(records, vec![SimpleStruct { a: 0, b: 1 }]);
+    }
+
+    #[test]
+    fn test_struct_rename_field_missing() {
+        let rdr = Reader::from_string("a,b\n0,1\n");
+        let err = rdr.rename_field("b", "Field B")
+            .decode()
+            .collect::<Result<Vec<SimpleStruct>>>()
+            .unwrap_err();
+        let mismatch = header_mismatch(err);
+        assert_eq!(mismatch.missing_fields, vec![missing(1, "Field B")]);
+        assert_eq!(mismatch.unexpected_headers, vec![unexpected(1, "b")]);
+    }
+
+    #[derive(Debug, PartialEq, RustcDecodable)]
+    struct StructWithWords {
+        animal_count: usize,
+        animal_name: String,
+    }
+
+    fn struct_with_words() -> StructWithWords {
+        StructWithWords {
+            animal_count: 7,
+            animal_name: "penguin".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_normalize_ascii_case_insensitive() {
+        let rdr = Reader::from_string("A,b\n0,1\n");
+        let records = rdr.normalize_headers(Normalization::ASCII_CASE_INSENSITIVE)
+            .decode()
+            .collect::<Result<Vec<SimpleStruct>>>()
+            .unwrap();
+        assert_eq!(records, vec![SimpleStruct { a: 0, b: 1 }]);
+    }
+
+    #[test]
+    fn test_normalize_trim_and_separators() {
+        let rdr = Reader::from_string(" animal-count ,animal name\n7,penguin\n");
+        let records = rdr.normalize_headers(Normalization::TRIM | Normalization::SEPARATORS)
+            .decode()
+            .collect::<Result<Vec<StructWithWords>>>()
+            .unwrap();
+        assert_eq!(records, vec![struct_with_words()]);
+    }
+
+    #[test]
+    fn test_normalize_word_case_allow_reorder() {
+        for headers in &["AnimalName,animalCount", "Animal Name,ANIMAL_COUNT"] {
+            let rdr = Reader::from_string(format!("{}\npenguin,7\n", headers));
+            let records = rdr.normalize_headers(Normalization::WORD_CASE)
+                .reorder_columns(true)
+                .decode()
+                .collect::<Result<Vec<StructWithWords>>>()
+                .unwrap();
+            assert_eq!(records, vec![struct_with_words()]);
+        }
+    }
+
+    #[test]
+    fn test_normalize_unicode_case_fold() {
+        let rdr = Reader::from_string("ÉTÉ,b\n0,1\n");
+        let records = rdr.rename_field("a", "été")
+            .normalize_headers(Normalization::UNICODE_CASE_FOLD)
+            .decode()
+            .collect::<Result<Vec<SimpleStruct>>>()
+            .unwrap();
+        assert_eq!(records, vec![SimpleStruct { a: 0, b: 1 }]);
+    }
+
+    #[test]
+    fn test_normalize_mismatch_has_original_names() {
+        let rdr = Reader::from_string(" A ,c\n0,1\n");
+        let err = rdr.normalize_headers(Normalization::TRIM | Normalization::ASCII_CASE_INSENSITIVE)
+            .decode()
+            .collect::<Result<Vec<SimpleStruct>>>()
+            .unwrap_err();
+        let mismatch = header_mismatch(err);
+        assert_eq!(mismatch.missing_fields, vec![missing(1, "b")]);
+        assert_eq!(mismatch.unexpected_headers, vec![unexpected(1, "c")]);
+    }
+
+    #[test]
+    fn test_struct_extra_header() {
+        let rdr = Reader::from_string("a,b,c\n0,1\n");
+        let err = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap_err();
+        let mismatch = header_mismatch(err);
+        assert!(mismatch.missing_fields.is_empty());
+        assert_eq!(mismatch.unexpected_headers, vec![unexpected(2, "c")]);
+    }
+
+    #[test]
+    fn test_struct_duplicate_header() {
+        let rdr = Reader::from_string("a,b,a\n0,1,2\n");
+        let err = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap_err();
+        let mismatch = header_mismatch(err);
+        assert_eq!(mismatch.duplicate_headers,
+                   vec![DuplicateHeader {
+                            header_index: 2,
+                            first_index: 0,
+                            header: "a".to_string(),
+                        }]);
+        assert!(mismatch.unexpected_headers.is_empty());
+    }
+
+    #[test]
+    fn test_struct_extra_data_column() {
+        let rdr = Reader::from_string("a,b\n0,1,2\n");
+        let err = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap_err();
+        assert_eq!(format!("{}", err),
+                   "CSV decode error: More data columns than headers".to_string());
+    }
+
+    #[test]
+    fn test_struct_decode_error_position() {
+        let rdr = Reader::from_string("a,b\n0,1\n2,x\n");
+        let err = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap_err();
+        assert_eq!(format!("{}", err),
+                   "CSV decode error in record 2 (line 3, byte 8), column 1, header 'b', field \
+                    'b', value \"x\": Failed converting 'x' from str."
+                       .to_string());
+        assert_eq!(decode_error(err),
+                   DecodeError {
+                       record: 2,
+                       line: 3,
+                       byte: 8,
+                       column: Some(1),
+                       header: Some("b".to_string()),
+                       field_name: Some("b".to_string()),
+                       value: Some(b"x".to_vec()),
+                       message: "Failed converting 'x' from str.".to_string(),
+                   });
+    }
+
+    #[test]
+    fn test_decode_error_position_after_multiline_field() {
+        #[derive(Debug, RustcDecodable)]
+        struct Record {
+            a: usize,
+            b: String,
+        }
+        let rdr = Reader::from_string("b,a\n\"x\ny\",0\nz,q\n");
+        let err = rdr.reorder_columns(true)
+            .decode()
+            .collect::<Result<Vec<Record>>>()
+            .unwrap_err();
+        let err = decode_error(err);
+        assert_eq!((err.record, err.line, err.byte), (2, 4, 12));
+        assert_eq!(err.column, Some(1));
+        assert_eq!(err.header, Some("a".to_string()));
+        assert_eq!(err.value, Some(b"q".to_vec()));
+    }
+
+    #[test]
+    fn test_error_policy_skip_and_log() {
+        let rdr = Reader::from_string("a,b\n0,1\nx,3\n4,5\n");
+        let mut records = rdr.error_policy(ErrorPolicy::SkipAndLog).decode();
+        let rows = records.by_ref().collect::<Result<Vec<SimpleStruct>>>().unwrap();
+        assert_eq!(rows, vec![SimpleStruct { a: 0, b: 1 }, SimpleStruct { a: 4, b: 5 }]);
+        assert!(records.rejected().is_empty());
+    }
+
+    #[test]
+    fn test_error_policy_collect() {
+        let rdr = Reader::from_string("b,a\n1,0\n3,x\n5,4\n,6\n");
+        let mut records = rdr.reorder_columns(true).error_policy(ErrorPolicy::Collect).decode();
+        let rows = records.by_ref().collect::<Result<Vec<SimpleStruct>>>().unwrap();
+        assert_eq!(rows, vec![SimpleStruct { a: 0, b: 1 }, SimpleStruct { a: 4, b: 5 }]);
+        let rejected = records.into_rejected();
+        assert_eq!(rejected.len(), 2);
+        assert_eq!(rejected[0].fields, vec![b"3".to_vec(), b"x".to_vec()]);
+        assert_eq!(rejected[1].fields, vec![b"".to_vec(), b"6".to_vec()]);
+        let errors = rejected.into_iter().map(|r| decode_error(r.error)).collect::<Vec<_>>();
+        assert_eq!((errors[0].record, errors[0].header.as_ref().unwrap().as_str()), (2, "a"));
+        assert_eq!((errors[1].record, errors[1].header.as_ref().unwrap().as_str()), (4, "b"));
+    }
+
+    #[test]
+    fn test_error_policy_collect_too_many_columns() {
+        let rdr = Reader::from_string("a,b\n0,1\n2,3,x\n4,5\n");
+        let mut records = rdr.error_policy(ErrorPolicy::Collect).decode();
+        let rows = records.by_ref().collect::<Result<Vec<SimpleStruct>>>().unwrap();
+        assert_eq!(rows, vec![SimpleStruct { a: 0, b: 1 }, SimpleStruct { a: 4, b: 5 }]);
+        let rejected = records.into_rejected();
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].fields, vec![b"2".to_vec(), b"3".to_vec(), b"x".to_vec()]);
+        let rdr = Reader::from_string("x\na,b\n0,1\n2\n4,5\n").skip_rows(1);
+        let mut records = rdr.error_policy(ErrorPolicy::Collect).decode();
+        let rows = records.by_ref().collect::<Result<Vec<SimpleStruct>>>().unwrap();
+        assert_eq!(rows, vec![SimpleStruct { a: 0, b: 1 }, SimpleStruct { a: 4, b: 5 }]);
+        assert_eq!(records.rejected()[0].fields, vec![b"2".to_vec()]);
+    }
+
+    #[test]
+    fn test_error_policy_does_not_skip_header_mismatch() {
+        let rdr = Reader::from_string("a,c\n0,1\n");
+        let mut records = rdr.error_policy(ErrorPolicy::Collect).decode::<SimpleStruct>();
+        assert!(records.next().unwrap().is_err());
+        assert!(records.next().is_none());
+        assert!(records.rejected().is_empty());
+    }
+
+    #[test]
+    fn test_tuple_of_structs() {
+        let rdr = Reader::from_string("a,b,a,b\n0,1,2,3\n4,5,6,7\n");
+        let records = rdr.decode().collect::<Result<Vec<(SimpleStruct, SimpleStruct)>>>().unwrap();
+        assert_eq!(records,
+                   vec![(SimpleStruct { a: 0, b: 1 }, SimpleStruct { a: 2, b: 3 }),
+                        (SimpleStruct { a: 4, b: 5 }, SimpleStruct { a: 6, b: 7 })]);
+    }
+
+    #[test]
+    fn test_tuple_of_structs_allow_reorder() {
+        let rdr = Reader::from_string("b,a,a,b\n0,1,2,3\n\n4,5,6,7\n");
+        let records = rdr.reorder_columns(true)
+            .decode()
+            .collect::<Result<Vec<(SimpleStruct, SimpleStruct)>>>()
+            .unwrap();
+        assert_eq!(records,
+                   vec![(SimpleStruct { a: 1, b: 0 }, SimpleStruct { a: 2, b: 3 }),
+                        (SimpleStruct { a: 5, b: 4 }, SimpleStruct { a: 6, b: 7 })]);
+    }
+
+    #[test]
+    fn test_tuple_of_structs_misnamed_headers() {
+        let rdr = Reader::from_string("a,b,c,d\n0,1,2,3\n4,5,6,7\n");
+        let err = rdr.decode().collect::<Result<Vec<(SimpleStruct, SimpleStruct)>>>().unwrap_err();
+        let mismatch = header_mismatch(err);
+        assert_eq!(mismatch.missing_fields, vec![missing(2, "a"), missing(3, "b")]);
+        assert_eq!(mismatch.unexpected_headers,
+                   vec![unexpected(2, "c"), unexpected(3, "d")]);
+    }
+
+    #[derive(Debug, PartialEq, RustcDecodable)]
+    #[cfg_attr(feature = "serde", derive(Deserialize))]
+    struct StructOfStruct {
+        p: SimpleStruct,
+        q: usize,
+    }
+
+    #[test]
+    fn test_struct_of_struct() {
+        let rdr = Reader::from_string("p.a,p.b,q\n0,1,2\n");
+        let records = rdr.decode().collect::<Result<Vec<StructOfStruct>>>().unwrap();
+        assert_eq!(records,
+                   vec![StructOfStruct {
+                            p: SimpleStruct { a: 0, b: 1 },
+                            q: 2,
+                        }]);
+    }
+
+    #[test]
+    fn test_struct_of_struct_allow_reorder_and_nested_field_separator() {
+        let rdr = Reader::from_string("q,p_b,p_a\n0,1,2\n");
+        let records = rdr.reorder_columns(true)
+            .nested_field_separator("_")
+            .decode()
+            .collect::<Result<Vec<StructOfStruct>>>()
+            .unwrap();
+        assert_eq!(records,
+                   vec![StructOfStruct {
+                            p: SimpleStruct { a: 2, b: 1 },
+                            q: 0,
+                        }]);
+    }
+
+    #[test]
+    fn test_struct_of_struct_unprefixed_headers() {
+        let rdr = Reader::from_string("a,b,q\n0,1,2\n");
+        let err = rdr.decode().collect::<Result<Vec<StructOfStruct>>>().unwrap_err();
+        let mismatch = header_mismatch(err);
+        assert_eq!(mismatch.missing_fields, vec![missing(0, "p.a"), missing(1, "p.b")]);
+        assert_eq!(mismatch.unexpected_headers,
+                   vec![unexpected(0, "a"), unexpected(1, "b")]);
     }
 
-    /// Enable double quote escapes.
-    ///
-    /// When disabled, doubled quotes are not interpreted as escapes.
-    pub fn double_quote(mut self, yes: bool) -> Reader<'a, R> {
-        self.csv = self.csv.double_quote(yes);
-        self
+    #[derive(Debug, PartialEq, RustcDecodable)]
+    #[cfg_attr(feature = "serde", derive(Deserialize))]
+    struct StructWithArrayAndTuple {
+        a: [usize; 2],
+        b: (usize, usize, usize),
+        c: usize,
     }
 
-    /// A convenience method for reading ASCII delimited text.
-    ///
-    /// This sets the delimiter and record terminator to the ASCII unit
-    /// separator (`\x1f`) and record separator (`\x1e`), respectively.
-    ///
-    /// Since ASCII delimited text is meant to be unquoted, this also sets
-    /// `quote` to `None`.
-    pub fn ascii(mut self) -> Reader<'a, R> {
-        self.csv = self.csv.ascii();
-        self
+    #[test]
+    fn test_struct_with_array_and_tuple() {
+        let rdr = Reader::from_string("a_0,a_1,b_0,b_1,b_2,c\n0,1,2,3,4,5\n");
+        let records = rdr.decode().collect::<Result<Vec<StructWithArrayAndTuple>>>().unwrap();
+        assert_eq!(records,
+                   vec![StructWithArrayAndTuple {
+                            a: [0, 1],
+                            b: (2, 3, 4),
+                            c: 5,
+                        }]);
     }
-}
 
-/// An iterator of decoded records.
-///
-/// The lifetime parameter `'a` refers to the lifetime of the predicate used
-/// for comparing headers to field names. The `R` type parameter refers to the
-/// type of the underlying reader. The `D` type parameter refers to the decoded
-/// type.
-pub struct DecodedRecords<'a, R: Read, D: Decodable> {
-    p: csv::Reader<R>,
-    reorder_columns: bool,
-    ignore_unused_columns: bool,
-    headers_match_by: &'a Fn(&[u8], &[u8]) -> bool,
-    done_first: bool,
-    /// Finished reading records or encountered an error.
-    done: bool,
-    /// Indices are column indices and values are the (optional) field indices.
-    column_mapping: Vec<Option<usize>>,
-    field_count: usize,
-    record_type: PhantomData<D>,
-}
+    #[test]
+    fn test_struct_with_array_and_tuple_allow_reorder_and_indexed_field_pattern() {
+        let rdr = Reader::from_string("c,b[2],a[1],b[0],a[0],b[1]\n0,1,2,3,4,5\n");
+        let records = rdr.reorder_columns(true)
+            .indexed_field_pattern("{name}[{index}]")
+            .decode()
+            .collect::<Result<Vec<StructWithArrayAndTuple>>>()
+            .unwrap();
+        assert_eq!(records,
+                   vec![StructWithArrayAndTuple {
+                            a: [4, 2],
+                            b: (3, 5, 1),
+                            c: 0,
+                        }]);
+    }
 
-/// Determinines mapping of columns to fields according to headers and field names.
-///
-/// The mapping is a `Vec` of indices, where the indices of the `Vec` are the
-/// column indices, and the values of the `Vec` are the (optional) field indices.
-///
-/// The first argument to the predicate is the header, and the second argument
-/// is the field name.
-fn map_headers<P>(headers: &[ByteString],
-                  field_names: &[ByteString],
-                  reorder: bool,
-                  ignore_unused_columns: bool,
-                  predicate: &P)
-                  -> Result<Vec<Option<usize>>>
-    where P: ?Sized + Fn(&[u8], &[u8]) -> bool
-{
-    if headers.len() < field_names.len() ||
-       (headers.len() > field_names.len() && !ignore_unused_columns) {
-        return Err(Error::Decode(format!("The decodable type has {} field names, but there are \
-                                          {} headers",
-                                         field_names.len(),
-                                         headers.len())));
-    }
-    if reorder {
-        let mut mapping = vec![None; headers.len()];
-        // Headers used so far.
-        let mut headers_used = vec![false; headers.len()];
-        for (field_index, field_name) in field_names.iter().enumerate() {
-            // Search for the first matching header that hasn't been used yet.
-            let found = headers.iter()
-                .zip(headers_used.iter())
-                .position(|(header, used)| predicate(header, field_name) && !used);
-            match found {
-                Some(header_index) => {
-                    mapping[header_index] = Some(field_index);
-                    headers_used[header_index] = true;
-                }
-                None => {
-                    return Err(Error::Decode("Headers don't match field names".to_string()));
-                }
-            }
+    #[test]
+    fn test_struct_with_array_and_tuple_missing_element() {
+        let rdr = Reader::from_string("a_0,b_0,b_1,b_2,c\n0,1,2,3,4\n");
+        let err = rdr.decode().collect::<Result<Vec<StructWithArrayAndTuple>>>().unwrap_err();
+        let mismatch = header_mismatch(err);
+        assert_eq!(mismatch.missing_fields, vec![missing(1, "a_1")]);
+        assert!(mismatch.unexpected_headers.is_empty());
+    }
+
+    #[test]
+    fn test_error_into_csv_error() {
+        fn read(data: &str) -> csv::Result<Vec<SimpleStruct>> {
+            let rdr = Reader::from_string(data);
+            Ok(rdr.decode().collect::<Result<Vec<SimpleStruct>>>()?)
         }
-        Ok(mapping)
-    } else if ignore_unused_columns {
-        let mut mapping = vec![None; headers.len()];
-        // Cursor to keep track of starting position in `headers` slice.
-        let mut cursor = 0;
-        for (field_index, field_name) in field_names.iter().enumerate() {
-            // Search for the first matching header, starting from `cursor`.
-            let found = (cursor..headers.len())
-                .find(|&header_index| predicate(&headers[header_index], field_name));
-            match found {
-                Some(header_index) => {
-                    mapping[header_index] = Some(field_index);
-                    cursor = header_index + 1;
-                }
-                None => {
-                    return Err(Error::Decode("Headers don't match field names".to_string()));
-                }
+        match read("a,b\n0,x\n") {
+            Err(csv::Error::Decode(msg)) => {
+                assert_eq!(msg,
+                           "CSV decode error in record 1 (line 2, byte 4), column 1, header 'b', \
+                            field 'b', value \"x\": Failed converting 'x' from str.")
             }
+            result => panic!("Expected a decode error, but got {:?}", result),
         }
-        Ok(mapping)
-    } else if headers.iter().zip(field_names).all(|(h, f)| predicate(h, f)) {
-        Ok((0..headers.len()).map(|i| Some(i)).collect())
-    } else {
-        Err(Error::Decode("Headers don't match field names".to_string()))
     }
-}
-
-impl<'a, R: Read, D: Decodable> DecodedRecords<'a, R, D> {
-    /// Processes the first row, setting `self.done_first, `self.field_count`,
-    /// and `self.column_mapping`.
-    ///
-    /// This method is idempotent and fast on subsequent calls (since it uses
-    /// `self.done_first` to track whether it's been called before).
-    fn process_first_row(&mut self) -> Result<()> {
-        if !self.done_first {
-            self.done_first = true;
-
-            // Always consume the header record. If headers have been read
-            // before this point, then this is equivalent to a harmless clone
-            // (and no parser progression).
-            let headers = self.p.byte_headers();
 
-            // If the header row is empty, then the CSV data contains no records.
-            if headers.as_ref().map(|r| r.is_empty()).unwrap_or(false) {
-                assert!(self.p.done());
-                return Ok(());
-            }
+    #[derive(Debug, PartialEq, RustcDecodable)]
+    #[cfg_attr(feature = "serde", derive(Deserialize))]
+    struct StructWithVec {
+        a: usize,
+        v: Vec<usize>,
+        b: usize,
+    }
 
-            // Otherwise, unwrap the headers.
-            let headers = headers?;
+    #[test]
+    fn test_struct_with_vec() {
+        let rdr = Reader::from_string("a,v_0,v_1,v_2,b\n0,1,2,3,4\n");
+        let records = rdr.decode().collect::<Result<Vec<StructWithVec>>>().unwrap();
+        assert_eq!(records,
+                   vec![StructWithVec {
+                            a: 0,
+                            v: vec![1, 2, 3],
+                            b: 4,
+                        }]);
+        let rdr = Reader::from_string("v,a,b\n1,0,4\n");
+        let records = rdr.reorder_columns(true)
+            .decode()
+            .collect::<Result<Vec<StructWithVec>>>()
+            .unwrap();
+        assert_eq!(records,
+                   vec![StructWithVec {
+                            a: 0,
+                            v: vec![1],
+                            b: 4,
+                        }]);
+        let rdr = Reader::from_string("a,b\n0,4\n");
+        let records = rdr.decode().collect::<Result<Vec<StructWithVec>>>().unwrap();
+        assert_eq!(records,
+                   vec![StructWithVec {
+                            a: 0,
+                            v: vec![],
+                            b: 4,
+                        }]);
+    }
 
-            // Get the field names of the decodable type and set
-            // `self.field_count`.
-            let mut field_names_decoder = FieldNamesDecoder::new();
-            D::decode(&mut field_names_decoder)?;
-            let field_names = field_names_decoder.into_field_names();
+    #[test]
+    fn test_struct_with_vec_gap() {
+        // The elements after a missing index aren't part of the `Vec`.
+        let rdr = Reader::from_string("a,v_0,v_1,v_3,b\n0,1,2,3,4\n");
+        let err = rdr.decode().collect::<Result<Vec<StructWithVec>>>().unwrap_err();
+        assert_eq!(header_mismatch(err).unexpected_headers, vec![unexpected(3, "v_3")]);
+    }
 
-            // Set `field_count` and `column_mapping`.
-            self.field_count = field_names.len();
-            self.column_mapping = map_headers(&headers,
-                                              &field_names,
-                                              self.reorder_columns,
-                                              self.ignore_unused_columns,
-                                              self.headers_match_by)?;
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_struct_with_vec() {
+        for data in &["a,v_0,v_1,v_2,b\n0,1,2,3,4\n", "a,v,b\n0,1,4\n", "a,b\n0,4\n"] {
+            decode_both::<StructWithVec>(data).unwrap();
         }
-        Ok(())
     }
 
-    /// This is wrapped in the `next()` method to ensure that `self.done` is
-    /// always set properly.
-    fn next_impl(&mut self) -> Option<Result<D>> {
-        if let Err(err) = self.process_first_row() {
-            return Some(Err(err));
-        }
+    /// A value that rejects 0 after reading it.
+    #[derive(Debug)]
+    struct NonZero(u8);
 
-        if self.p.done() {
-            return None;
+    impl Decodable for NonZero {
+        fn decode<D: Decoder>(d: &mut D) -> result::Result<Self, D::Error> {
+            match d.read_u8()? {
+                0 => Err(d.error("zero is not allowed")),
+                value => Ok(NonZero(value)),
+            }
         }
+    }
 
-        let mut record = vec![Vec::new(); self.field_count];
-        let mut column = 0;
-        loop {
-            match self.p.next_bytes() {
-                NextField::EndOfRecord | NextField::EndOfCsv => {
-                    if record.is_empty() {
-                        return None;
-                    }
-                    break;
-                }
-                NextField::Error(err) => {
-                    return Some(Err(err));
-                }
-                NextField::Data(field) => {
-                    if column < self.column_mapping.len() {
-                        if let Some(field_index) = self.column_mapping[column] {
-                            record[field_index] = field.to_vec();
-                        }
-                        column += 1;
-                    } else {
-                        return Some(Err(Error::Decode("More data columns than headers"
-                            .to_string())));
-                    }
-                }
-            }
+    #[test]
+    fn test_struct_with_array_error_is_not_a_length_error() {
+        #[derive(Debug, RustcDecodable)]
+        struct Record {
+            a: [NonZero; 2],
         }
-        Some(Decodable::decode(&mut Decoded::new(record)))
+        let rdr = Reader::from_string("a_0,a_1\n1,2\n");
+        let err = rdr.decode().collect::<Result<Vec<Record>>>().unwrap_err();
+        assert_eq!(err.to_string(), "CSV decode error: zero is not allowed");
     }
-}
 
-impl<'a, R: Read, D: Decodable> Iterator for DecodedRecords<'a, R, D> {
-    type Item = Result<D>;
+    /// A sequence that requires more elements than any array.
+    #[derive(Debug)]
+    struct LongSeq;
 
-    fn next(&mut self) -> Option<Result<D>> {
-        if self.done {
-            None
-        } else {
-            let next = self.next_impl();
-            match next {
-                None | Some(Err(_)) => self.done = true,
-                _ => ()
-            }
-            next
+    impl Decodable for LongSeq {
+        fn decode<D: Decoder>(d: &mut D) -> result::Result<Self, D::Error> {
+            d.read_seq(|d, len| if len == 40 {
+                Ok(LongSeq)
+            } else {
+                Err(d.error("wrong sequence length"))
+            })
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::{Reader, Result};
-    use std::ascii::AsciiExt;
+    #[test]
+    fn test_struct_with_seq_of_unknown_length() {
+        #[derive(Debug, RustcDecodable)]
+        struct Record {
+            a: LongSeq,
+        }
+        let rdr = Reader::from_string("a\n1\n");
+        let err = rdr.decode().collect::<Result<Vec<Record>>>().unwrap_err();
+        assert_eq!(err.to_string(),
+                   "CSV decode error: The length of a sequence can't be determined: CSV decode \
+                    error: wrong sequence length");
+    }
 
     #[derive(Debug, PartialEq, RustcDecodable)]
-    struct SimpleStruct {
+    #[cfg_attr(feature = "serde", derive(Deserialize))]
+    struct StructWithCatchAll {
         a: usize,
+        other: BTreeMap<String, String>,
         b: usize,
     }
 
+    fn catch_all(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
     #[test]
-    fn test_struct() {
-        let rdr = Reader::from_string("a,b\n0,1\n2,3\n");
-        let records = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap();
-        assert_eq!(records,
-                   vec![SimpleStruct { a: 0, b: 1 }, SimpleStruct { a: 2, b: 3 }]);
+    fn test_struct_with_catch_all_decode_error_position() {
+        #[derive(Debug, RustcDecodable)]
+        struct Record {
+            a: usize,
+            other: BTreeMap<String, usize>,
+            b: usize,
+        }
+        let rdr = Reader::from_string("a,x,b\n0,1,2\n3,y,5\n");
+        let err = rdr.decode().collect::<Result<Vec<Record>>>().unwrap_err();
+        let err = decode_error(err);
+        assert_eq!(err.record, 2);
+        assert_eq!(err.column, Some(1));
+        assert_eq!(err.header, Some("x".to_string()));
+        assert_eq!(err.field_name, None);
+        assert_eq!(err.value, Some(b"y".to_vec()));
     }
 
     #[test]
-    fn test_struct_allow_reorder() {
-        let rdr = Reader::from_string("b,a\n0,1\n2,3\n");
-        let records =
-            rdr.reorder_columns(true).decode().collect::<Result<Vec<SimpleStruct>>>().unwrap();
+    fn test_struct_with_catch_all() {
+        let rdr = Reader::from_string("x,a,y,b\n0,1,2,3\n4,5,,7\n");
+        let records = rdr.decode().collect::<Result<Vec<StructWithCatchAll>>>().unwrap();
         assert_eq!(records,
-                   vec![SimpleStruct { a: 1, b: 0 }, SimpleStruct { a: 3, b: 2 }]);
+                   vec![StructWithCatchAll {
+                            a: 1,
+                            other: catch_all(&[("x", "0"), ("y", "2")]),
+                            b: 3,
+                        },
+                        StructWithCatchAll {
+                            a: 5,
+                            other: catch_all(&[("x", "4"), ("y", "")]),
+                            b: 7,
+                        }]);
     }
 
     #[test]
-    fn test_struct_ignore_unused_columns() {
-        let rdr = Reader::from_string("a,b,c\n0,1,2\n3,4,5\n");
-        let records = rdr.ignore_unused_columns(true)
+    fn test_struct_with_catch_all_allow_reorder() {
+        let rdr = Reader::from_string("b,a\n0,1\n");
+        let records = rdr.reorder_columns(true)
             .decode()
-            .collect::<Result<Vec<SimpleStruct>>>()
+            .collect::<Result<Vec<StructWithCatchAll>>>()
             .unwrap();
         assert_eq!(records,
-                   vec![SimpleStruct { a: 0, b: 1 }, SimpleStruct { a: 3, b: 4 }]);
+                   vec![StructWithCatchAll {
+                            a: 1,
+                            other: BTreeMap::new(),
+                            b: 0,
+                        }]);
     }
 
     #[test]
-    fn test_struct_allow_reorder_and_ignore_unused_columns() {
+    fn test_catch_all_only() {
+        let rdr = Reader::from_string("a,b\n0,1\n");
+        let records = rdr.decode().collect::<Result<Vec<HashMap<String, usize>>>>().unwrap();
+        let expected = vec![("a".to_string(), 0), ("b".to_string(), 1)].into_iter().collect();
+        assert_eq!(records, vec![expected]);
+    }
+
+    #[derive(Debug, RustcDecodable)]
+    #[cfg_attr(feature = "serde", derive(Deserialize))]
+    struct StructWithTwoCatchAlls {
+        a: HashMap<String, String>,
+        b: HashMap<String, String>,
+    }
+
+    #[test]
+    fn test_struct_with_two_catch_alls() {
+        let rdr = Reader::from_string("a,b\n0,1\n");
+        let err = rdr.decode().collect::<Result<Vec<StructWithTwoCatchAlls>>>().unwrap_err();
+        assert_eq!(format!("{}", err),
+                   "CSV decode error: The decodable type has more than one catch-all map"
+                       .to_string());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_struct() {
+        let rdr = Reader::from_string("a,b\n0,1\n2,3\n");
+        let records = rdr.deserialize().collect::<Result<Vec<SimpleStruct>>>().unwrap();
+        assert_eq!(records,
+                   vec![SimpleStruct { a: 0, b: 1 }, SimpleStruct { a: 2, b: 3 }]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_struct_allow_reorder_and_ignore_unused_columns() {
         let rdr = Reader::from_string("b,c,a\n0,1,2\n3,4,5\n");
         let records = rdr.reorder_columns(true)
             .ignore_unused_columns(true)
-            .decode()
+            .deserialize()
             .collect::<Result<Vec<SimpleStruct>>>()
             .unwrap();
         assert_eq!(records,
                    vec![SimpleStruct { a: 2, b: 0 }, SimpleStruct { a: 5, b: 3 }]);
     }
 
+    #[cfg(feature = "serde")]
     #[test]
-    fn test_struct_headers_match_by() {
-        let rdr = Reader::from_string("a,B\n0,1\n2,3\n");
-        let records = rdr.headers_match_by(&<[u8]>::eq_ignore_ascii_case)
-            .decode()
-            .collect::<Result<Vec<SimpleStruct>>>()
-            .unwrap();
+    fn test_serde_struct_misnamed_headers() {
+        let rdr = Reader::from_string("c,d\n0,1\n");
+        let err = rdr.deserialize().collect::<Result<Vec<SimpleStruct>>>().unwrap_err();
+        let mismatch = header_mismatch(err);
+        assert_eq!(mismatch.missing_fields, vec![missing(0, "a"), missing(1, "b")]);
+        assert_eq!(mismatch.unexpected_headers,
+                   vec![unexpected(0, "c"), unexpected(1, "d")]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_check_headers() {
+        let mut rdr = Reader::from_string("a,c\n0,1\n");
+        let mismatch = header_mismatch(rdr.check_deserialize_headers::<SimpleStruct>()
+            .unwrap_err());
+        assert_eq!(mismatch.missing_fields, vec![missing(1, "b")]);
+        rdr.check_deserialize_headers::<StructWithOption>().unwrap_err();
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_struct_with_option_missing_header() {
+        let rdr = Reader::from_string("a\n0\n");
+        let records = rdr.deserialize().collect::<Result<Vec<StructWithOption>>>().unwrap();
+        assert_eq!(records, vec![StructWithOption { a: 0, b: None }]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_error_policy_collect() {
+        let rdr = Reader::from_string("a,b\n0,1\nx,3\n4,5\n");
+        let mut records = rdr.error_policy(ErrorPolicy::Collect).deserialize();
+        let rows = records.by_ref().collect::<Result<Vec<SimpleStruct>>>().unwrap();
+        assert_eq!(rows, vec![SimpleStruct { a: 0, b: 1 }, SimpleStruct { a: 4, b: 5 }]);
+        assert_eq!(records.rejected().len(), 1);
+        assert_eq!(records.rejected()[0].fields, vec![b"x".to_vec(), b"3".to_vec()]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_tuple_of_structs() {
+        let rdr = Reader::from_string("a,b,a,b\n0,1,2,3\n4,5,6,7\n");
+        let records =
+            rdr.deserialize().collect::<Result<Vec<(SimpleStruct, SimpleStruct)>>>().unwrap();
         assert_eq!(records,
-                   vec![SimpleStruct { a: 0, b: 1 }, SimpleStruct { a: 2, b: 3 }]);
+                   vec![(SimpleStruct { a: 0, b: 1 }, SimpleStruct { a: 2, b: 3 }),
+                        (SimpleStruct { a: 4, b: 5 }, SimpleStruct { a: 6, b: 7 })]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[derive(Debug, PartialEq, Deserialize)]
+    enum Group {
+        Bird,
+        Mammal,
+    }
+
+    #[cfg(feature = "serde")]
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Count(usize);
+
+    #[cfg(feature = "serde")]
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct SerdeStruct {
+        count: Count,
+        group: Group,
+        #[serde(rename = "desc")]
+        description: Option<String>,
     }
 
+    #[cfg(feature = "serde")]
     #[test]
-    fn test_struct_reordered_headers() {
-        let rdr = Reader::from_string("b,a\n0,1\n2,3\n");
-        let err = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap_err();
-        assert_eq!(format!("{}", err),
-                   "CSV decode error: Headers don't match field names".to_string());
+    fn test_serde_newtype_enum_option_and_rename() {
+        let rdr = Reader::from_string("count,group,desc\n7,Bird,happy\n10,Mammal,\n");
+        let records = rdr.deserialize().collect::<Result<Vec<SerdeStruct>>>().unwrap();
+        assert_eq!(records,
+                   vec![SerdeStruct {
+                            count: Count(7),
+                            group: Group::Bird,
+                            description: Some("happy".to_string()),
+                        },
+                        SerdeStruct {
+                            count: Count(10),
+                            group: Group::Mammal,
+                            description: None,
+                        }]);
     }
 
+    #[cfg(feature = "serde")]
     #[test]
-    fn test_struct_wrong_case() {
-        let rdr = Reader::from_string("a,B\n0,1\n2,3\n");
-        let err = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap_err();
-        assert_eq!(format!("{}", err),
-                   "CSV decode error: Headers don't match field names".to_string());
+    fn test_serde_struct_of_struct() {
+        let rdr = Reader::from_string("p.a,p.b,q\n0,1,2\n");
+        let records = rdr.deserialize().collect::<Result<Vec<StructOfStruct>>>().unwrap();
+        assert_eq!(records,
+                   vec![StructOfStruct {
+                            p: SimpleStruct { a: 0, b: 1 },
+                            q: 2,
+                        }]);
     }
 
+    #[cfg(feature = "serde")]
     #[test]
-    fn test_struct_misnamed_headers() {
-        let rdr = Reader::from_string("c,d\n0,1\n");
-        let err = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap_err();
-        assert_eq!(format!("{}", err),
-                   "CSV decode error: Headers don't match field names".to_string());
+    fn test_serde_struct_with_array_and_tuple() {
+        let rdr = Reader::from_string("a_0,a_1,b_0,b_1,b_2,c\n0,1,2,3,4,5\n");
+        let records = rdr.deserialize().collect::<Result<Vec<StructWithArrayAndTuple>>>().unwrap();
+        assert_eq!(records,
+                   vec![StructWithArrayAndTuple {
+                            a: [0, 1],
+                            b: (2, 3, 4),
+                            c: 5,
+                        }]);
     }
 
+    #[cfg(feature = "serde")]
     #[test]
-    fn test_struct_missing_header() {
-        let rdr = Reader::from_string("a\n0\n");
-        let err = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap_err();
-        assert_eq!(format!("{}", err),
-                   "CSV decode error: The decodable type has 2 field names, but there are 1 \
-                    headers"
-                       .to_string());
+    fn test_serde_decode_error_position() {
+        let rdr = Reader::from_string("a,b\n0,1\n2,x\n");
+        let err = rdr.deserialize().collect::<Result<Vec<SimpleStruct>>>().unwrap_err();
+        let err = decode_error(err);
+        assert_eq!((err.record, err.line, err.byte), (2, 3, 8));
+        assert_eq!(err.column, Some(1));
+        assert_eq!(err.header, Some("b".to_string()));
+        assert_eq!(err.field_name, Some("b".to_string()));
+        assert_eq!(err.value, Some(b"x".to_vec()));
     }
 
+    #[cfg(feature = "serde")]
     #[test]
-    fn test_struct_extra_header() {
-        let rdr = Reader::from_string("a,b,c\n0,1\n");
-        let err = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap_err();
-        assert_eq!(format!("{}", err),
-                   "CSV decode error: The decodable type has 2 field names, but there are 3 \
-                    headers"
-                       .to_string());
+    fn test_serde_unknown_variant_error_position() {
+        let rdr = Reader::from_string("count,group,desc\n7,Fish,happy\n");
+        let err = rdr.deserialize().collect::<Result<Vec<SerdeStruct>>>().unwrap_err();
+        let err = decode_error(err);
+        assert_eq!((err.record, err.column), (1, Some(1)));
+        assert_eq!(err.field_name, Some("group".to_string()));
+        assert_eq!(err.value, Some(b"Fish".to_vec()));
     }
 
+    #[cfg(feature = "serde")]
     #[test]
-    fn test_struct_extra_data_column() {
-        let rdr = Reader::from_string("a,b\n0,1,2\n");
-        let err = rdr.decode().collect::<Result<Vec<SimpleStruct>>>().unwrap_err();
-        assert_eq!(format!("{}", err),
-                   "CSV decode error: More data columns than headers".to_string());
+    fn test_serde_struct_with_catch_all_decode_error_position() {
+        #[derive(Debug, Deserialize)]
+        struct Record {
+            a: usize,
+            other: BTreeMap<String, usize>,
+            b: usize,
+        }
+        let rdr = Reader::from_string("a,x,b\n0,1,2\n3,y,5\n");
+        let err = rdr.deserialize().collect::<Result<Vec<Record>>>().unwrap_err();
+        let err = decode_error(err);
+        assert_eq!(err.record, 2);
+        assert_eq!(err.column, Some(1));
+        assert_eq!(err.header, Some("x".to_string()));
+        assert_eq!(err.field_name, None);
+        assert_eq!(err.value, Some(b"y".to_vec()));
     }
 
+    #[cfg(feature = "serde")]
     #[test]
-    fn test_tuple_of_structs() {
-        let rdr = Reader::from_string("a,b,a,b\n0,1,2,3\n4,5,6,7\n");
-        let records = rdr.decode().collect::<Result<Vec<(SimpleStruct, SimpleStruct)>>>().unwrap();
+    fn test_serde_struct_with_catch_all() {
+        let rdr = Reader::from_string("x,a,y,b\n0,1,2,3\n");
+        let records = rdr.deserialize().collect::<Result<Vec<StructWithCatchAll>>>().unwrap();
         assert_eq!(records,
-                   vec![(SimpleStruct { a: 0, b: 1 }, SimpleStruct { a: 2, b: 3 }),
-                        (SimpleStruct { a: 4, b: 5 }, SimpleStruct { a: 6, b: 7 })]);
+                   vec![StructWithCatchAll {
+                            a: 1,
+                            other: catch_all(&[("x", "0"), ("y", "2")]),
+                            b: 3,
+                        }]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[derive(Debug, PartialEq, RustcDecodable, Deserialize)]
+    struct ParityStruct {
+        a: i32,
+        b: Option<u8>,
+        c: Option<Option<f64>>,
+        d: Option<String>,
+        e: bool,
+        f: char,
+    }
+
+    /// Decodes `data` with both `rustc_serialize` and `serde`, and checks
+    /// that the results are the same.
+    #[cfg(feature = "serde")]
+    fn decode_both<D>(data: &str) -> Result<Vec<D>>
+        where D: Decodable + ::serde::de::DeserializeOwned + PartialEq +
+                 ::std::fmt::Debug
+    {
+        let decoded = Reader::from_string(data).decode().collect::<Result<Vec<D>>>();
+        let deserialized = Reader::from_string(data).deserialize().collect::<Result<Vec<D>>>();
+        match (decoded, deserialized) {
+            (Ok(decoded), Ok(deserialized)) => {
+                assert_eq!(decoded, deserialized);
+                Ok(decoded)
+            }
+            (Err(decoded), Err(deserialized)) => {
+                let (decoded, deserialized) = (decode_error(decoded), decode_error(deserialized));
+                assert_eq!((decoded.record, decoded.column),
+                           (deserialized.record, deserialized.column));
+                Err(Error::Decode(decoded))
+            }
+            (decoded, deserialized) => panic!("{:?} != {:?}", decoded, deserialized),
+        }
     }
 
+    #[cfg(feature = "serde")]
     #[test]
-    fn test_tuple_of_structs_allow_reorder() {
-        let rdr = Reader::from_string("b,a,a,b\n0,1,2,3\n\n4,5,6,7\n");
-        let records = rdr.reorder_columns(true)
-            .decode()
-            .collect::<Result<Vec<(SimpleStruct, SimpleStruct)>>>()
+    fn test_serde_same_as_rustc() {
+        let records = decode_both::<ParityStruct>("\
+a,b,c,d,e,f
+ 1 ,2, 3.5 ,x,true,y
+-4, 300 ,z,,false, 
+5,,,  ,  true  ,z
+")
             .unwrap();
         assert_eq!(records,
-                   vec![(SimpleStruct { a: 1, b: 0 }, SimpleStruct { a: 2, b: 3 }),
-                        (SimpleStruct { a: 5, b: 4 }, SimpleStruct { a: 6, b: 7 })]);
+                   vec![ParityStruct {
+                            a: 1,
+                            b: Some(2),
+                            c: Some(Some(3.5)),
+                            d: Some("x".to_string()),
+                            e: true,
+                            f: 'y',
+                        },
+                        ParityStruct {
+                            a: -4,
+                            b: None,
+                            c: Some(None),
+                            d: None,
+                            e: false,
+                            f: ' ',
+                        },
+                        ParityStruct {
+                            a: 5,
+                            b: None,
+                            c: None,
+                            d: Some("  ".to_string()),
+                            e: true,
+                            f: 'z',
+                        }]);
     }
 
+    #[cfg(feature = "serde")]
     #[test]
-    fn test_tuple_of_structs_misnamed_headers() {
-        let rdr = Reader::from_string("a,b,c,d\n0,1,2,3\n4,5,6,7\n");
-        let err = rdr.decode().collect::<Result<Vec<(SimpleStruct, SimpleStruct)>>>().unwrap_err();
+    fn test_serde_same_errors_as_rustc() {
+        let data = "a,b,c,d,e,f\n1,,,,true, y\n";
+        let err = decode_error(decode_both::<ParityStruct>(data).unwrap_err());
+        assert_eq!((err.record, err.column), (1, Some(5)));
+        let data = "a,b,c,d,e,f\n1.5,,,,true,y\n";
+        let err = decode_error(decode_both::<ParityStruct>(data).unwrap_err());
+        assert_eq!((err.record, err.column), (1, Some(0)));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_struct_with_two_catch_alls() {
+        let rdr = Reader::from_string("a,b\n0,1\n");
+        let err = rdr.deserialize().collect::<Result<Vec<StructWithTwoCatchAlls>>>().unwrap_err();
         assert_eq!(format!("{}", err),
-                   "CSV decode error: Headers don't match field names".to_string());
+                   "CSV decode error: The deserializable type has more than one catch-all map"
+                       .to_string());
     }
 }
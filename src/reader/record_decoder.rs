@@ -0,0 +1,350 @@
+use super::MappedRecord;
+
+use csv::{ByteString, Error, Result};
+use rustc_serialize::Decoder;
+use std::str::FromStr;
+
+/// Decoder for a single record whose fields are in the same order as the
+/// field names of the record type.
+///
+/// This behaves like `csv::Decoded`, except that sequences are given the
+/// lengths determined by `FieldNamesDecoder` and the headers instead of the
+/// number of remaining fields. This is necessary for sequences that aren't at
+/// the end of the record.
+#[derive(Debug)]
+pub struct RecordDecoder<'a> {
+    record: Vec<ByteString>,
+    /// Headers and values of the columns for the catch-all map.
+    catch_all: Vec<(ByteString, ByteString)>,
+    /// Index of the next field in `record`.
+    pos: usize,
+    /// Lengths of the sequences, in the order they're read, or `None` for
+    /// sequences that consume the rest of the record.
+    seq_lens: &'a [Option<usize>],
+    /// Number of sequences read so far.
+    seq_count: usize,
+    /// Number of `Option`s containing the current value.
+    option_depth: usize,
+    /// Position in `record` where the catch-all map was inserted, and the
+    /// number of fields inserted.
+    catch_all_span: Option<(usize, usize)>,
+    /// Index in `record` of the field that caused the last error.
+    error_pos: Option<usize>,
+}
+
+/// The field of a record that caused a decoding error, with its raw bytes.
+#[derive(Debug)]
+pub enum ErrorField<'r> {
+    /// The field with the given index in the field names.
+    Field(usize, &'r [u8]),
+    /// The header or value of the entry of the catch-all map with the given
+    /// index.
+    CatchAll(usize, &'r [u8]),
+}
+
+impl<'a> RecordDecoder<'a> {
+    /// Creates a new `RecordDecoder` from a record and the lengths of the
+    /// sequences in the record type.
+    pub fn new(record: MappedRecord, seq_lens: &'a [Option<usize>]) -> RecordDecoder<'a> {
+        RecordDecoder {
+            record: record.fields,
+            catch_all: record.catch_all,
+            pos: 0,
+            seq_lens: seq_lens,
+            seq_count: 0,
+            option_depth: 0,
+            catch_all_span: None,
+            error_pos: None,
+        }
+    }
+
+    /// Returns the field that caused the last error, if the error was caused
+    /// by a single field.
+    pub fn error_field(&self) -> Option<ErrorField> {
+        let pos = match self.error_pos {
+            Some(pos) => pos,
+            None => return None,
+        };
+        let value = match self.record.get(pos) {
+            Some(value) => value,
+            None => return None,
+        };
+        Some(match self.catch_all_span {
+            Some((start, len)) if pos >= start + len => ErrorField::Field(pos - len, value),
+            Some((start, _)) if pos >= start => ErrorField::CatchAll((pos - start) / 2, value),
+            _ => ErrorField::Field(pos, value),
+        })
+    }
+
+    /// Returns a decoding error caused by the field at `pos`.
+    fn field_error(&mut self, pos: usize, msg: String) -> Error {
+        self.error_pos = Some(pos);
+        Error::Decode(msg)
+    }
+
+    fn peek_bytes(&mut self) -> Result<&[u8]> {
+        if self.pos >= self.record.len() {
+            self.error_pos = None;
+            return Err(Error::Decode("Expected a record field, but reached the end of the \
+                                      record."
+                .to_string()));
+        }
+        Ok(&self.record[self.pos])
+    }
+
+    fn pop_string(&mut self) -> Result<String> {
+        let pos = self.pos;
+        let bytes = self.peek_bytes()?.to_vec();
+        self.pos += 1;
+        String::from_utf8(bytes).map_err(|err| {
+            let msg = format!("Could not convert bytes '{:?}' to UTF-8.", err.into_bytes());
+            self.field_error(pos, msg)
+        })
+    }
+
+    fn pop_from_str<T: FromStr>(&mut self) -> Result<T> {
+        let s = self.pop_string()?;
+        let s = s.trim();
+        let pos = self.pos - 1;
+        s.parse().map_err(|_| self.field_error(pos, format!("Failed converting '{}' from str.", s)))
+    }
+}
+
+impl<'a> Decoder for RecordDecoder<'a> {
+    type Error = Error;
+
+    fn error(&mut self, err: &str) -> Error {
+        // This is called by `Decodable` implementations after reading the
+        // field that they reject.
+        let pos = self.pos.saturating_sub(1);
+        self.field_error(pos, err.into())
+    }
+
+    fn read_nil(&mut self) -> Result<()> {
+        let s = self.pop_string()?;
+        if s.is_empty() {
+            Ok(())
+        } else {
+            let pos = self.pos - 1;
+            Err(self.field_error(pos, format!("Expected an empty field but got '{}'.", s)))
+        }
+    }
+
+    fn read_usize(&mut self) -> Result<usize> {
+        self.pop_from_str()
+    }
+
+    fn read_u64(&mut self) -> Result<u64> {
+        self.pop_from_str()
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        self.pop_from_str()
+    }
+
+    fn read_u16(&mut self) -> Result<u16> {
+        self.pop_from_str()
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        self.pop_from_str()
+    }
+
+    fn read_isize(&mut self) -> Result<isize> {
+        self.pop_from_str()
+    }
+
+    fn read_i64(&mut self) -> Result<i64> {
+        self.pop_from_str()
+    }
+
+    fn read_i32(&mut self) -> Result<i32> {
+        self.pop_from_str()
+    }
+
+    fn read_i16(&mut self) -> Result<i16> {
+        self.pop_from_str()
+    }
+
+    fn read_i8(&mut self) -> Result<i8> {
+        self.pop_from_str()
+    }
+
+    fn read_bool(&mut self) -> Result<bool> {
+        self.pop_from_str()
+    }
+
+    fn read_f64(&mut self) -> Result<f64> {
+        self.pop_from_str()
+    }
+
+    fn read_f32(&mut self) -> Result<f32> {
+        self.pop_from_str()
+    }
+
+    fn read_char(&mut self) -> Result<char> {
+        let s = self.pop_string()?;
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(c),
+            _ => {
+                let pos = self.pos - 1;
+                Err(self.field_error(pos, format!("Expected single character but got '{}'.", s)))
+            }
+        }
+    }
+
+    fn read_str(&mut self) -> Result<String> {
+        self.pop_string()
+    }
+
+    fn read_enum<T, F>(&mut self, _: &str, f: F) -> Result<T>
+        where F: FnOnce(&mut Self) -> Result<T>
+    {
+        f(self)
+    }
+
+    fn read_enum_variant<T, F>(&mut self, names: &[&str], mut f: F) -> Result<T>
+        where F: FnMut(&mut Self, usize) -> Result<T>
+    {
+        // If the field is the name of a variant, use that variant.
+        let variant = String::from_utf8_lossy(self.peek_bytes()?).to_lowercase();
+        if let Some(idx) = names.iter().position(|name| name.to_lowercase() == variant) {
+            self.pos += 1;
+            return f(self, idx);
+        }
+        // Otherwise, try each variant in order.
+        let pos = self.pos;
+        for idx in 0..names.len() {
+            match f(self, idx) {
+                Ok(value) => return Ok(value),
+                Err(_) => self.pos = pos,
+            }
+        }
+        Err(self.field_error(pos, format!("Could not load value into any variant in {:?}", names)))
+    }
+
+    fn read_enum_variant_arg<T, F>(&mut self, _: usize, f: F) -> Result<T>
+        where F: FnOnce(&mut Self) -> Result<T>
+    {
+        f(self)
+    }
+
+    fn read_enum_struct_variant<T, F>(&mut self, names: &[&str], f: F) -> Result<T>
+        where F: FnMut(&mut Self, usize) -> Result<T>
+    {
+        self.read_enum_variant(names, f)
+    }
+
+    fn read_enum_struct_variant_field<T, F>(&mut self, _: &str, f_idx: usize, f: F) -> Result<T>
+        where F: FnOnce(&mut Self) -> Result<T>
+    {
+        self.read_enum_variant_arg(f_idx, f)
+    }
+
+    fn read_struct<T, F>(&mut self, _: &str, _: usize, f: F) -> Result<T>
+        where F: FnOnce(&mut Self) -> Result<T>
+    {
+        f(self)
+    }
+
+    fn read_struct_field<T, F>(&mut self, _: &str, _: usize, f: F) -> Result<T>
+        where F: FnOnce(&mut Self) -> Result<T>
+    {
+        f(self)
+    }
+
+    fn read_tuple<T, F>(&mut self, _: usize, f: F) -> Result<T>
+        where F: FnOnce(&mut Self) -> Result<T>
+    {
+        f(self)
+    }
+
+    fn read_tuple_arg<T, F>(&mut self, _: usize, f: F) -> Result<T>
+        where F: FnOnce(&mut Self) -> Result<T>
+    {
+        f(self)
+    }
+
+    fn read_tuple_struct<T, F>(&mut self, _: &str, _: usize, f: F) -> Result<T>
+        where F: FnOnce(&mut Self) -> Result<T>
+    {
+        f(self)
+    }
+
+    fn read_tuple_struct_arg<T, F>(&mut self, _: usize, f: F) -> Result<T>
+        where F: FnOnce(&mut Self) -> Result<T>
+    {
+        f(self)
+    }
+
+    fn read_option<T, F>(&mut self, mut f: F) -> Result<T>
+        where F: FnMut(&mut Self, bool) -> Result<T>
+    {
+        if self.peek_bytes()?.is_empty() {
+            self.pos += 1;
+            return f(self, false);
+        }
+        // If the value can't be decoded, it's `None`.
+        let pos = self.pos;
+        self.option_depth += 1;
+        let result = f(self, true);
+        self.option_depth -= 1;
+        match result {
+            Ok(value) => Ok(value),
+            Err(_) => {
+                self.pos = pos + 1;
+                f(self, false)
+            }
+        }
+    }
+
+    fn read_seq<T, F>(&mut self, f: F) -> Result<T>
+        where F: FnOnce(&mut Self, usize) -> Result<T>
+    {
+        // `FieldNamesDecoder` doesn't look inside `Option`s, so sequences in
+        // them consume the rest of the record, like in `csv::Decoded`.
+        if self.option_depth > 0 {
+            let len = self.record.len() - self.pos;
+            return f(self, len);
+        }
+        let len = match self.seq_lens.get(self.seq_count) {
+            Some(&Some(len)) => len,
+            _ => self.record.len() - self.pos,
+        };
+        self.seq_count += 1;
+        f(self, len)
+    }
+
+    fn read_seq_elt<T, F>(&mut self, _: usize, f: F) -> Result<T>
+        where F: FnOnce(&mut Self) -> Result<T>
+    {
+        f(self)
+    }
+
+    fn read_map<T, F>(&mut self, f: F) -> Result<T>
+        where F: FnOnce(&mut Self, usize) -> Result<T>
+    {
+        // The catch-all map is decoded from its headers and values, which are
+        // inserted into the record as alternating keys and values.
+        let catch_all = ::std::mem::replace(&mut self.catch_all, Vec::new());
+        let len = catch_all.len();
+        let entries = catch_all.into_iter().flat_map(|(header, value)| vec![header, value]);
+        let pos = self.pos;
+        self.record.splice(pos..pos, entries);
+        self.catch_all_span = Some((pos, 2 * len));
+        f(self, len)
+    }
+
+    fn read_map_elt_key<T, F>(&mut self, _: usize, f: F) -> Result<T>
+        where F: FnOnce(&mut Self) -> Result<T>
+    {
+        f(self)
+    }
+
+    fn read_map_elt_val<T, F>(&mut self, _: usize, f: F) -> Result<T>
+        where F: FnOnce(&mut Self) -> Result<T>
+    {
+        f(self)
+    }
+}
@@ -0,0 +1,432 @@
+use super::MappedRecord;
+use super::record_decoder::ErrorField;
+
+use csv::ByteString;
+use serde::de::{self, DeserializeSeed, Deserializer, EnumAccess, IntoDeserializer, MapAccess,
+                SeqAccess, VariantAccess, Visitor};
+use std::error;
+use std::fmt;
+use std::iter;
+use std::result;
+use std::str::FromStr;
+use std::vec;
+
+/// Error produced while deserializing with `serde`.
+///
+/// This is converted into an `Error::Decode` with the position of the record
+/// and the field before being returned to the user.
+#[derive(Debug)]
+pub struct DeError(String);
+
+pub type DeResult<T> = result::Result<T, DeError>;
+
+impl fmt::Display for DeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl error::Error for DeError {
+    fn description(&self) -> &str {
+        &self.0
+    }
+}
+
+impl de::Error for DeError {
+    fn custom<T: fmt::Display>(msg: T) -> DeError {
+        DeError(msg.to_string())
+    }
+}
+
+impl DeError {
+    /// Returns the description of the error.
+    pub fn into_message(self) -> String {
+        self.0
+    }
+}
+
+/// Deserializer for a single record whose fields are in the same order as
+/// the field names of the record type.
+#[derive(Debug)]
+pub struct RecordDeserializer<'a> {
+    fields: Vec<ByteString>,
+    /// Index of the next field in `fields`.
+    pos: usize,
+    /// Headers and values of the columns for the catch-all map.
+    catch_all: Vec<(ByteString, ByteString)>,
+    /// Index in `fields` of the field that was read last, or `None` if the
+    /// record ended before the field that was expected.
+    last_field: Option<usize>,
+    /// Index and raw bytes of the entry of the catch-all map that caused the
+    /// last error.
+    error_entry: Option<(usize, ByteString)>,
+    /// Lengths of the sequences of variable length, in the order they're
+    /// read, or `None` for sequences that consume the rest of the record.
+    seq_lens: &'a [Option<usize>],
+    /// Number of sequences of variable length read so far.
+    seq_count: usize,
+    /// Number of `Option`s containing the current value.
+    option_depth: usize,
+    /// Positions (in `fields`) and depths of the `Option`s that are `None`
+    /// because their values couldn't be deserialized.
+    none_options: Vec<(usize, usize)>,
+    /// Position and depth of the innermost `Option` whose value caused the
+    /// last error.
+    failed_option: Option<(usize, usize)>,
+}
+
+impl<'a> RecordDeserializer<'a> {
+    /// Creates a new `RecordDeserializer` from a record and the lengths of the
+    /// sequences of variable length in the record type.
+    pub fn new(record: MappedRecord, seq_lens: &'a [Option<usize>]) -> RecordDeserializer<'a> {
+        RecordDeserializer {
+            fields: record.fields,
+            pos: 0,
+            catch_all: record.catch_all,
+            last_field: None,
+            error_entry: None,
+            seq_lens: seq_lens,
+            seq_count: 0,
+            option_depth: 0,
+            none_options: Vec::new(),
+            failed_option: None,
+        }
+    }
+
+    /// Creates a new `RecordDeserializer` for a single field.
+    fn from_field(field: ByteString) -> RecordDeserializer<'a> {
+        RecordDeserializer {
+            fields: vec![field],
+            pos: 0,
+            catch_all: Vec::new(),
+            last_field: None,
+            error_entry: None,
+            seq_lens: &[],
+            seq_count: 0,
+            option_depth: 0,
+            none_options: Vec::new(),
+            failed_option: None,
+        }
+    }
+
+    /// Prepares to deserialize the record again after an error, if the error
+    /// was caused by the value of an `Option`. That `Option` is then `None`,
+    /// like in `RecordDecoder::read_option`.
+    ///
+    /// Returns `false` if the error can't be recovered from.
+    pub fn retry(&mut self) -> bool {
+        match self.failed_option.take() {
+            Some(option) => {
+                self.none_options.push(option);
+                self.pos = 0;
+                self.seq_count = 0;
+                self.last_field = None;
+                self.error_entry = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the field that caused the last error. Errors are attributed to
+    /// the field that was read last, like in `RecordDecoder::error`.
+    pub fn error_field(&self) -> Option<ErrorField> {
+        if let Some((entry, ref value)) = self.error_entry {
+            return Some(ErrorField::CatchAll(entry, value));
+        }
+        self.last_field.map(|pos| ErrorField::Field(pos, &self.fields[pos]))
+    }
+
+    fn peek_bytes(&self) -> Option<&ByteString> {
+        self.fields.get(self.pos)
+    }
+
+    fn next_bytes(&mut self) -> DeResult<ByteString> {
+        match self.fields.get(self.pos) {
+            Some(field) => {
+                self.last_field = Some(self.pos);
+                self.pos += 1;
+                Ok(field.clone())
+            }
+            None => {
+                self.last_field = None;
+                Err(DeError("Expected a field, but the record ended".into()))
+            }
+        }
+    }
+
+    fn next_string(&mut self) -> DeResult<String> {
+        String::from_utf8(self.next_bytes()?)
+            .map_err(|err| DeError(format!("Field is not valid UTF-8: {}", err)))
+    }
+
+    fn parse<T>(&mut self) -> DeResult<T>
+        where T: FromStr,
+              T::Err: fmt::Display
+    {
+        let s = self.next_string()?;
+        let s = s.trim();
+        s.parse().map_err(|err| DeError(format!("Failed converting '{}': {}", s, err)))
+    }
+}
+
+macro_rules! deserialize_parse {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+                visitor.$visit(self.parse()?)
+            }
+        )*
+    }
+}
+
+impl<'de, 'a, 'b> Deserializer<'de> for &'a mut RecordDeserializer<'b> {
+    type Error = DeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        visitor.visit_string(self.next_string()?)
+    }
+
+    deserialize_parse! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        let s = self.next_string()?;
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => visitor.visit_char(c),
+            _ => Err(DeError(format!("Expected single character but got '{}'", s))),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        visitor.visit_string(self.next_string()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        visitor.visit_string(self.next_string()?)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        visitor.visit_byte_buf(self.next_bytes()?)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        visitor.visit_byte_buf(self.next_bytes()?)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        let is_empty = self.peek_bytes().map(|field| field.is_empty()).unwrap_or(true);
+        let option = (self.pos, self.option_depth);
+        if is_empty || self.none_options.contains(&option) {
+            self.pos += 1;
+            return visitor.visit_none();
+        }
+        // If the value can't be deserialized, the record is deserialized
+        // again with this `Option` being `None` (see `retry`), since the
+        // visitor can't be reused.
+        self.option_depth += 1;
+        let result = visitor.visit_some(&mut *self);
+        self.option_depth -= 1;
+        if result.is_err() && self.failed_option.is_none() {
+            self.failed_option = Some(option);
+        }
+        result
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        self.next_bytes()?;
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self,
+                                                _: &'static str,
+                                                visitor: V)
+                                                -> DeResult<V::Value> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self,
+                                                   _: &'static str,
+                                                   visitor: V)
+                                                   -> DeResult<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        // `FieldNamesDeserializer` doesn't look inside `Option`s, so sequences
+        // in them consume the rest of the record, like in `csv::Decoded`.
+        let rest = self.fields.len().saturating_sub(self.pos);
+        if self.option_depth > 0 {
+            return visitor.visit_seq(Elements { de: self, remaining: rest });
+        }
+        let len = match self.seq_lens.get(self.seq_count) {
+            Some(&Some(len)) => len,
+            _ => rest,
+        };
+        self.seq_count += 1;
+        visitor.visit_seq(Elements { de: self, remaining: len })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> DeResult<V::Value> {
+        visitor.visit_seq(Elements { de: self, remaining: len })
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self,
+                                                 _: &'static str,
+                                                 len: usize,
+                                                 visitor: V)
+                                                 -> DeResult<V::Value> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        // The entries are kept in case the record is deserialized again.
+        let catch_all = self.catch_all.clone();
+        visitor.visit_map(Entries {
+            de: self,
+            entries: catch_all.into_iter().enumerate(),
+            value: None,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self,
+                                           _: &'static str,
+                                           fields: &'static [&'static str],
+                                           visitor: V)
+                                           -> DeResult<V::Value> {
+        self.deserialize_tuple(fields.len(), visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self,
+                                         _: &'static str,
+                                         _: &'static [&'static str],
+                                         visitor: V)
+                                         -> DeResult<V::Value> {
+        visitor.visit_enum(self)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> DeResult<V::Value> {
+        self.next_bytes()?;
+        visitor.visit_unit()
+    }
+}
+
+impl<'de, 'a, 'b> EnumAccess<'de> for &'a mut RecordDeserializer<'b> {
+    type Error = DeError;
+    type Variant = Self;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> DeResult<(S::Value, Self)> {
+        let name = self.next_string()?;
+        let variant = seed.deserialize(name.into_deserializer())?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de, 'a, 'b> VariantAccess<'de> for &'a mut RecordDeserializer<'b> {
+    type Error = DeError;
+
+    fn unit_variant(self) -> DeResult<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(self, _: S) -> DeResult<S::Value> {
+        Err(DeError("Only unit variants of enums are supported".into()))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _: usize, _: V) -> DeResult<V::Value> {
+        Err(DeError("Only unit variants of enums are supported".into()))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self,
+                                       _: &'static [&'static str],
+                                       _: V)
+                                       -> DeResult<V::Value> {
+        Err(DeError("Only unit variants of enums are supported".into()))
+    }
+}
+
+/// Access to the elements of a struct, tuple, or sequence.
+struct Elements<'a, 'b: 'a> {
+    de: &'a mut RecordDeserializer<'b>,
+    remaining: usize,
+}
+
+impl<'de, 'a, 'b> SeqAccess<'de> for Elements<'a, 'b> {
+    type Error = DeError;
+
+    fn next_element_seed<S>(&mut self, seed: S) -> DeResult<Option<S::Value>>
+        where S: DeserializeSeed<'de>
+    {
+        if self.remaining == 0 {
+            Ok(None)
+        } else {
+            self.remaining -= 1;
+            seed.deserialize(&mut *self.de).map(Some)
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+/// Access to the headers and values of the columns for the catch-all map.
+struct Entries<'a, 'b: 'a> {
+    de: &'a mut RecordDeserializer<'b>,
+    entries: iter::Enumerate<vec::IntoIter<(ByteString, ByteString)>>,
+    /// Index and value of the entry whose key was deserialized last.
+    value: Option<(usize, ByteString)>,
+}
+
+impl<'de, 'a, 'b> MapAccess<'de> for Entries<'a, 'b> {
+    type Error = DeError;
+
+    fn next_key_seed<S>(&mut self, seed: S) -> DeResult<Option<S::Value>>
+        where S: DeserializeSeed<'de>
+    {
+        match self.entries.next() {
+            Some((entry, (header, value))) => {
+                self.value = Some((entry, value));
+                seed.deserialize(&mut RecordDeserializer::from_field(header.clone()))
+                    .map(Some)
+                    .map_err(|err| {
+                        self.de.error_entry = Some((entry, header));
+                        err
+                    })
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<S>(&mut self, seed: S) -> DeResult<S::Value>
+        where S: DeserializeSeed<'de>
+    {
+        let (entry, value) = self.value
+            .take()
+            .ok_or_else(|| DeError("Expected a map key".into()))?;
+        seed.deserialize(&mut RecordDeserializer::from_field(value.clone())).map_err(|err| {
+            self.de.error_entry = Some((entry, value));
+            err
+        })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
@@ -1,18 +1,37 @@
+use field_naming::{FieldNaming, PathElement};
+
 use csv::{ByteString, Error, Result};
 use rustc_serialize::Encoder;
 
 /// Encoder to extract field names from types that implement
 /// `rustc_serialize::Encodable`.
+///
+/// There is one field name for each scalar value (including enums and
+/// `Option`s) in a struct field. The field name of a scalar is determined by
+/// the path of struct fields and array/tuple indices leading to it; see
+/// `FieldNaming`. A map doesn't have any field names, since its keys are
+/// written as separate columns by the writer.
 #[derive(Debug)]
-pub struct FieldNamesEncoder {
+pub struct FieldNamesEncoder<'a> {
     record: Vec<ByteString>,
+    naming: &'a FieldNaming,
+    /// Path to the current value.
+    path: Vec<PathElement>,
+    /// Whether the elements of each sequence or tuple containing the current
+    /// value are indexed, from outermost to innermost.
+    indexed: Vec<bool>,
 }
 
-impl FieldNamesEncoder {
-    /// Creates a new `FieldNamesEncoder`. The value returned can be passed to
-    /// `Encodable::encode`.
-    pub fn new() -> FieldNamesEncoder {
-        FieldNamesEncoder { record: vec![] }
+impl<'a> FieldNamesEncoder<'a> {
+    /// Creates a new `FieldNamesEncoder` that names fields according to
+    /// `naming`. The value returned can be passed to `Encodable::encode`.
+    pub fn new(naming: &'a FieldNaming) -> FieldNamesEncoder<'a> {
+        FieldNamesEncoder {
+            record: vec![],
+            naming: naming,
+            path: vec![],
+            indexed: vec![],
+        }
     }
 
     /// Once a record has been encoded into this value, `into_field_names` can
@@ -34,66 +53,101 @@ impl FieldNamesEncoder {
         self.push_bytes(s.into().into_bytes())
     }
 
-    fn push_to_string<T: ToString>(&mut self, t: T) -> Result<()> {
-        self.push_string(t.to_string())
+    /// Adds the field name for a scalar at the current path.
+    ///
+    /// Scalars that aren't in a struct field don't have a field name.
+    fn push_field_name(&mut self) -> Result<()> {
+        match self.naming.field_name(&self.path) {
+            Some(field_name) => self.push_string(field_name),
+            None => Ok(()),
+        }
+    }
+
+    /// Calls `f` for a sequence or tuple of length `len`.
+    fn emit_elements<F>(&mut self, len: usize, f: F) -> Result<()>
+        where F: FnOnce(&mut Self) -> Result<()>
+    {
+        // The elements are indexed only if there is more than one of them and
+        // they're in a struct field.
+        let indexed = len > 1 && !self.path.is_empty();
+        self.indexed.push(indexed);
+        let result = f(self);
+        self.indexed.pop();
+        result
+    }
+
+    /// Calls `f` for the element of a sequence or tuple at index `idx`.
+    fn emit_element<F>(&mut self, idx: usize, f: F) -> Result<()>
+        where F: FnOnce(&mut Self) -> Result<()>
+    {
+        if self.indexed.last() == Some(&true) {
+            self.path.push(PathElement::Index(idx));
+            let result = f(self);
+            self.path.pop();
+            result
+        } else {
+            f(self)
+        }
     }
 }
 
-impl Encoder for FieldNamesEncoder {
+impl<'a> Encoder for FieldNamesEncoder<'a> {
     type Error = Error;
 
     fn emit_nil(&mut self) -> Result<()> {
-        unimplemented!()
+        self.push_field_name()
     }
     fn emit_usize(&mut self, _: usize) -> Result<()> {
-        Ok(())
+        self.push_field_name()
     }
     fn emit_u64(&mut self, _: u64) -> Result<()> {
-        Ok(())
+        self.push_field_name()
     }
     fn emit_u32(&mut self, _: u32) -> Result<()> {
-        Ok(())
+        self.push_field_name()
     }
     fn emit_u16(&mut self, _: u16) -> Result<()> {
-        Ok(())
+        self.push_field_name()
     }
     fn emit_u8(&mut self, _: u8) -> Result<()> {
-        Ok(())
+        self.push_field_name()
     }
     fn emit_isize(&mut self, _: isize) -> Result<()> {
-        Ok(())
+        self.push_field_name()
     }
     fn emit_i64(&mut self, _: i64) -> Result<()> {
-        Ok(())
+        self.push_field_name()
     }
     fn emit_i32(&mut self, _: i32) -> Result<()> {
-        Ok(())
+        self.push_field_name()
     }
     fn emit_i16(&mut self, _: i16) -> Result<()> {
-        Ok(())
+        self.push_field_name()
     }
     fn emit_i8(&mut self, _: i8) -> Result<()> {
-        Ok(())
+        self.push_field_name()
     }
     fn emit_bool(&mut self, _: bool) -> Result<()> {
-        Ok(())
+        self.push_field_name()
     }
     fn emit_f64(&mut self, _: f64) -> Result<()> {
-        Ok(())
+        self.push_field_name()
     }
     fn emit_f32(&mut self, _: f32) -> Result<()> {
-        Ok(())
+        self.push_field_name()
     }
     fn emit_char(&mut self, _: char) -> Result<()> {
-        Ok(())
+        self.push_field_name()
     }
     fn emit_str(&mut self, _: &str) -> Result<()> {
-        Ok(())
+        self.push_field_name()
     }
     fn emit_enum<F>(&mut self, _: &str, _: F) -> Result<()>
         where F: FnOnce(&mut Self) -> Result<()>
     {
-        Ok(())
+        // An enum is a single scalar, regardless of the arguments of the
+        // variant.
+        self.push_field_name()
     }
     fn emit_enum_variant<F>(&mut self, _: &str, _: usize, _: usize, _: F) -> Result<()>
         where F: FnOnce(&mut Self) -> Result<()>
@@ -120,25 +174,28 @@ impl Encoder for FieldNamesEncoder {
     {
         f(self)
     }
-    fn emit_struct_field<F>(&mut self, f_name: &str, f_idx: usize, _: F) -> Result<()>
+    fn emit_struct_field<F>(&mut self, f_name: &str, f_idx: usize, f: F) -> Result<()>
         where F: FnOnce(&mut Self) -> Result<()>
     {
         // Heuristic to ignore field names in tuple structs.
         // See https://github.com/rust-lang/rust/issues/19756
-        if f_name != format!("_field{}", f_idx) {
-            self.push_to_string(f_name)?;
+        if f_name == format!("_field{}", f_idx) {
+            return f(self);
         }
-        Ok(())
+        self.path.push(PathElement::Field(f_name.to_owned()));
+        let result = f(self);
+        self.path.pop();
+        result
     }
-    fn emit_tuple<F>(&mut self, _: usize, f: F) -> Result<()>
+    fn emit_tuple<F>(&mut self, len: usize, f: F) -> Result<()>
         where F: FnOnce(&mut Self) -> Result<()>
     {
-        f(self)
+        self.emit_elements(len, f)
     }
-    fn emit_tuple_arg<F>(&mut self, _: usize, f: F) -> Result<()>
+    fn emit_tuple_arg<F>(&mut self, idx: usize, f: F) -> Result<()>
         where F: FnOnce(&mut Self) -> Result<()>
     {
-        f(self)
+        self.emit_element(idx, f)
     }
     fn emit_tuple_struct<F>(&mut self, _: &str, _: usize, _: F) -> Result<()>
         where F: FnOnce(&mut Self) -> Result<()>
@@ -153,7 +210,7 @@ impl Encoder for FieldNamesEncoder {
     fn emit_option<F>(&mut self, _: F) -> Result<()>
         where F: FnOnce(&mut Self) -> Result<()>
     {
-        Ok(())
+        self.push_field_name()
     }
     fn emit_option_none(&mut self) -> Result<()> {
         Ok(())
@@ -163,29 +220,29 @@ impl Encoder for FieldNamesEncoder {
     {
         Ok(())
     }
-    fn emit_seq<F>(&mut self, _: usize, f: F) -> Result<()>
+    fn emit_seq<F>(&mut self, len: usize, f: F) -> Result<()>
         where F: FnOnce(&mut Self) -> Result<()>
     {
-        f(self)
+        self.emit_elements(len, f)
     }
-    fn emit_seq_elt<F>(&mut self, _: usize, f: F) -> Result<()>
+    fn emit_seq_elt<F>(&mut self, idx: usize, f: F) -> Result<()>
         where F: FnOnce(&mut Self) -> Result<()>
     {
-        f(self)
+        self.emit_element(idx, f)
     }
     fn emit_map<F>(&mut self, _: usize, _: F) -> Result<()>
         where F: FnOnce(&mut Self) -> Result<()>
     {
-        unimplemented!()
+        Ok(())
     }
     fn emit_map_elt_key<F>(&mut self, _: usize, _: F) -> Result<()>
         where F: FnOnce(&mut Self) -> Result<()>
     {
-        unimplemented!()
+        Ok(())
     }
     fn emit_map_elt_val<F>(&mut self, _: usize, _: F) -> Result<()>
         where F: FnOnce(&mut Self) -> Result<()>
     {
-        unimplemented!()
+        Ok(())
     }
 }
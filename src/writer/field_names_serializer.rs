@@ -0,0 +1,271 @@
+use super::record_serializer::{SerError, SerResult};
+use field_naming::{FieldNaming, PathElement};
+
+use csv::ByteString;
+use serde::ser::{self, Impossible, Serialize, SerializeMap, SerializeSeq, SerializeStruct,
+                 SerializeTuple, SerializeTupleStruct, Serializer};
+
+/// Serializer to extract field names from types that implement
+/// `serde::Serialize`.
+///
+/// Like `FieldNamesEncoder`, the field name of a scalar is determined by the
+/// path of struct fields and array/tuple indices leading to it; see
+/// `FieldNaming`. A map doesn't have any field names, since its keys are
+/// written as separate columns by the writer.
+#[derive(Debug)]
+pub struct FieldNamesSerializer<'a> {
+    record: Vec<ByteString>,
+    naming: &'a FieldNaming,
+    /// Path to the current value.
+    path: Vec<PathElement>,
+    /// For each sequence or tuple containing the current value, from
+    /// outermost to innermost, whether its elements are indexed and the index
+    /// of the next element.
+    elements: Vec<(bool, usize)>,
+}
+
+impl<'a> FieldNamesSerializer<'a> {
+    /// Creates a new `FieldNamesSerializer` that names fields according to
+    /// `naming`. The value returned can be passed to `Serialize::serialize`.
+    pub fn new(naming: &'a FieldNaming) -> FieldNamesSerializer<'a> {
+        FieldNamesSerializer {
+            record: vec![],
+            naming: naming,
+            path: vec![],
+            elements: vec![],
+        }
+    }
+
+    /// Once a record has been serialized into this value, `into_field_names`
+    /// can be used to access the raw field names.
+    pub fn into_field_names(self) -> Vec<ByteString> {
+        self.record
+    }
+
+    /// Adds the field name for a scalar at the current path.
+    ///
+    /// Scalars that aren't in a struct field don't have a field name.
+    fn push_field_name(&mut self) -> SerResult<()> {
+        if let Some(field_name) = self.naming.field_name(&self.path) {
+            self.record.push(field_name.into_bytes());
+        }
+        Ok(())
+    }
+
+    /// Starts a sequence or tuple that may have more than one element.
+    fn start_elements(&mut self, many: bool) {
+        // The elements are indexed only if there is more than one of them and
+        // they're in a struct field.
+        let indexed = many && !self.path.is_empty();
+        self.elements.push((indexed, 0));
+    }
+
+    /// Serializes the next element of the innermost sequence or tuple.
+    fn serialize_next_element<T: ?Sized + Serialize>(&mut self, value: &T) -> SerResult<()> {
+        let (indexed, index) = self.elements.pop().expect("no open sequence or tuple");
+        self.elements.push((indexed, index + 1));
+        if indexed {
+            self.path.push(PathElement::Index(index));
+            let result = value.serialize(&mut *self);
+            self.path.pop();
+            result
+        } else {
+            value.serialize(self)
+        }
+    }
+
+    fn end_elements(&mut self) -> SerResult<()> {
+        self.elements.pop();
+        Ok(())
+    }
+}
+
+macro_rules! serialize_scalar {
+    ($($method:ident($ty:ty),)*) => {
+        $(
+            fn $method(self, _: $ty) -> SerResult<()> {
+                self.push_field_name()
+            }
+        )*
+    }
+}
+
+impl<'a, 'b> Serializer for &'a mut FieldNamesSerializer<'b> {
+    type Ok = ();
+    type Error = SerError;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Impossible<(), SerError>;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Impossible<(), SerError>;
+
+    serialize_scalar! {
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+        serialize_bytes(&[u8]),
+        serialize_unit_struct(&'static str),
+    }
+
+    fn serialize_none(self) -> SerResult<()> {
+        self.push_field_name()
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, _: &T) -> SerResult<()> {
+        self.push_field_name()
+    }
+
+    fn serialize_unit(self) -> SerResult<()> {
+        self.push_field_name()
+    }
+
+    fn serialize_unit_variant(self, _: &'static str, _: u32, _: &'static str) -> SerResult<()> {
+        self.push_field_name()
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(self,
+                                                       _: &'static str,
+                                                       value: &T)
+                                                       -> SerResult<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(self,
+                                                        _: &'static str,
+                                                        _: u32,
+                                                        _: &'static str,
+                                                        _: &T)
+                                                        -> SerResult<()> {
+        // An enum is a single scalar, regardless of the contents of the
+        // variant.
+        self.push_field_name()
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> SerResult<Self> {
+        self.start_elements(len.map_or(true, |len| len > 1));
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, len: usize) -> SerResult<Self> {
+        self.start_elements(len > 1);
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _: &'static str, len: usize) -> SerResult<Self> {
+        self.start_elements(len > 1);
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(self,
+                               _: &'static str,
+                               _: u32,
+                               _: &'static str,
+                               _: usize)
+                               -> SerResult<Impossible<(), SerError>> {
+        Err(ser::Error::custom("Enum variants with more than one value are not supported"))
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> SerResult<Self> {
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> SerResult<Self> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(self,
+                                _: &'static str,
+                                _: u32,
+                                _: &'static str,
+                                _: usize)
+                                -> SerResult<Impossible<(), SerError>> {
+        Err(ser::Error::custom("Enum variants with more than one value are not supported"))
+    }
+}
+
+impl<'a, 'b> SerializeSeq for &'a mut FieldNamesSerializer<'b> {
+    type Ok = ();
+    type Error = SerError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> SerResult<()> {
+        self.serialize_next_element(value)
+    }
+
+    fn end(self) -> SerResult<()> {
+        self.end_elements()
+    }
+}
+
+impl<'a, 'b> SerializeTuple for &'a mut FieldNamesSerializer<'b> {
+    type Ok = ();
+    type Error = SerError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> SerResult<()> {
+        self.serialize_next_element(value)
+    }
+
+    fn end(self) -> SerResult<()> {
+        self.end_elements()
+    }
+}
+
+impl<'a, 'b> SerializeTupleStruct for &'a mut FieldNamesSerializer<'b> {
+    type Ok = ();
+    type Error = SerError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> SerResult<()> {
+        self.serialize_next_element(value)
+    }
+
+    fn end(self) -> SerResult<()> {
+        self.end_elements()
+    }
+}
+
+impl<'a, 'b> SerializeMap for &'a mut FieldNamesSerializer<'b> {
+    type Ok = ();
+    type Error = SerError;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, _: &T) -> SerResult<()> {
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, _: &T) -> SerResult<()> {
+        Ok(())
+    }
+
+    fn end(self) -> SerResult<()> {
+        Ok(())
+    }
+}
+
+impl<'a, 'b> SerializeStruct for &'a mut FieldNamesSerializer<'b> {
+    type Ok = ();
+    type Error = SerError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self,
+                                              key: &'static str,
+                                              value: &T)
+                                              -> SerResult<()> {
+        self.path.push(PathElement::Field(key.to_owned()));
+        let result = value.serialize(&mut **self);
+        self.path.pop();
+        result
+    }
+
+    fn end(self) -> SerResult<()> {
+        Ok(())
+    }
+}
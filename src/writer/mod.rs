@@ -1,13 +1,39 @@
 mod field_names_encoder;
+#[cfg(feature = "serde")]
+mod field_names_serializer;
+mod record_encoder;
+#[cfg(feature = "serde")]
+mod record_serializer;
 
 use self::field_names_encoder::FieldNamesEncoder;
+#[cfg(feature = "serde")]
+use self::field_names_serializer::FieldNamesSerializer;
+use self::record_encoder::RecordEncoder;
+#[cfg(feature = "serde")]
+use self::record_serializer::RecordSerializer;
 
-use csv::{self, Result};
-use rustc_serialize::Encodable;
-use std::fs::File;
-use std::io::{BufWriter, Write};
+use compression::{self, CompressingWriter, Compression, detect_compression};
+use csv::{self, ByteString, QuoteStyle, RecordTerminator};
+use dialect::Dialect;
+use encoding::{Encoding, EncodingWriter};
+use error::{Error, HeaderMismatch, MissingField, Result, UnexpectedHeader};
+use field_naming::FieldNaming;
+use normalization::Normalization;
+use reader::{ColumnMapping, ColumnTarget, Reader, decodable_field_names, map_headers};
+#[cfg(feature = "serde")]
+use reader::deserializable_field_names;
+use rustc_serialize::{Decodable, Encodable};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+#[cfg(feature = "serde")]
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufWriter, Read, Seek, SeekFrom, Write};
 use std::marker::PhantomData;
+use std::mem;
 use std::path::Path;
+use std::sync::Arc;
 
 /// A CSV writer that automatically writes the headers.
 ///
@@ -15,7 +41,8 @@ use std::path::Path;
 /// creating CSV data is much easier than parsing it, having a writer can be
 /// convenient because it can handle quoting for you automatically. Moreover,
 /// this particular writer supports [`rustc_serialize::Encodable`][Encodable]
-/// types, which makes it easy to write your custom types as CSV records and
+/// types (and `serde::Serialize` types if the `serde` feature is enabled),
+/// which makes it easy to write your custom types as CSV records and
 /// automatically generate headers.
 ///
 /// All CSV data produced by this writer, with default options, conforms with
@@ -68,23 +95,280 @@ use std::path::Path;
 /// ```
 ///
 /// [Encodable]: https://doc.rust-lang.org/rustc-serialize/rustc_serialize/trait.Encodable.html
-pub struct Writer<W: Write, E: Encodable> {
-    csv: csv::Writer<W>,
+pub struct Writer<W: Write, E> {
+    /// The underlying CSV writer, which is only missing while one of its
+    /// builder methods is applied or once it has been taken out.
+    csv: Option<csv::Writer<W>>,
+    has_headers: bool,
     first_row: bool,
+    field_naming: FieldNaming,
+    /// Keys of the catch-all map, in the order of the headers.
+    catch_all_keys: Vec<ByteString>,
+    /// The columns of the header row that is already in the output, as
+    /// matched to the fields by a `Reader`, when appending to a file.
+    existing_columns: Option<ColumnMapping>,
+    /// The headers of the columns to write, if not all of the columns in the
+    /// order of the fields.
+    column_order: Option<Vec<ByteString>>,
+    /// Index of the field to write in each column (or `None` for an empty
+    /// column), determined from `column_order` or `existing_columns` when the
+    /// first row is written.
+    column_indices: Option<Vec<Option<usize>>>,
+    /// Annotations to write in a second header row, by field name.
+    field_annotations: HashMap<ByteString, ByteString>,
+    /// Writes the header row from the field names of the record type, if it's
+    /// still pending when the writer is flushed or dropped.
+    pending_headers: Option<fn(&mut Writer<W, E>) -> Result<()>>,
     record_type: PhantomData<E>,
 }
 
-impl<E: Encodable> Writer<File, E> {
-    /// Creates a new typed CSV writer that writes to the file path given.
+impl<E> Writer<CompressingWriter<File>, E> {
+    /// Creates a new typed CSV writer that writes to the file path given,
+    /// which is compressed while it's written if it's a compressed file.
     ///
     /// The file is created if it does not already exist and is truncated
-    /// otherwise.
-    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Writer<File, E>> {
-        Ok(Self::from_csv_writer(csv::Writer::from_file(path)?))
+    /// otherwise. The compression format is determined from the extension of
+    /// the path (`gz`, `zst`, or `bz2`) or, if that isn't one of those, from
+    /// the first bytes of the existing file. If the cargo feature of the
+    /// format isn't enabled, the error is an I/O error, and the file is left
+    /// as it is. See [`Compression`](enum.Compression.html). Call
+    /// [`finish`](#method.finish) after writing the last record to find out
+    /// about errors writing the end of the compressed data.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// #[derive(RustcEncodable)]
+    /// struct Record {
+    ///     count: usize,
+    ///     animal: &'static str,
+    /// }
+    ///
+    /// // With the `zstd` feature, this file is compressed.
+    /// let mut wtr = typed_csv::Writer::from_file("zoo.csv.zst").unwrap();
+    /// wtr.encode(Record { count: 7, animal: "penguin" }).unwrap();
+    /// wtr.finish().unwrap();
+    /// # }
+    /// ```
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Writer<CompressingWriter<File>, E>> {
+        if let Some(compression) = Compression::from_extension(&path) {
+            return Self::from_file_with_compression(path, compression);
+        }
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(&path)
+            .map_err(csv::Error::Io)?;
+        let compression = detect_compression(path.as_ref(), &mut file).map_err(csv::Error::Io)?;
+        // Don't truncate the file if the data can't be compressed.
+        compression::check_enabled(compression).map_err(csv::Error::Io)?;
+        file.set_len(0).map_err(csv::Error::Io)?;
+        Self::from_writer_with_compression(file, compression)
+    }
+
+    /// Creates a new typed CSV writer that writes to the file path given, in
+    /// the given compression format, regardless of the extension of the path.
+    pub fn from_file_with_compression<P>(path: P,
+                                         compression: Compression)
+                                         -> Result<Writer<CompressingWriter<File>, E>>
+        where P: AsRef<Path>
+    {
+        // Don't truncate the file if the data can't be compressed.
+        compression::check_enabled(compression).map_err(csv::Error::Io)?;
+        let file = File::create(path).map_err(csv::Error::Io)?;
+        Self::from_writer_with_compression(file, compression)
     }
 }
 
-impl<W: Write, E: Encodable> Writer<W, E> {
+impl<E: Decodable> Writer<File, E> {
+    /// Creates a new typed CSV writer that appends to the file path given.
+    ///
+    /// The file is created if it does not already exist. If the file isn't
+    /// empty, its header row is read and checked against the field names of
+    /// the record type by a [`Reader`](struct.Reader.html), the same way as
+    /// [`Reader::check_headers`](struct.Reader.html#method.check_headers), but
+    /// with the columns allowed in any order. If the headers don't match, the
+    /// error is an `Error::HeaderMismatch` describing each problem. Otherwise,
+    /// no header row is written, and each field is written in the column of
+    /// its header. If the record type has a catch-all map, its keys are the
+    /// headers of the columns collected into it.
+    ///
+    /// The header row is read with the default dialect. Use
+    /// [`append_to_file_with`](#method.append_to_file_with) to use another
+    /// dialect or other options for matching the headers.
+    ///
+    /// A UTF-8 byte order mark at the start of the file is ignored. If the
+    /// last line of the file doesn't end with a record terminator, one is
+    /// written first, the same as the one at the end of the header row.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// #[derive(RustcEncodable, RustcDecodable)]
+    /// struct Record {
+    ///     count: usize,
+    ///     animal: String,
+    /// }
+    ///
+    /// let mut wtr = typed_csv::Writer::append_to_file("zoo.csv").unwrap();
+    /// wtr.encode(Record { count: 7, animal: "penguin".to_string() }).unwrap();
+    /// # }
+    /// ```
+    pub fn append_to_file<P: AsRef<Path>>(path: P) -> Result<Writer<File, E>> {
+        append_to_file(path, None, |rdr| rdr, |rdr| rdr.decode::<E>().column_mapping())
+    }
+
+    /// Creates a new typed CSV writer that appends to the file path given,
+    /// using `dialect` for both the existing data and the new records.
+    ///
+    /// This is like [`append_to_file`](#method.append_to_file), except that
+    /// the `Reader` that checks the header row is passed to `configure`
+    /// first. This lets you use the same options for matching headers as
+    /// when reading the file, such as
+    /// [`field_aliases`](struct.Reader.html#method.field_aliases),
+    /// [`headers_match_by`](struct.Reader.html#method.headers_match_by) or
+    /// [`normalize_headers`](struct.Reader.html#method.normalize_headers).
+    /// The `Reader` allows the columns in any order unless `configure`
+    /// disables [`reorder_columns`](struct.Reader.html#method.reorder_columns).
+    /// Fields that the `Reader` allows to not have a column aren't written.
+    ///
+    /// The writer uses the field names of the `Reader`, so options that
+    /// change them, such as `nested_field_separator`, must be set on the
+    /// `Reader` in `configure` and on the returned writer.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    /// use typed_csv::{Dialect, Normalization};
+    ///
+    /// #[derive(RustcEncodable, RustcDecodable)]
+    /// struct Record {
+    ///     animal_count: usize,
+    ///     animal: String,
+    /// }
+    ///
+    /// // The header row of the file is "Animal\tAnimal Count".
+    /// let mut wtr = typed_csv::Writer::append_to_file_with("zoo.tsv", Dialect::tsv(), |rdr| {
+    ///         rdr.normalize_headers(Normalization::TRIM | Normalization::WORD_CASE)
+    ///     })
+    ///     .unwrap();
+    /// wtr.encode(Record { animal_count: 7, animal: "penguin".to_string() }).unwrap();
+    /// # }
+    /// ```
+    pub fn append_to_file_with<'a, P, Q, F>(path: P,
+                                            dialect: Dialect,
+                                            configure: F)
+                                            -> Result<Writer<File, E>>
+        where P: AsRef<Path>,
+              Q: ?Sized + Fn(&[u8], &[u8]) -> bool + 'a,
+              F: FnOnce(Reader<'static, File>) -> Reader<'a, File, Q>
+    {
+        append_to_file(path,
+                       Some(dialect),
+                       configure,
+                       |rdr| rdr.decode::<E>().column_mapping())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<E: DeserializeOwned> Writer<File, E> {
+    /// Creates a new typed CSV writer that appends to the file path given.
+    ///
+    /// This is the equivalent of [`append_to_file`](#method.append_to_file)
+    /// for types that implement `serde::Serialize` and `serde::Deserialize`.
+    pub fn append_to_file_serialize<P: AsRef<Path>>(path: P) -> Result<Writer<File, E>> {
+        append_to_file(path, None, |rdr| rdr, |rdr| rdr.deserialize::<E>().column_mapping())
+    }
+
+    /// Creates a new typed CSV writer that appends to the file path given,
+    /// using `dialect` for both the existing data and the new records.
+    ///
+    /// This is the equivalent of
+    /// [`append_to_file_with`](#method.append_to_file_with) for types that
+    /// implement `serde::Serialize` and `serde::Deserialize`.
+    pub fn append_to_file_serialize_with<'a, P, Q, F>(path: P,
+                                                      dialect: Dialect,
+                                                      configure: F)
+                                                      -> Result<Writer<File, E>>
+        where P: AsRef<Path>,
+              Q: ?Sized + Fn(&[u8], &[u8]) -> bool + 'a,
+              F: FnOnce(Reader<'static, File>) -> Reader<'a, File, Q>
+    {
+        append_to_file(path,
+                       Some(dialect),
+                       configure,
+                       |rdr| rdr.deserialize::<E>().column_mapping())
+    }
+}
+
+/// Opens a file for appending, and matches its header row to the fields of
+/// the record type with the `Reader` returned by `configure`. The mapping of
+/// the columns to the fields is returned by `column_mapping`.
+fn append_to_file<'a, P, Q, F, M, E>(path: P,
+                                     dialect: Option<Dialect>,
+                                     configure: F,
+                                     column_mapping: M)
+                                     -> Result<Writer<File, E>>
+    where P: AsRef<Path>,
+          Q: ?Sized + Fn(&[u8], &[u8]) -> bool + 'a,
+          F: FnOnce(Reader<'static, File>) -> Reader<'a, File, Q>,
+          M: FnOnce(Reader<'a, File, Q>) -> Result<ColumnMapping>
+{
+    let mut file = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(path)
+        .map_err(csv::Error::Io)?;
+    let rdr = Reader::from_reader(file.try_clone().map_err(csv::Error::Io)?).reorder_columns(true);
+    let rdr = match dialect {
+        Some(dialect) => rdr.dialect(dialect),
+        None => rdr,
+    };
+    let mapping = column_mapping(configure(rdr))?;
+    if !mapping.columns.is_empty() {
+        let terminator = missing_terminator(&mut file).map_err(csv::Error::Io)?;
+        file.write_all(terminator).map_err(csv::Error::Io)?;
+    }
+    let mut wtr = Writer::from_writer(file);
+    if let Some(dialect) = dialect {
+        wtr = wtr.dialect(dialect);
+    }
+    if !mapping.columns.is_empty() {
+        wtr.existing_columns = Some(mapping);
+    }
+    Ok(wtr)
+}
+
+/// Returns the record terminator that is missing at the end of a file, which
+/// is empty if the last line of the file ends with one. The terminator is
+/// `\r\n` if the first line of the file ends with it, and `\n` otherwise.
+fn missing_terminator(file: &mut File) -> io::Result<&'static [u8]> {
+    let mut last = [0];
+    file.seek(SeekFrom::End(-1))?;
+    file.read_exact(&mut last)?;
+    if last[0] == b'\n' || last[0] == b'\r' {
+        return Ok(b"");
+    }
+    file.seek(SeekFrom::Start(0))?;
+    let mut first_line = Vec::new();
+    io::BufReader::new(&*file).read_until(b'\n', &mut first_line)?;
+    Ok(if first_line.ends_with(b"\r\n") { b"\r\n" } else { b"\n" })
+}
+
+impl<W: Write, E> Writer<W, E> {
     /// Creates a new typed CSV writer that writes to the `io::Write` given.
     ///
     /// Note that the writer is buffered for you automatically.
@@ -98,8 +382,16 @@ impl<W: Write, E: Encodable> Writer<W, E> {
     /// a different delimiter).
     pub fn from_csv_writer(w: csv::Writer<W>) -> Writer<W, E> {
         Writer {
-            csv: w,
+            csv: Some(w),
+            has_headers: true,
             first_row: true,
+            field_naming: FieldNaming::new(),
+            catch_all_keys: Vec::new(),
+            existing_columns: None,
+            column_order: None,
+            column_indices: None,
+            field_annotations: HashMap::new(),
+            pending_headers: None,
             record_type: PhantomData,
         }
     }
@@ -114,7 +406,95 @@ impl<W: Write, E: Encodable> Writer<W, E> {
     }
 }
 
-impl<E: Encodable> Writer<Vec<u8>, E> {
+impl<W: Write, E> Writer<EncodingWriter<W>, E> {
+    /// Creates a new typed CSV writer that writes to the `io::Write` given,
+    /// in the given encoding.
+    ///
+    /// If `bom` is `true`, a byte order mark is written at the start of the
+    /// data, unless the encoding is Latin-1 or Windows-1252. A field that
+    /// contains a character that can't be represented in the encoding is an
+    /// I/O error. See [`EncodingWriter`](struct.EncodingWriter.html).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    /// use typed_csv::Encoding;
+    ///
+    /// #[derive(RustcEncodable)]
+    /// struct Record {
+    ///     count: usize,
+    ///     animal: &'static str,
+    /// }
+    ///
+    /// let mut data = Vec::new();
+    /// {
+    ///     let mut wtr = typed_csv::Writer::from_writer_with_encoding(&mut data,
+    ///                                                                Encoding::Utf8,
+    ///                                                                true);
+    ///     wtr.encode(Record { count: 7, animal: "penguin" }).unwrap();
+    ///     wtr.flush().unwrap();
+    /// }
+    ///
+    /// assert_eq!(data, b"\xef\xbb\xbfcount,animal\n7,penguin\n");
+    /// # }
+    /// ```
+    pub fn from_writer_with_encoding(w: W,
+                                     encoding: Encoding,
+                                     bom: bool)
+                                     -> Writer<EncodingWriter<W>, E> {
+        Self::from_writer(EncodingWriter::new(w, encoding, bom))
+    }
+}
+
+impl<W: Write, E> Writer<CompressingWriter<W>, E> {
+    /// Creates a new typed CSV writer that writes to the `io::Write` given,
+    /// compressed in the given format.
+    ///
+    /// See [`Compression`](enum.Compression.html) for the formats that are
+    /// available.
+    pub fn from_writer_with_compression(w: W,
+                                        compression: Compression)
+                                        -> Result<Writer<CompressingWriter<W>, E>> {
+        let wtr = CompressingWriter::new(w, compression).map_err(csv::Error::Io)?;
+        Ok(Self::from_writer(wtr))
+    }
+
+    /// Flushes the writer, writes the end of the compressed data, and returns
+    /// the underlying writer.
+    ///
+    /// Otherwise, the compressed data is finished when the writer is dropped,
+    /// and errors that occur at that point are ignored. If
+    /// `always_write_headers` is enabled and no record has been written, the
+    /// header row is written first.
+    pub fn finish(mut self) -> Result<W> {
+        self.flush()?;
+        let wtr = self.csv.take().expect("the CSV writer has been taken out").into_inner();
+        Ok(wtr.finish().map_err(csv::Error::Io)?)
+    }
+}
+
+impl<E> Writer<EncodingWriter<File>, E> {
+    /// Creates a new typed CSV writer that writes to the file path given, in
+    /// the given encoding.
+    ///
+    /// The file is created if it does not already exist and is truncated
+    /// otherwise. See
+    /// [`from_writer_with_encoding`](#method.from_writer_with_encoding).
+    pub fn from_file_with_encoding<P>(path: P,
+                                      encoding: Encoding,
+                                      bom: bool)
+                                      -> Result<Writer<EncodingWriter<File>, E>>
+        where P: AsRef<Path>
+    {
+        let file = File::create(path).map_err(csv::Error::Io)?;
+        Ok(Self::from_writer_with_encoding(file, encoding, bom))
+    }
+}
+
+impl<E> Writer<Vec<u8>, E> {
     /// Creates a new CSV writer that writes to an in memory buffer. At any
     /// time, `as_string` or `as_bytes` can be called to retrieve the
     /// cumulative CSV data.
@@ -124,22 +504,387 @@ impl<E: Encodable> Writer<Vec<u8>, E> {
 
     /// Returns the written CSV data as a string.
     pub fn as_string(&mut self) -> &str {
-        self.csv.as_string()
+        self.csv().as_string()
     }
 
     /// Returns the encoded CSV data as raw bytes.
     pub fn as_bytes(&mut self) -> &[u8] {
-        self.csv.as_bytes()
+        self.csv().as_bytes()
     }
 
     /// Convert the Writer into a string of written CSV data
-    pub fn into_string(self) -> String {
-        self.csv.into_string()
+    ///
+    /// If `always_write_headers` is enabled and no record has been written,
+    /// the header row is written first. An error writing it is ignored, so
+    /// call `flush` first to find out about it.
+    pub fn into_string(mut self) -> String {
+        let _ = self.write_pending_headers();
+        self.csv.take().unwrap().into_string()
     }
 
     /// Convert the Writer into a vector of encoded CSV bytes.
-    pub fn into_bytes(self) -> Vec<u8> {
-        self.csv.into_bytes()
+    ///
+    /// Like `into_string`, this ignores an error writing the pending header
+    /// row.
+    pub fn into_bytes(mut self) -> Vec<u8> {
+        let _ = self.write_pending_headers();
+        self.csv.take().unwrap().into_bytes()
+    }
+}
+
+impl<W: Write, E> Writer<W, E> {
+    /// Set the separator used to join the field names of nested structs.
+    ///
+    /// A struct field that is itself a struct is flattened into one column
+    /// per field of the inner struct. The header of each of those columns is
+    /// the name of the outer field and the name of the inner field, joined
+    /// with this separator.
+    ///
+    /// The default value is `"."`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// #[derive(RustcEncodable)]
+    /// struct Point {
+    ///     x: i32,
+    ///     y: i32,
+    /// }
+    ///
+    /// #[derive(RustcEncodable)]
+    /// struct Record {
+    ///     name: &'static str,
+    ///     position: Point,
+    /// }
+    ///
+    /// let mut wtr = typed_csv::Writer::from_memory().nested_field_separator("_");
+    /// wtr.encode(Record { name: "origin", position: Point { x: 0, y: 0 } }).unwrap();
+    /// wtr.encode(Record { name: "corner", position: Point { x: 3, y: -4 } }).unwrap();
+    ///
+    /// assert_eq!(wtr.as_string(), "\
+    /// name,position_x,position_y
+    /// origin,0,0
+    /// corner,3,-4
+    /// ");
+    /// # }
+    /// ```
+    pub fn nested_field_separator<S: Into<String>>(mut self, separator: S) -> Writer<W, E> {
+        self.field_naming.set_separator(separator.into());
+        self
+    }
+
+    /// Set the pattern used to name the elements of arrays and tuples.
+    ///
+    /// A struct field that is an array, `Vec`, or tuple with more than one
+    /// element is expanded into one column per element. The header of each of
+    /// those columns is this pattern, with `{name}` replaced by the field name
+    /// and `{index}` replaced by the index of the element (starting at `0`).
+    ///
+    /// The default value is `"{name}_{index}"`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// #[derive(RustcEncodable)]
+    /// struct Record {
+    ///     name: &'static str,
+    ///     position: [i32; 2],
+    ///     size: (u32, u32),
+    /// }
+    ///
+    /// let mut wtr = typed_csv::Writer::from_memory().indexed_field_pattern("{name}[{index}]");
+    /// wtr.encode(Record { name: "square", position: [3, -4], size: (10, 10) }).unwrap();
+    ///
+    /// assert_eq!(wtr.as_string(), "\
+    /// name,position[0],position[1],size[0],size[1]
+    /// square,3,-4,10,10
+    /// ");
+    /// # }
+    /// ```
+    pub fn indexed_field_pattern<S: Into<String>>(mut self, pattern: S) -> Writer<W, E> {
+        self.field_naming.set_indexed_pattern(pattern.into());
+        self
+    }
+
+    /// Write the given header instead of the field name `field_name`.
+    ///
+    /// The field name is the name after flattening nested structs and
+    /// expanding arrays and tuples, e.g. `position_0` or `size.width`. This
+    /// takes precedence over [`rename_fields_with`](#method.rename_fields_with).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// #[derive(RustcEncodable)]
+    /// struct Record {
+    ///     count: usize,
+    ///     animal: &'static str,
+    /// }
+    ///
+    /// let mut wtr = typed_csv::Writer::from_memory().rename_field("count", "Animal Count");
+    /// wtr.encode(Record { count: 7, animal: "penguin" }).unwrap();
+    ///
+    /// assert_eq!(wtr.as_string(), "\
+    /// Animal Count,animal
+    /// 7,penguin
+    /// ");
+    /// # }
+    /// ```
+    pub fn rename_field<S, T>(mut self, field_name: S, header: T) -> Writer<W, E>
+        where S: Into<String>,
+              T: Into<String>
+    {
+        self.field_naming.rename(field_name.into(), header.into());
+        self
+    }
+
+    /// Write the header returned by `rename` instead of each field name.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// #[derive(RustcEncodable)]
+    /// struct Record {
+    ///     count: usize,
+    ///     animal: &'static str,
+    /// }
+    ///
+    /// let mut wtr = typed_csv::Writer::from_memory()
+    ///     .rename_fields_with(|field_name| field_name.to_uppercase());
+    /// wtr.encode(Record { count: 7, animal: "penguin" }).unwrap();
+    ///
+    /// assert_eq!(wtr.as_string(), "\
+    /// COUNT,ANIMAL
+    /// 7,penguin
+    /// ");
+    /// # }
+    /// ```
+    pub fn rename_fields_with<F>(mut self, rename: F) -> Writer<W, E>
+        where F: Fn(&str) -> String + Send + Sync + 'static
+    {
+        self.field_naming.set_rename_fn(Arc::new(rename));
+        self
+    }
+
+    /// Write `annotation` under the header of the field `field_name`, in a
+    /// second header row.
+    ///
+    /// The second header row is only written if at least one field has an
+    /// annotation, and the columns without an annotation are empty in it.
+    /// This is useful for units or descriptions of the columns. The field name
+    /// is the name after flattening nested structs and expanding arrays and
+    /// tuples, as for [`rename_field`](#method.rename_field), or a key of the
+    /// catch-all map. To read the CSV data back, use
+    /// [`Reader::header_rows`](struct.Reader.html#method.header_rows).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// #[derive(RustcEncodable)]
+    /// struct Record {
+    ///     time: f64,
+    ///     speed: f64,
+    ///     note: &'static str,
+    /// }
+    ///
+    /// let mut wtr = typed_csv::Writer::from_memory()
+    ///     .field_annotation("time", "s")
+    ///     .field_annotation("speed", "m/s");
+    /// wtr.encode(Record { time: 0.5, speed: 4.25, note: "gust" }).unwrap();
+    ///
+    /// assert_eq!(wtr.as_string(), "\
+    /// time,speed,note
+    /// s,m/s,
+    /// 0.5,4.25,gust
+    /// ");
+    /// # }
+    /// ```
+    pub fn field_annotation<S, T>(mut self, field_name: S, annotation: T) -> Writer<W, E>
+        where S: Into<String>,
+              T: Into<String>
+    {
+        self.field_annotations
+            .insert(field_name.into().into_bytes(), annotation.into().into_bytes());
+        self
+    }
+
+    /// Whether to write a header row.
+    ///
+    /// By default, the header row is written before the first record. When
+    /// this is disabled, only the records are written. The columns are still
+    /// in the order of the fields, or of
+    /// [`column_order`](#method.column_order) if it's given.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// #[derive(RustcEncodable)]
+    /// struct Record {
+    ///     count: usize,
+    ///     animal: &'static str,
+    /// }
+    ///
+    /// let mut wtr = typed_csv::Writer::from_memory().has_headers(false);
+    /// wtr.encode(Record { count: 7, animal: "penguin" }).unwrap();
+    ///
+    /// assert_eq!(wtr.as_string(), "7,penguin\n");
+    /// # }
+    /// ```
+    pub fn has_headers(mut self, yes: bool) -> Writer<W, E> {
+        self.has_headers = yes;
+        self
+    }
+
+    /// Write only the columns with the given headers, in the given order.
+    ///
+    /// The headers are the renamed field names (and the keys of the catch-all
+    /// map, if the record type has one). Fields whose header isn't given
+    /// aren't written. If a given header doesn't belong to any field, the
+    /// first record isn't written, and the error is an
+    /// `Error::HeaderMismatch` describing each problem.
+    ///
+    /// This is ignored when appending to a file that already has a header
+    /// row, which determines the columns instead.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// #[derive(RustcEncodable)]
+    /// struct Record {
+    ///     count: usize,
+    ///     animal: &'static str,
+    ///     description: &'static str,
+    /// }
+    ///
+    /// let mut wtr = typed_csv::Writer::from_memory().column_order(vec!["animal", "count"]);
+    /// wtr.encode(Record { count: 7, animal: "penguin", description: "happy" }).unwrap();
+    ///
+    /// assert_eq!(wtr.as_string(), "\
+    /// animal,count
+    /// penguin,7
+    /// ");
+    /// # }
+    /// ```
+    pub fn column_order<I, S>(mut self, headers: I) -> Writer<W, E>
+        where I: IntoIterator<Item = S>,
+              S: Into<String>
+    {
+        let headers = headers.into_iter().map(|header| header.into().into_bytes()).collect();
+        self.column_order = Some(headers);
+        self
+    }
+
+    /// The delimiter to use when writing CSV data.
+    ///
+    /// Since the CSV writer is meant to be mostly encoding agnostic, you must
+    /// specify the delimiter as a single ASCII byte. For example, to write
+    /// tab-delimited data, you would use `b'\t'`.
+    ///
+    /// The default value is `b','`.
+    pub fn delimiter(self, delimiter: u8) -> Writer<W, E> {
+        self.map_csv(|csv| csv.delimiter(delimiter))
+    }
+
+    /// Set the record terminator to use when writing CSV data.
+    ///
+    /// The default value is `RecordTerminator::Any(b'\n')`. Use
+    /// `RecordTerminator::CRLF` to write `\r\n` after each record.
+    pub fn record_terminator(self, term: RecordTerminator) -> Writer<W, E> {
+        self.map_csv(|csv| csv.record_terminator(term))
+    }
+
+    /// Set the quoting style to use when writing CSV data.
+    ///
+    /// The default value is `QuoteStyle::Necessary`, which only quotes fields
+    /// that contain the delimiter, the quote character, or a record
+    /// terminator.
+    pub fn quote_style(self, style: QuoteStyle) -> Writer<W, E> {
+        self.map_csv(|csv| csv.quote_style(style))
+    }
+
+    /// Set the quote character to use when writing CSV data.
+    ///
+    /// Since the CSV writer is meant to be mostly encoding agnostic, you must
+    /// specify the quote as a single ASCII byte. For example, to write
+    /// single quoted data, you would use `b'\''`.
+    ///
+    /// The default value is `b'"'`.
+    pub fn quote(self, quote: u8) -> Writer<W, E> {
+        self.map_csv(|csv| csv.quote(quote))
+    }
+
+    /// Set the escape character to use when writing CSV data.
+    ///
+    /// This is only used when `double_quote` is disabled. Since the CSV writer
+    /// is meant to be mostly encoding agnostic, you must specify the escape
+    /// as a single ASCII byte.
+    ///
+    /// The default value is `b'\\'`.
+    pub fn escape(self, escape: u8) -> Writer<W, E> {
+        self.map_csv(|csv| csv.escape(escape))
+    }
+
+    /// Enable double quote escapes.
+    ///
+    /// When enabled (which is the default), quotes in fields are escaped by
+    /// doubling them, e.g. `"` is written as `""`. When disabled, quotes are
+    /// escaped with the escape character.
+    pub fn double_quote(self, yes: bool) -> Writer<W, E> {
+        self.map_csv(|csv| csv.double_quote(yes))
+    }
+
+    /// Whether to allow records with different lengths when writing CSV data.
+    ///
+    /// By default, this is disabled, and writing a record with a different
+    /// length than the header row is an error. Every record of the same type
+    /// has the same length, unless it contains a `Vec` of varying length.
+    pub fn flexible(self, yes: bool) -> Writer<W, E> {
+        self.map_csv(|csv| csv.flexible(yes))
+    }
+
+    /// Set the delimiter, quote, escape, record terminator, and quoting style
+    /// options from a `Dialect`.
+    ///
+    /// If the dialect doesn't have an escape character, the escape character
+    /// of the writer is left as it is.
+    pub fn dialect(self, dialect: Dialect) -> Writer<W, E> {
+        let wtr = match dialect.escape {
+            Some(escape) => self.escape(escape),
+            None => self,
+        };
+        wtr.delimiter(dialect.delimiter)
+            .quote(dialect.quote)
+            .double_quote(dialect.double_quote)
+            .record_terminator(dialect.record_terminator)
+            .quote_style(dialect.quote_style)
     }
 }
 
@@ -147,17 +892,31 @@ impl<W: Write, E: Encodable> Writer<W, E> {
     /// Writes a record by encoding any `Encodable` value.
     ///
     /// When the first record is encoded, the headers (the field names in the
-    /// struct) are written prior to encoding the record.
+    /// struct) are written prior to encoding the record, unless they were
+    /// already written by [`write_headers`](#method.write_headers).
     ///
     /// The type that is being encoded into should correspond to *one full CSV
     /// record*. This can be a single struct, or arbitrarily nested tuples,
     /// arrays, Vecs, and structs, as long as all scalar types (integers,
-    /// floats, characters, strings, collections containing one scalar, and
-    /// enums with 0 or 1 scalar arguments) are fields in structs. Enums with
+    /// floats, characters, strings, and enums with 0 or 1 scalar arguments)
+    /// are fields in structs. Enums with
     /// zero arguments encode to their name, while enums of one argument encode
     /// to their constituent value. `Option` types are also supported. (`None`
     /// encodes to an empty field.)
     ///
+    /// Struct fields that are themselves structs are flattened, with headers
+    /// like `outer.inner`. See
+    /// [`nested_field_separator`](#method.nested_field_separator). Struct
+    /// fields that are arrays, Vecs, or tuples with more than one element are
+    /// expanded into one column per element, with headers like `field_0`. See
+    /// [`indexed_field_pattern`](#method.indexed_field_pattern).
+    ///
+    /// One field of the record type may be a map (e.g. `HashMap<String,
+    /// String>` or `BTreeMap<String, String>`), which is expanded into one
+    /// column per key. The keys of the map in the first record become headers
+    /// in sorted order. In later records, missing keys are written as empty
+    /// fields, and keys that aren't headers are an error.
+    ///
     /// Note that single-element tuple structs (the newtype pattern) are
     /// supported. Unfortunately, to provide this functionality, a heuristic is
     /// necessary to differentiate field names in normal structs from those in
@@ -227,36 +986,475 @@ impl<W: Write, E: Encodable> Writer<W, E> {
     /// ");
     /// # }
     /// ```
-    pub fn encode(&mut self, row: E) -> csv::Result<()> {
-        // Write headers if this is the first row.
-        if self.first_row {
-            let mut field_names_encoder = FieldNamesEncoder::new();
+    pub fn encode(&mut self, row: E) -> Result<()> {
+        let mut record_encoder = RecordEncoder::new();
+        row.encode(&mut record_encoder)?;
+        self.write_record(record_encoder.into_record(), |naming| {
+            let mut field_names_encoder = FieldNamesEncoder::new(naming);
             row.encode(&mut field_names_encoder)?;
-            self.csv.write(field_names_encoder.into_field_names().into_iter())?;
+            Ok(field_names_encoder.into_field_names())
+        })
+    }
+}
+
+impl<W: Write, E: Encodable + Decodable> Writer<W, E> {
+    /// Writes the header row now, if it hasn't been written yet.
+    ///
+    /// Normally, the header row is written when the first record is encoded,
+    /// so writing no records produces no output at all. Calling this method
+    /// first ensures that the output always has a header row. The field names
+    /// are taken from the `Decodable` implementation of the record type, the
+    /// same way [`Reader`](struct.Reader.html) determines them.
+    ///
+    /// The number of columns of a `Vec` depends on the record, so a record
+    /// type that contains a `Vec` is an error. A catch-all map written this
+    /// way has no columns, so later records can't have any keys in it.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// #[derive(RustcEncodable, RustcDecodable)]
+    /// struct Record {
+    ///     count: usize,
+    ///     animal: String,
+    /// }
+    ///
+    /// let mut wtr = typed_csv::Writer::<_, Record>::from_memory();
+    /// wtr.write_headers().unwrap();
+    ///
+    /// assert_eq!(wtr.as_string(), "count,animal\n");
+    /// # }
+    /// ```
+    pub fn write_headers(&mut self) -> Result<()> {
+        if !self.first_row {
+            return Ok(());
+        }
+        let (field_names, catch_all_index) = decodable_field_names::<E>(&self.field_naming)?;
+        self.write_type_header_row(field_names, catch_all_index)
+    }
+
+    /// Writes the header row when the writer is flushed or dropped, if no
+    /// record has been encoded by then.
+    ///
+    /// With this option, writing no records produces a file with only the
+    /// header row instead of an empty file. The headers are the ones written
+    /// by [`write_headers`](#method.write_headers).
+    ///
+    /// An error writing the headers when the writer is dropped, or when it's
+    /// converted with `into_string` or `into_bytes`, is ignored. Call `flush`
+    /// first to find out about it.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate rustc_serialize;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// #[derive(RustcEncodable, RustcDecodable)]
+    /// struct Record {
+    ///     count: usize,
+    ///     animal: String,
+    /// }
+    ///
+    /// let wtr = typed_csv::Writer::<_, Record>::from_memory().always_write_headers();
+    ///
+    /// assert_eq!(wtr.into_string(), "count,animal\n");
+    /// # }
+    /// ```
+    pub fn always_write_headers(mut self) -> Writer<W, E> {
+        self.pending_headers = Some(Writer::write_headers);
+        self
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<W: Write, E: Serialize> Writer<W, E> {
+    /// Writes a record by serializing any `serde::Serialize` value.
+    ///
+    /// This is the equivalent of [`encode`](#method.encode) for types that
+    /// implement `serde::Serialize`. When the first record is serialized, the
+    /// headers (the field names in the struct, as provided by `serde`) are
+    /// written prior to serializing the record.
+    ///
+    /// Field attributes such as `#[serde(rename = "...")]` are respected.
+    /// Unit variants of enums serialize to their name, and newtype variants
+    /// serialize to their constituent value. `None` serializes to an empty
+    /// field.
+    ///
+    /// This method is only available if the `serde` feature is enabled.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// #[macro_use]
+    /// extern crate serde_derive;
+    /// # extern crate typed_csv;
+    /// # fn main() {
+    ///
+    /// #[derive(Serialize)]
+    /// struct Record {
+    ///     count: usize,
+    ///     animal: &'static str,
+    ///     description: Option<&'static str>,
+    /// }
+    ///
+    /// let records = vec![
+    ///     Record { count: 7, animal: "penguin", description: Some("happy") },
+    ///     Record { count: 10, animal: "cheetah", description: None },
+    /// ];
+    ///
+    /// let mut wtr = typed_csv::Writer::from_memory();
+    /// for record in records.into_iter() {
+    ///     wtr.serialize(record).unwrap();
+    /// }
+    ///
+    /// assert_eq!(wtr.as_string(), "\
+    /// count,animal,description
+    /// 7,penguin,happy
+    /// 10,cheetah,
+    /// ");
+    /// # }
+    /// ```
+    pub fn serialize(&mut self, row: E) -> Result<()> {
+        let mut record_serializer = RecordSerializer::new();
+        row.serialize(&mut record_serializer)?;
+        self.write_record(record_serializer.into_record(), |naming| {
+            let mut field_names_serializer = FieldNamesSerializer::new(naming);
+            row.serialize(&mut field_names_serializer)?;
+            Ok(field_names_serializer.into_field_names())
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<W: Write, E: Serialize + DeserializeOwned> Writer<W, E> {
+    /// Writes the header row now, if it hasn't been written yet.
+    ///
+    /// This is the equivalent of [`write_headers`](#method.write_headers) for
+    /// types that implement `serde::Serialize` and `serde::Deserialize`.
+    pub fn write_serialize_headers(&mut self) -> Result<()> {
+        if !self.first_row {
+            return Ok(());
+        }
+        let (field_names, catch_all_index) = deserializable_field_names::<E>(&self.field_naming)?;
+        self.write_type_header_row(field_names, catch_all_index)
+    }
+
+    /// Writes the header row when the writer is flushed or dropped, if no
+    /// record has been serialized by then.
+    ///
+    /// This is the equivalent of
+    /// [`always_write_headers`](#method.always_write_headers) for types that
+    /// implement `serde::Serialize` and `serde::Deserialize`.
+    pub fn always_write_serialize_headers(mut self) -> Writer<W, E> {
+        self.pending_headers = Some(Writer::write_serialize_headers);
+        self
+    }
+}
+
+/// Matches `headers` to the `field_names`, in any order, and returns the
+/// index of the field for each header. Any field may be left out.
+fn match_headers(headers: &[ByteString], field_names: &[ByteString]) -> Result<Vec<usize>> {
+    let indices = map_headers(headers,
+                              field_names,
+                              &vec![&[][..]; field_names.len()],
+                              &vec![true; field_names.len()],
+                              Normalization::NONE,
+                              true,
+                              false,
+                              &|header: &[u8], field_name: &[u8]| header == field_name)?;
+    // Every header has a field, since unused columns aren't ignored.
+    Ok(indices.into_iter().filter_map(|index| index).collect())
+}
+
+/// Returns the index in the record of the field to write in each of the
+/// existing columns (or `None` for an ignored column), and the keys of the
+/// catch-all map, which are the headers of the columns collected into it.
+///
+/// `field_names` are the field names of the record, and the columns of the
+/// catch-all map belong at `catch_all_index`, if it has one.
+fn existing_column_indices(mapping: &ColumnMapping,
+                           field_names: &[ByteString],
+                           catch_all_index: Option<usize>)
+                           -> Result<(Vec<Option<usize>>, Vec<ByteString>)> {
+    let headers = mapping.columns
+        .iter()
+        .map(|column| column.header.clone().into_bytes())
+        .collect::<Vec<_>>();
+    let keys = mapping.columns
+        .iter()
+        .filter(|column| column.target == ColumnTarget::CatchAll)
+        .map(|column| column.header.clone().into_bytes())
+        .collect::<Vec<_>>();
+    let mut mismatch = HeaderMismatch::new(&headers, field_names);
+    let mut written = vec![false; field_names.len()];
+    let mut key_index = 0;
+    let mut indices = Vec::new();
+    for (header_index, column) in mapping.columns.iter().enumerate() {
+        let index = match column.target {
+            ColumnTarget::Field { ref name, .. } => {
+                field_names.iter().position(|field_name| field_name == name.as_bytes()).map(|i| {
+                    written[i] = true;
+                    match catch_all_index {
+                        Some(catch_all_index) if i >= catch_all_index => i + keys.len(),
+                        _ => i,
+                    }
+                })
+            }
+            ColumnTarget::CatchAll => {
+                key_index += 1;
+                catch_all_index.map(|i| i + key_index - 1)
+            }
+            ColumnTarget::Ignored => {
+                indices.push(None);
+                continue;
+            }
+        };
+        if index.is_none() {
+            mismatch.unexpected_headers.push(UnexpectedHeader {
+                header_index: header_index,
+                header: column.header.clone(),
+            });
+        }
+        indices.push(index);
+    }
+    for (field_index, field_name) in field_names.iter().enumerate() {
+        let field_name = String::from_utf8_lossy(field_name).into_owned();
+        if !written[field_index] && !mapping.unmatched_fields.contains(&field_name) {
+            mismatch.missing_fields.push(MissingField {
+                field_index: field_index,
+                field_name: field_name,
+                aliases: Vec::new(),
+            });
+        }
+    }
+    if mismatch.is_empty() {
+        Ok((indices, keys))
+    } else {
+        Err(Error::HeaderMismatch(mismatch))
+    }
+}
+
+/// A record produced by `RecordEncoder` or `RecordSerializer`.
+#[derive(Debug)]
+struct EncodedRecord {
+    fields: Vec<ByteString>,
+    /// Index in `fields` where the columns of the catch-all map belong, if
+    /// the record has one.
+    catch_all_index: Option<usize>,
+    /// Keys and values of the catch-all map.
+    catch_all: Vec<(ByteString, ByteString)>,
+}
+
+impl<W: Write, E> Writer<W, E> {
+    /// Writes a record, preceded by the headers if this is the first row.
+    ///
+    /// `field_names` is called with the field naming options to get the
+    /// field names only if the headers need to be written. The headers are
+    /// the renamed field names. The keys of the catch-all map in the first row
+    /// become headers (in sorted order) at the position of the map, unless
+    /// the headers are given by `column_order` or the existing header row.
+    fn write_record<F>(&mut self, record: EncodedRecord, field_names: F) -> Result<()>
+        where F: FnOnce(&FieldNaming) -> Result<Vec<ByteString>>
+    {
+        if self.first_row {
+            self.write_header_row(&record, field_names)?;
+        }
+        let mut fields = record.fields;
+        if let Some(index) = record.catch_all_index {
+            let values = self.catch_all_values(record.catch_all)?;
+            fields.splice(index..index, values);
+        }
+        if let Some(ref indices) = self.column_indices {
+            fields = indices.iter()
+                .map(|&index| match index {
+                    Some(index) => mem::replace(&mut fields[index], Vec::new()),
+                    None => Vec::new(),
+                })
+                .collect();
+        }
+        self.csv().write(fields.into_iter()).map_err(Error::from)
+    }
+
+    /// Writes the headers for the first row `record` (unless `has_headers` is
+    /// disabled), or determines the columns of the existing header row.
+    fn write_header_row<F>(&mut self, record: &EncodedRecord, field_names: F) -> Result<()>
+        where F: FnOnce(&FieldNaming) -> Result<Vec<ByteString>>
+    {
+        let field_names = field_names(&self.field_naming)?;
+        if let Some(ref mapping) = self.existing_columns {
+            // The header row of the file was matched to the fields when it
+            // was opened, and isn't written again.
+            let (indices, keys) = existing_column_indices(mapping,
+                                                          &field_names,
+                                                          record.catch_all_index)?;
+            self.column_indices = Some(indices);
+            self.catch_all_keys = keys;
             self.first_row = false;
+            return Ok(());
+        }
+        let mut headers = field_names.iter()
+            .map(|field_name| self.field_naming.header(field_name))
+            .collect::<Vec<_>>();
+        let mut annotations =
+            field_names.iter().map(|field_name| self.annotation(field_name)).collect::<Vec<_>>();
+        let mut keys = Vec::new();
+        if let Some(index) = record.catch_all_index {
+            keys = match self.column_order {
+                // The given headers that don't belong to fields are the keys
+                // of the catch-all map.
+                Some(ref order) => {
+                    order.iter().filter(|header| !headers.contains(header)).cloned().collect()
+                }
+                None => {
+                    let mut keys: Vec<ByteString> =
+                        record.catch_all.iter().map(|&(ref key, _)| key.clone()).collect();
+                    keys.sort();
+                    keys
+                }
+            };
+            headers.splice(index..index, keys.iter().cloned());
+            annotations.splice(index..index, keys.iter().map(|key| self.annotation(key)));
+        }
+        if let Some(ref order) = self.column_order {
+            // Any field may be left out, but every column must have a field.
+            let indices = match_headers(order, &headers)?;
+            annotations = indices.iter().map(|&index| annotations[index].clone()).collect();
+            self.column_indices = Some(indices.into_iter().map(Some).collect());
+            headers = order.clone();
+        }
+        if self.has_headers {
+            self.csv().write(headers.into_iter())?;
+            if !self.field_annotations.is_empty() {
+                self.csv().write(annotations.into_iter())?;
+            }
+        }
+        self.catch_all_keys = keys;
+        self.first_row = false;
+        Ok(())
+    }
+
+    /// Writes the headers for the field names of the record type, without a
+    /// record.
+    fn write_type_header_row(&mut self,
+                             field_names: Vec<ByteString>,
+                             catch_all_index: Option<usize>)
+                             -> Result<()> {
+        let record = EncodedRecord {
+            fields: Vec::new(),
+            catch_all_index: catch_all_index,
+            catch_all: Vec::new(),
+        };
+        self.write_header_row(&record, |_| Ok(field_names))
+    }
+
+    /// Returns the annotation of a field or a key of the catch-all map.
+    fn annotation(&self, name: &[u8]) -> ByteString {
+        self.field_annotations.get(name).cloned().unwrap_or_default()
+    }
+
+    /// Returns the values of the catch-all map in the order of the headers.
+    ///
+    /// Keys that are missing have empty values, and keys that aren't headers
+    /// are an error.
+    fn catch_all_values(&self,
+                        mut entries: Vec<(ByteString, ByteString)>)
+                        -> Result<Vec<ByteString>> {
+        let values = self.catch_all_keys
+            .iter()
+            .map(|key| match entries.iter().position(|&(ref k, _)| k == key) {
+                Some(i) => entries.swap_remove(i).1,
+                None => Vec::new(),
+            })
+            .collect();
+        match entries.first() {
+            Some(&(ref key, _)) => {
+                let msg = format!("The catch-all map has the key '{}', which isn't in the headers",
+                                  String::from_utf8_lossy(key));
+                Err(Error::Csv(csv::Error::Encode(msg)))
+            }
+            None => Ok(values),
+        }
+    }
+
+    /// Returns the underlying CSV writer.
+    fn csv(&mut self) -> &mut csv::Writer<W> {
+        self.csv.as_mut().expect("the CSV writer has been taken out")
+    }
+
+    /// Applies a builder method to the underlying CSV writer.
+    fn map_csv<F>(mut self, f: F) -> Writer<W, E>
+        where F: FnOnce(csv::Writer<W>) -> csv::Writer<W>
+    {
+        self.csv = self.csv.take().map(f);
+        self
+    }
+
+    /// Writes the header row if `always_write_headers` is enabled and no
+    /// record has been written.
+    fn write_pending_headers(&mut self) -> Result<()> {
+        match self.pending_headers {
+            Some(write_headers) if self.first_row => write_headers(self),
+            _ => Ok(()),
         }
-        // Write row.
-        let mut erecord = csv::Encoded::new();
-        row.encode(&mut erecord)?;
-        self.csv.write(erecord.unwrap().into_iter())
     }
 
     /// Flushes the underlying buffer.
+    ///
+    /// If `always_write_headers` is enabled and no record has been written,
+    /// the header row is written first.
     pub fn flush(&mut self) -> Result<()> {
-        self.csv.flush()
+        self.write_pending_headers()?;
+        self.csv().flush().map_err(Error::from)
+    }
+}
+
+/// Writes the pending header row when the writer is dropped. Errors are
+/// ignored, since they can't be returned; see `always_write_headers`.
+impl<W: Write, E> Drop for Writer<W, E> {
+    fn drop(&mut self) {
+        if self.csv.is_some() && self.pending_headers.is_some() && self.first_row {
+            let _ = self.flush();
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::Writer;
+    #[cfg(feature = "bzip2")]
+    use compression::{Compression, DecompressingReader};
+    use csv;
+    use dialect::Dialect;
+    use encoding::Encoding;
+    use error::Error;
+    use normalization::Normalization;
+    use reader::Reader;
+    use std::collections::{BTreeMap, HashMap};
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{Read, Write};
+    use std::path::PathBuf;
 
-    #[derive(RustcEncodable)]
+    #[derive(RustcEncodable, RustcDecodable)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
     struct SimpleStruct {
         a: usize,
         b: usize,
     }
 
+    #[derive(RustcEncodable, RustcDecodable)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    struct OptionStruct {
+        a: usize,
+        b: Option<String>,
+    }
+
     #[test]
     fn test_struct() {
         let mut w = Writer::from_memory();
@@ -267,6 +1465,303 @@ mod tests {
         assert_eq!(w.as_string(), "a,b\n0,1\n3,4\n");
     }
 
+    #[test]
+    fn test_struct_rename_field() {
+        let mut w = Writer::from_memory().rename_field("b", "Field B");
+        w.encode(SimpleStruct { a: 0, b: 1 }).unwrap();
+        assert_eq!(w.as_string(), "a,Field B\n0,1\n");
+    }
+
+    #[test]
+    fn test_struct_rename_fields_with() {
+        let mut w = Writer::from_memory()
+            .rename_field("a", "first")
+            .rename_fields_with(|name| format!("field_{}", name));
+        w.encode(SimpleStruct { a: 0, b: 1 }).unwrap();
+        assert_eq!(w.as_string(), "first,field_b\n0,1\n");
+    }
+
+    #[test]
+    fn test_write_headers_without_records() {
+        let mut w = Writer::<_, OptionStruct>::from_memory();
+        w.write_headers().unwrap();
+        assert_eq!(w.as_string(), "a,b\n");
+    }
+
+    #[test]
+    fn test_write_headers_before_records() {
+        let mut w = Writer::from_memory().rename_field("b", "B");
+        w.write_headers().unwrap();
+        w.write_headers().unwrap();
+        w.encode(OptionStruct { a: 0, b: Some("x".to_string()) }).unwrap();
+        assert_eq!(w.as_string(), "a,B\n0,x\n");
+    }
+
+    #[test]
+    fn test_write_headers_with_arrays_and_catch_all() {
+        #[derive(RustcEncodable, RustcDecodable)]
+        struct Record {
+            a: [[u8; 2]; 2],
+            other: BTreeMap<String, String>,
+            b: (u8, Option<u8>),
+        }
+        let mut w = Writer::<_, Record>::from_memory();
+        w.write_headers().unwrap();
+        assert_eq!(w.as_string(), "a_0_0,a_0_1,a_1_0,a_1_1,b_0,b_1\n");
+    }
+
+    #[test]
+    fn test_write_headers_with_vec() {
+        let mut w = Writer::<_, RoundTripStruct>::from_memory();
+        match w.write_headers() {
+            Err(Error::Csv(csv::Error::Encode(_))) => {}
+            result => panic!("Expected an encoding error, but got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_always_write_headers_on_flush() {
+        let mut data = Vec::new();
+        {
+            let mut w = Writer::<_, OptionStruct>::from_writer(&mut data).always_write_headers();
+            w.flush().unwrap();
+        }
+        assert_eq!(data, b"a,b\n");
+    }
+
+    #[test]
+    fn test_always_write_headers_on_drop() {
+        let mut data = Vec::new();
+        {
+            Writer::<_, OptionStruct>::from_writer(&mut data).always_write_headers();
+        }
+        assert_eq!(data, b"a,b\n");
+    }
+
+    #[test]
+    fn test_always_write_headers_with_records() {
+        let mut w = Writer::from_memory().always_write_headers();
+        w.encode(OptionStruct { a: 0, b: Some("x".to_string()) }).unwrap();
+        assert_eq!(w.into_string(), "a,b\n0,x\n");
+    }
+
+    /// Returns the path of a temporary file with the given contents.
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("typed_csv_{}.csv", name));
+        File::create(&path).unwrap().write_all(contents.as_bytes()).unwrap();
+        path
+    }
+
+    fn read_and_remove(path: PathBuf) -> String {
+        let mut contents = String::new();
+        File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+        fs::remove_file(path).unwrap();
+        contents
+    }
+
+    #[test]
+    fn test_append_to_file() {
+        let path = temp_file("append", "a,b\n0,1\n");
+        {
+            let mut w = Writer::append_to_file(&path).unwrap();
+            w.encode(SimpleStruct { a: 2, b: 3 }).unwrap();
+            w.encode(SimpleStruct { a: 4, b: 5 }).unwrap();
+            w.flush().unwrap();
+        }
+        assert_eq!(read_and_remove(path), "a,b\n0,1\n2,3\n4,5\n");
+    }
+
+    #[test]
+    fn test_append_to_empty_file() {
+        let path = temp_file("append_empty", "");
+        {
+            let mut w = Writer::append_to_file(&path).unwrap();
+            w.encode(SimpleStruct { a: 0, b: 1 }).unwrap();
+            w.flush().unwrap();
+        }
+        assert_eq!(read_and_remove(path), "a,b\n0,1\n");
+    }
+
+    #[test]
+    fn test_append_to_file_header_mismatch() {
+        let path = temp_file("append_mismatch", "a,c\n0,1\n");
+        match Writer::<_, SimpleStruct>::append_to_file(&path) {
+            Err(Error::HeaderMismatch(mismatch)) => assert!(!mismatch.missing_fields.is_empty()),
+            Err(err) => panic!("Expected a header mismatch, but got {}", err),
+            Ok(_) => panic!("Expected a header mismatch"),
+        }
+        assert_eq!(read_and_remove(path), "a,c\n0,1\n");
+    }
+
+    #[test]
+    fn test_append_to_file_write_headers() {
+        let path = temp_file("append_write_headers", "b,a\n1,0\n");
+        {
+            let mut w = Writer::<_, OptionStruct>::append_to_file(&path).unwrap();
+            w.write_headers().unwrap();
+            w.encode(OptionStruct { a: 2, b: None }).unwrap();
+            w.flush().unwrap();
+        }
+        assert_eq!(read_and_remove(path), "b,a\n1,0\n,2\n");
+    }
+
+    #[test]
+    fn test_append_to_file_optional_field() {
+        let path = temp_file("append_optional", "a\n0\n");
+        {
+            let mut w = Writer::append_to_file(&path).unwrap();
+            w.encode(OptionStruct { a: 1, b: Some("x".to_string()) }).unwrap();
+            w.flush().unwrap();
+        }
+        assert_eq!(read_and_remove(path), "a\n0\n1\n");
+    }
+
+    #[test]
+    fn test_append_to_file_reordered() {
+        let path = temp_file("append_reordered", "b,a\n1,0\n");
+        {
+            let mut w = Writer::append_to_file(&path).unwrap();
+            w.encode(SimpleStruct { a: 2, b: 3 }).unwrap();
+            w.flush().unwrap();
+        }
+        assert_eq!(read_and_remove(path), "b,a\n1,0\n3,2\n");
+    }
+
+    #[test]
+    fn test_append_to_file_normalized() {
+        let path = temp_file("append_normalized", "\u{feff} B \tA\n1\t0\n");
+        {
+            let mut w = Writer::append_to_file_with(&path, Dialect::tsv(), |rdr| {
+                    rdr.normalize_headers(Normalization::TRIM |
+                                          Normalization::ASCII_CASE_INSENSITIVE)
+                })
+                .unwrap();
+            w.encode(SimpleStruct { a: 2, b: 3 }).unwrap();
+            w.flush().unwrap();
+        }
+        assert_eq!(read_and_remove(path), "\u{feff} B \tA\n1\t0\n3\t2\n");
+    }
+
+    #[test]
+    fn test_append_to_file_with_aliases() {
+        let path = temp_file("append_aliases", "B\talpha\tc\n1\t0\tx\n");
+        {
+            let mut w = Writer::append_to_file_with(&path, Dialect::tsv(), |rdr| {
+                    rdr.field_aliases("a", vec!["alpha"])
+                        .ignore_unused_columns(true)
+                        .headers_match_by_owned(|header, field_name| {
+                            header.to_ascii_lowercase() == field_name
+                        })
+                })
+                .unwrap();
+            w.encode(SimpleStruct { a: 2, b: 3 }).unwrap();
+            w.flush().unwrap();
+        }
+        assert_eq!(read_and_remove(path), "B\talpha\tc\n1\t0\tx\n3\t2\t\n");
+    }
+
+    #[test]
+    fn test_append_to_file_with_dialect() {
+        let path = temp_file("append_dialect", "b;a\r\n1;0");
+        {
+            let dialect = Dialect { delimiter: b';', ..Dialect::rfc4180() };
+            let mut w = Writer::append_to_file_with(&path, dialect, |rdr| rdr).unwrap();
+            w.encode(SimpleStruct { a: 2, b: 3 }).unwrap();
+            w.flush().unwrap();
+        }
+        assert_eq!(read_and_remove(path), "b;a\r\n1;0\r\n3;2\r\n");
+    }
+
+    #[test]
+    fn test_append_to_file_without_terminator() {
+        let path = temp_file("append_no_terminator", "a,b\r\n0,1");
+        {
+            let mut w = Writer::append_to_file(&path).unwrap();
+            w.encode(SimpleStruct { a: 2, b: 3 }).unwrap();
+            w.flush().unwrap();
+        }
+        assert_eq!(read_and_remove(path), "a,b\r\n0,1\r\n2,3\n");
+    }
+
+    #[test]
+    fn test_append_to_file_with_catch_all() {
+        let path = temp_file("append_catch_all", "a,y,x,b\n0,1,2,3\n");
+        {
+            let mut w = Writer::append_to_file(&path).unwrap();
+            w.encode(struct_with_catch_all(4, &[("x", 6)])).unwrap();
+            w.flush().unwrap();
+        }
+        assert_eq!(read_and_remove(path), "a,y,x,b\n0,1,2,3\n4,,6,5\n");
+    }
+
+    #[test]
+    fn test_field_annotation() {
+        let mut w = Writer::from_memory().field_annotation("b", "kg");
+        w.encode(SimpleStruct { a: 0, b: 1 }).unwrap();
+        w.encode(SimpleStruct { a: 2, b: 3 }).unwrap();
+        assert_eq!(w.as_string(), "a,b\n,kg\n0,1\n2,3\n");
+    }
+
+    #[test]
+    fn test_field_annotation_column_order_and_catch_all() {
+        let mut w = Writer::from_memory()
+            .field_annotation("a", "m")
+            .field_annotation("x", "s")
+            .column_order(vec!["x", "a"]);
+        w.encode(struct_with_catch_all(0, &[("x", 2)])).unwrap();
+        assert_eq!(w.as_string(), "x,a\ns,m\n2,0\n");
+    }
+
+    #[test]
+    fn test_no_headers() {
+        let mut w = Writer::from_memory().has_headers(false);
+        w.encode(SimpleStruct { a: 0, b: 1 }).unwrap();
+        w.encode(SimpleStruct { a: 2, b: 3 }).unwrap();
+        assert_eq!(w.as_string(), "0,1\n2,3\n");
+    }
+
+    #[test]
+    fn test_no_headers_column_order() {
+        let mut w = Writer::from_memory().has_headers(false).column_order(vec!["b", "a"]);
+        w.encode(SimpleStruct { a: 0, b: 1 }).unwrap();
+        assert_eq!(w.as_string(), "1,0\n");
+    }
+
+    #[test]
+    fn test_column_order() {
+        let mut w = Writer::from_memory().column_order(vec!["b", "a"]);
+        w.encode(SimpleStruct { a: 0, b: 1 }).unwrap();
+        w.encode(SimpleStruct { a: 2, b: 3 }).unwrap();
+        assert_eq!(w.as_string(), "b,a\n1,0\n3,2\n");
+    }
+
+    #[test]
+    fn test_column_order_subset_and_rename() {
+        let mut w = Writer::from_memory().rename_field("b", "B").column_order(vec!["B"]);
+        w.encode(SimpleStruct { a: 0, b: 1 }).unwrap();
+        assert_eq!(w.as_string(), "B\n1\n");
+    }
+
+    #[test]
+    fn test_column_order_unknown_header() {
+        let mut w = Writer::from_memory().column_order(vec!["a", "c"]);
+        match w.encode(SimpleStruct { a: 0, b: 1 }) {
+            Err(Error::HeaderMismatch(mismatch)) => {
+                assert_eq!(mismatch.unexpected_headers[0].header, "c");
+            }
+            res => panic!("Expected a header mismatch, but got {:?}", res),
+        }
+        assert_eq!(w.as_string(), "");
+    }
+
+    #[test]
+    fn test_column_order_with_catch_all() {
+        let mut w = Writer::from_memory().column_order(vec!["x", "b", "a"]);
+        w.encode(struct_with_catch_all(0, &[("x", 2)])).unwrap();
+        w.encode(struct_with_catch_all(3, &[])).unwrap();
+        assert_eq!(w.as_string(), "x,b,a\n2,1,0\n,4,3\n");
+    }
+
     #[test]
     fn test_tuple_of_structs() {
         let mut w = Writer::from_memory();
@@ -344,20 +1839,32 @@ mod tests {
     }
 
     #[derive(RustcEncodable)]
+    #[cfg_attr(feature = "serde", derive(Serialize))]
     struct StructOfStruct {
         p: SimpleStruct,
-        q: (usize, usize),
+        q: usize,
     }
 
-    #[should_panic]
     #[test]
     fn test_struct_of_struct() {
         let mut w = Writer::from_memory();
         let s = StructOfStruct {
             p: SimpleStruct { a: 0, b: 1 },
-            q: (2, 3),
+            q: 2,
+        };
+        w.encode(s).unwrap();
+        assert_eq!(w.as_string(), "p.a,p.b,q\n0,1,2\n");
+    }
+
+    #[test]
+    fn test_struct_of_struct_nested_field_separator() {
+        let mut w = Writer::from_memory().nested_field_separator("__");
+        let s = StructOfStruct {
+            p: SimpleStruct { a: 0, b: 1 },
+            q: 2,
         };
         w.encode(s).unwrap();
+        assert_eq!(w.as_string(), "p__a,p__b,q\n0,1,2\n");
     }
 
     #[derive(RustcEncodable)]
@@ -365,12 +1872,132 @@ mod tests {
         a: [usize; 2],
     }
 
-    #[should_panic]
     #[test]
     fn test_struct_with_longer_seq() {
         let mut w = Writer::from_memory();
         let s = StructWithLongerSeq { a: [0, 1] };
         w.encode(s).unwrap();
+        assert_eq!(w.as_string(), "a_0,a_1\n0,1\n");
+    }
+
+    #[derive(RustcEncodable)]
+    #[cfg_attr(feature = "serde", derive(Serialize))]
+    struct StructWithArrayAndTuple {
+        a: [usize; 2],
+        b: (usize, usize, usize),
+        c: Vec<SimpleStruct>,
+    }
+
+    #[test]
+    fn test_struct_with_array_and_tuple() {
+        let mut w = Writer::from_memory();
+        let s = StructWithArrayAndTuple {
+            a: [0, 1],
+            b: (2, 3, 4),
+            c: vec![SimpleStruct { a: 5, b: 6 }, SimpleStruct { a: 7, b: 8 }],
+        };
+        w.encode(s).unwrap();
+        assert_eq!(w.as_string(),
+                   "a_0,a_1,b_0,b_1,b_2,c_0.a,c_0.b,c_1.a,c_1.b\n0,1,2,3,4,5,6,7,8\n");
+    }
+
+    #[test]
+    fn test_struct_with_array_and_tuple_indexed_field_pattern() {
+        let mut w = Writer::from_memory().indexed_field_pattern("{name}[{index}]");
+        let s = StructWithArrayAndTuple {
+            a: [0, 1],
+            b: (2, 3, 4),
+            c: vec![SimpleStruct { a: 5, b: 6 }, SimpleStruct { a: 7, b: 8 }],
+        };
+        w.encode(s).unwrap();
+        assert_eq!(w.as_string(),
+                   "a[0],a[1],b[0],b[1],b[2],c[0].a,c[0].b,c[1].a,c[1].b\n0,1,2,3,4,5,6,7,8\n");
+    }
+
+    #[derive(Clone, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    struct Pair {
+        x: u8,
+        y: Option<u8>,
+    }
+
+    #[derive(Clone, Debug, PartialEq, RustcEncodable, RustcDecodable)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    struct RoundTripStruct {
+        a: [u8; 3],
+        b: (i32, String),
+        c: Vec<u16>,
+        d: [[u8; 2]; 2],
+        e: Vec<Pair>,
+        f: usize,
+    }
+
+    /// Returns records whose `Vec`s have `len` elements.
+    fn round_trip_records(len: usize) -> Vec<RoundTripStruct> {
+        (0..2)
+            .map(|i| {
+                RoundTripStruct {
+                    a: [i, 1, 2],
+                    b: (-3, "four".to_string()),
+                    c: (0..len as u16).map(|c| c + 5).collect(),
+                    d: [[6, 7], [8, i]],
+                    e: (0..len as u8)
+                        .map(|x| {
+                            Pair {
+                                x: x,
+                                y: if x > 0 { Some(x) } else { None },
+                            }
+                        })
+                        .collect(),
+                    f: 9,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip_arrays_tuples_and_vecs() {
+        for len in 0..4 {
+            let records = round_trip_records(len);
+            let mut w = Writer::from_memory();
+            for record in records.clone() {
+                w.encode(record).unwrap();
+            }
+            let data = w.as_string().to_string();
+            let rdr = Reader::from_string(&*data);
+            let decoded = rdr.decode().collect::<Result<Vec<RoundTripStruct>, _>>().unwrap();
+            assert_eq!(decoded, records, "{}", data);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_vec_headers() {
+        let mut w = Writer::from_memory();
+        w.encode(round_trip_records(1).remove(0)).unwrap();
+        assert_eq!(w.as_string().lines().next(),
+                   Some("a_0,a_1,a_2,b_0,b_1,c,d_0_0,d_0_1,d_1_0,d_1_1,e.x,e.y,f"));
+        let mut w = Writer::from_memory();
+        w.encode(round_trip_records(2).remove(0)).unwrap();
+        assert_eq!(w.as_string().lines().next(),
+                   Some("a_0,a_1,a_2,b_0,b_1,c_0,c_1,d_0_0,d_0_1,d_1_0,d_1_1,e_0.x,e_0.y,\
+                         e_1.x,e_1.y,f"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip_arrays_tuples_and_vecs() {
+        for len in 0..4 {
+            let records = round_trip_records(len);
+            let mut w = Writer::from_memory();
+            for record in records.clone() {
+                w.serialize(record).unwrap();
+            }
+            let data = w.as_string().to_string();
+            let rdr = Reader::from_string(&*data);
+            let deserialized = rdr.deserialize().collect::<Result<Vec<RoundTripStruct>, _>>()
+                .unwrap();
+            assert_eq!(deserialized, records, "{}", data);
+        }
     }
 
     #[should_panic]
@@ -380,4 +2007,284 @@ mod tests {
         let array = vec![0, 1];
         w.encode(array).unwrap();
     }
+
+    #[test]
+    fn test_struct_with_vec_flexible() {
+        #[derive(RustcEncodable)]
+        struct Record {
+            a: usize,
+            b: Vec<usize>,
+        }
+        let mut w = Writer::from_memory();
+        w.encode(Record { a: 0, b: vec![1, 2] }).unwrap();
+        assert!(w.encode(Record { a: 3, b: vec![4] }).is_err());
+        let mut w = Writer::from_memory().flexible(true);
+        w.encode(Record { a: 0, b: vec![1, 2] }).unwrap();
+        w.encode(Record { a: 3, b: vec![4] }).unwrap();
+        assert_eq!(w.as_string(), "a,b_0,b_1\n0,1,2\n3,4\n");
+    }
+
+    #[derive(RustcEncodable, RustcDecodable)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    struct StructWithCatchAll {
+        a: usize,
+        other: HashMap<String, usize>,
+        b: usize,
+    }
+
+    fn struct_with_catch_all(a: usize, entries: &[(&'static str, usize)]) -> StructWithCatchAll {
+        StructWithCatchAll {
+            a: a,
+            other: entries.iter().map(|&(key, value)| (key.to_string(), value)).collect(),
+            b: a + 1,
+        }
+    }
+
+    #[test]
+    fn test_struct_with_catch_all() {
+        let mut w = Writer::from_memory();
+        w.encode(struct_with_catch_all(0, &[("y", 1), ("x", 2)])).unwrap();
+        w.encode(struct_with_catch_all(3, &[("x", 4)])).unwrap();
+        assert_eq!(w.as_string(), "a,x,y,b\n0,2,1,1\n3,4,,4\n");
+    }
+
+    #[test]
+    fn test_struct_with_catch_all_unknown_key() {
+        let mut w = Writer::from_memory();
+        w.encode(struct_with_catch_all(0, &[("x", 1)])).unwrap();
+        let err = w.encode(struct_with_catch_all(2, &[("x", 3), ("z", 4)])).unwrap_err();
+        assert_eq!(format!("{}", err),
+                   "CSV encode error: The catch-all map has the key 'z', which isn't in the \
+                    headers"
+                       .to_string());
+    }
+
+    #[test]
+    fn test_catch_all_only() {
+        let mut w = Writer::from_memory();
+        let mut map = BTreeMap::new();
+        map.insert("b", 0);
+        map.insert("a", 1);
+        w.encode(map).unwrap();
+        assert_eq!(w.as_string(), "a,b\n1,0\n");
+    }
+
+    #[test]
+    fn test_utf16_with_bom() {
+        let mut data = Vec::new();
+        {
+            let mut w = Writer::from_writer_with_encoding(&mut data, Encoding::Utf16Le, true);
+            w.encode(SimpleStruct { a: 0, b: 1 }).unwrap();
+            w.flush().unwrap();
+        }
+        let mut expected = vec![0xff, 0xfe];
+        for unit in "a,b\n0,1\n".encode_utf16() {
+            expected.extend_from_slice(&[unit as u8, (unit >> 8) as u8]);
+        }
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_latin1() {
+        let mut data = Vec::new();
+        {
+            let mut w = Writer::from_writer_with_encoding(&mut data, Encoding::Latin1, true);
+            let mut map = BTreeMap::new();
+            map.insert("a", "é");
+            w.encode(map).unwrap();
+            w.flush().unwrap();
+        }
+        assert_eq!(data, b"a\n\xe9\n");
+        let mut w = Writer::from_writer_with_encoding(Vec::new(), Encoding::Latin1, false);
+        let mut map = BTreeMap::new();
+        map.insert("a", "€");
+        let result = w.encode(map).and_then(|_| w.flush());
+        match result.unwrap_err() {
+            Error::Csv(csv::Error::Io(_)) => {}
+            err => panic!("Expected an I/O error, but got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_from_file_uncompressed() {
+        let path = temp_file("from_file_uncompressed", "x\n");
+        let mut w = Writer::from_file(&path).unwrap();
+        w.encode(SimpleStruct { a: 0, b: 1 }).unwrap();
+        w.finish().unwrap();
+        assert_eq!(read_and_remove(path), "a,b\n0,1\n");
+    }
+
+    #[cfg(feature = "bzip2")]
+    #[test]
+    fn test_from_file_compressed() {
+        let path = env::temp_dir().join("typed_csv_compressed.csv.bz2");
+        let mut w = Writer::from_file(&path).unwrap();
+        w.encode(SimpleStruct { a: 0, b: 1 }).unwrap();
+        w.finish().unwrap();
+        let mut contents = String::new();
+        DecompressingReader::new(File::open(&path).unwrap(), Compression::Bzip2)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(contents, "a,b\n0,1\n");
+    }
+
+    #[cfg(feature = "bzip2")]
+    #[test]
+    fn test_from_file_detects_compression() {
+        let path = env::temp_dir().join("typed_csv_detect_compression.csv");
+        Writer::from_file_with_compression(&path, Compression::Bzip2)
+            .unwrap()
+            .encode(SimpleStruct { a: 0, b: 1 })
+            .unwrap();
+        let mut w = Writer::from_file(&path).unwrap();
+        w.encode(SimpleStruct { a: 2, b: 3 }).unwrap();
+        w.finish().unwrap();
+        let mut contents = String::new();
+        DecompressingReader::new(File::open(&path).unwrap(), Compression::Bzip2)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(contents, "a,b\n2,3\n");
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    #[test]
+    fn test_from_file_compression_disabled() {
+        let path = env::temp_dir().join("typed_csv_compression_disabled.csv.zst");
+        match Writer::<_, SimpleStruct>::from_file(&path) {
+            Err(Error::Csv(csv::Error::Io(_))) => {}
+            res => panic!("Expected an I/O error, but got {:?}", res.map(|_| ())),
+        }
+        assert!(!path.exists());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_struct() {
+        let mut w = Writer::from_memory();
+        w.serialize(SimpleStruct { a: 0, b: 1 }).unwrap();
+        w.serialize(SimpleStruct { a: 3, b: 4 }).unwrap();
+        assert_eq!(w.as_string(), "a,b\n0,1\n3,4\n");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_write_headers_without_records() {
+        let mut w = Writer::<_, OptionStruct>::from_memory();
+        w.write_serialize_headers().unwrap();
+        assert_eq!(w.as_string(), "a,b\n");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_always_write_headers() {
+        let w = Writer::<_, OptionStruct>::from_memory().always_write_serialize_headers();
+        assert_eq!(w.into_string(), "a,b\n");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_append_to_file_with_catch_all() {
+        let path = temp_file("serde_append_catch_all", "b,x,a\n0,1,2\n");
+        {
+            let mut w = Writer::append_to_file_serialize(&path).unwrap();
+            w.serialize(struct_with_catch_all(3, &[("x", 5)])).unwrap();
+            w.flush().unwrap();
+        }
+        assert_eq!(read_and_remove(path), "b,x,a\n0,1,2\n4,5,3\n");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_append_to_file_header_mismatch() {
+        let path = temp_file("serde_append_mismatch", "a,c\n0,1\n");
+        match Writer::<_, SimpleStruct>::append_to_file_serialize(&path) {
+            Err(Error::HeaderMismatch(mismatch)) => assert!(!mismatch.missing_fields.is_empty()),
+            Err(err) => panic!("Expected a header mismatch, but got {}", err),
+            Ok(_) => panic!("Expected a header mismatch"),
+        }
+        assert_eq!(read_and_remove(path), "a,c\n0,1\n");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_tuple_of_structs() {
+        let mut w = Writer::from_memory();
+        w.serialize((SimpleStruct { a: 0, b: 1 }, SimpleStruct { a: 2, b: 3 })).unwrap();
+        w.serialize((SimpleStruct { a: 4, b: 5 }, SimpleStruct { a: 6, b: 7 })).unwrap();
+        assert_eq!(w.as_string(), "a,b,a,b\n0,1,2,3\n4,5,6,7\n");
+    }
+
+    #[cfg(feature = "serde")]
+    #[derive(Serialize)]
+    enum Group {
+        Bird,
+        Mammal,
+    }
+
+    #[cfg(feature = "serde")]
+    #[derive(Serialize)]
+    struct SerdeStruct {
+        count: usize,
+        group: Group,
+        #[serde(rename = "desc")]
+        description: Option<&'static str>,
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_enum_option_and_rename() {
+        let mut w = Writer::from_memory();
+        w.serialize(SerdeStruct {
+                count: 7,
+                group: Group::Bird,
+                description: Some("happy"),
+            })
+            .unwrap();
+        w.serialize(SerdeStruct {
+                count: 10,
+                group: Group::Mammal,
+                description: None,
+            })
+            .unwrap();
+        assert_eq!(w.as_string(), "count,group,desc\n7,Bird,happy\n10,Mammal,\n");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_struct_of_struct() {
+        let mut w = Writer::from_memory();
+        let s = StructOfStruct {
+            p: SimpleStruct { a: 0, b: 1 },
+            q: 2,
+        };
+        w.serialize(s).unwrap();
+        assert_eq!(w.as_string(), "p.a,p.b,q\n0,1,2\n");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_struct_with_array_and_tuple() {
+        let mut w = Writer::from_memory();
+        let s = StructWithArrayAndTuple {
+            a: [0, 1],
+            b: (2, 3, 4),
+            c: vec![SimpleStruct { a: 5, b: 6 }, SimpleStruct { a: 7, b: 8 }],
+        };
+        w.serialize(s).unwrap();
+        assert_eq!(w.as_string(),
+                   "a_0,a_1,b_0,b_1,b_2,c_0.a,c_0.b,c_1.a,c_1.b\n0,1,2,3,4,5,6,7,8\n");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_struct_with_catch_all() {
+        let mut w = Writer::from_memory();
+        w.serialize(struct_with_catch_all(0, &[("y", 1), ("x", 2)])).unwrap();
+        w.serialize(struct_with_catch_all(3, &[("x", 4)])).unwrap();
+        assert_eq!(w.as_string(), "a,x,y,b\n0,2,1,1\n3,4,,4\n");
+    }
 }
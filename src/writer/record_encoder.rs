@@ -0,0 +1,231 @@
+use super::EncodedRecord;
+
+use csv::{ByteString, Error, Result};
+use rustc_serialize::Encoder;
+
+/// Encoder for a single record.
+///
+/// Like `csv::Encoded`, each scalar becomes one field of the record. In
+/// addition, a map is encoded as the catch-all for columns that aren't field
+/// names of the record type.
+#[derive(Debug)]
+pub struct RecordEncoder {
+    record: EncodedRecord,
+}
+
+impl RecordEncoder {
+    /// Creates a new `RecordEncoder`. The value returned can be passed to
+    /// `Encodable::encode`.
+    pub fn new() -> RecordEncoder {
+        RecordEncoder {
+            record: EncodedRecord {
+                fields: vec![],
+                catch_all_index: None,
+                catch_all: vec![],
+            },
+        }
+    }
+
+    /// Once a record has been encoded into this value, `into_record` can be
+    /// used to access the raw fields.
+    pub fn into_record(self) -> EncodedRecord {
+        self.record
+    }
+
+    fn push_bytes<S>(&mut self, s: S) -> Result<()>
+        where S: Into<Vec<u8>>
+    {
+        self.record.fields.push(s.into());
+        Ok(())
+    }
+
+    fn push_to_string<T: ToString>(&mut self, t: T) -> Result<()> {
+        self.push_bytes(t.to_string().into_bytes())
+    }
+
+    /// Encodes a key or value of the catch-all map, which must be a single
+    /// field.
+    fn encode_single_field<F>(f: F) -> Result<ByteString>
+        where F: FnOnce(&mut Self) -> Result<()>
+    {
+        let mut encoder = RecordEncoder::new();
+        f(&mut encoder)?;
+        let mut fields = encoder.record.fields;
+        match fields.len() {
+            1 => Ok(fields.pop().unwrap()),
+            len => {
+                Err(Error::Encode(format!("Keys and values of the catch-all map must be a \
+                                           single field, but got {} fields",
+                                          len)))
+            }
+        }
+    }
+}
+
+impl Encoder for RecordEncoder {
+    type Error = Error;
+
+    fn emit_nil(&mut self) -> Result<()> {
+        self.push_bytes::<&[u8]>(&[])
+    }
+    fn emit_usize(&mut self, v: usize) -> Result<()> {
+        self.push_to_string(v)
+    }
+    fn emit_u64(&mut self, v: u64) -> Result<()> {
+        self.push_to_string(v)
+    }
+    fn emit_u32(&mut self, v: u32) -> Result<()> {
+        self.push_to_string(v)
+    }
+    fn emit_u16(&mut self, v: u16) -> Result<()> {
+        self.push_to_string(v)
+    }
+    fn emit_u8(&mut self, v: u8) -> Result<()> {
+        self.push_to_string(v)
+    }
+    fn emit_isize(&mut self, v: isize) -> Result<()> {
+        self.push_to_string(v)
+    }
+    fn emit_i64(&mut self, v: i64) -> Result<()> {
+        self.push_to_string(v)
+    }
+    fn emit_i32(&mut self, v: i32) -> Result<()> {
+        self.push_to_string(v)
+    }
+    fn emit_i16(&mut self, v: i16) -> Result<()> {
+        self.push_to_string(v)
+    }
+    fn emit_i8(&mut self, v: i8) -> Result<()> {
+        self.push_to_string(v)
+    }
+    fn emit_bool(&mut self, v: bool) -> Result<()> {
+        self.push_to_string(v)
+    }
+    fn emit_f64(&mut self, v: f64) -> Result<()> {
+        self.push_to_string(v)
+    }
+    fn emit_f32(&mut self, v: f32) -> Result<()> {
+        self.push_to_string(v)
+    }
+    fn emit_char(&mut self, v: char) -> Result<()> {
+        self.push_to_string(v)
+    }
+    fn emit_str(&mut self, v: &str) -> Result<()> {
+        self.push_bytes(v)
+    }
+    fn emit_enum<F>(&mut self, _: &str, f: F) -> Result<()>
+        where F: FnOnce(&mut Self) -> Result<()>
+    {
+        f(self)
+    }
+    fn emit_enum_variant<F>(&mut self, v_name: &str, _: usize, len: usize, f: F) -> Result<()>
+        where F: FnOnce(&mut Self) -> Result<()>
+    {
+        // Variants without arguments encode to their name.
+        if len == 0 {
+            self.push_bytes(v_name)
+        } else {
+            f(self)
+        }
+    }
+    fn emit_enum_variant_arg<F>(&mut self, _: usize, f: F) -> Result<()>
+        where F: FnOnce(&mut Self) -> Result<()>
+    {
+        f(self)
+    }
+    fn emit_enum_struct_variant<F>(&mut self,
+                                   v_name: &str,
+                                   v_id: usize,
+                                   len: usize,
+                                   f: F)
+                                   -> Result<()>
+        where F: FnOnce(&mut Self) -> Result<()>
+    {
+        self.emit_enum_variant(v_name, v_id, len, f)
+    }
+    fn emit_enum_struct_variant_field<F>(&mut self, _: &str, _: usize, f: F) -> Result<()>
+        where F: FnOnce(&mut Self) -> Result<()>
+    {
+        f(self)
+    }
+    fn emit_struct<F>(&mut self, _: &str, _: usize, f: F) -> Result<()>
+        where F: FnOnce(&mut Self) -> Result<()>
+    {
+        f(self)
+    }
+    fn emit_struct_field<F>(&mut self, _: &str, _: usize, f: F) -> Result<()>
+        where F: FnOnce(&mut Self) -> Result<()>
+    {
+        f(self)
+    }
+    fn emit_tuple<F>(&mut self, _: usize, f: F) -> Result<()>
+        where F: FnOnce(&mut Self) -> Result<()>
+    {
+        f(self)
+    }
+    fn emit_tuple_arg<F>(&mut self, _: usize, f: F) -> Result<()>
+        where F: FnOnce(&mut Self) -> Result<()>
+    {
+        f(self)
+    }
+    fn emit_tuple_struct<F>(&mut self, _: &str, _: usize, f: F) -> Result<()>
+        where F: FnOnce(&mut Self) -> Result<()>
+    {
+        f(self)
+    }
+    fn emit_tuple_struct_arg<F>(&mut self, _: usize, f: F) -> Result<()>
+        where F: FnOnce(&mut Self) -> Result<()>
+    {
+        f(self)
+    }
+    fn emit_option<F>(&mut self, f: F) -> Result<()>
+        where F: FnOnce(&mut Self) -> Result<()>
+    {
+        f(self)
+    }
+    fn emit_option_none(&mut self) -> Result<()> {
+        self.push_bytes::<&[u8]>(&[])
+    }
+    fn emit_option_some<F>(&mut self, f: F) -> Result<()>
+        where F: FnOnce(&mut Self) -> Result<()>
+    {
+        f(self)
+    }
+    fn emit_seq<F>(&mut self, _: usize, f: F) -> Result<()>
+        where F: FnOnce(&mut Self) -> Result<()>
+    {
+        f(self)
+    }
+    fn emit_seq_elt<F>(&mut self, _: usize, f: F) -> Result<()>
+        where F: FnOnce(&mut Self) -> Result<()>
+    {
+        f(self)
+    }
+    fn emit_map<F>(&mut self, _: usize, f: F) -> Result<()>
+        where F: FnOnce(&mut Self) -> Result<()>
+    {
+        if self.record.catch_all_index.is_some() {
+            return Err(Error::Encode("The encodable type has more than one catch-all map"
+                .to_string()));
+        }
+        self.record.catch_all_index = Some(self.record.fields.len());
+        f(self)
+    }
+    fn emit_map_elt_key<F>(&mut self, _: usize, f: F) -> Result<()>
+        where F: FnOnce(&mut Self) -> Result<()>
+    {
+        let key = RecordEncoder::encode_single_field(f)?;
+        self.record.catch_all.push((key, Vec::new()));
+        Ok(())
+    }
+    fn emit_map_elt_val<F>(&mut self, _: usize, f: F) -> Result<()>
+        where F: FnOnce(&mut Self) -> Result<()>
+    {
+        let value = RecordEncoder::encode_single_field(f)?;
+        match self.record.catch_all.last_mut() {
+            Some(entry) => entry.1 = value,
+            None => return Err(Error::Encode("Expected a map key".to_string())),
+        }
+        Ok(())
+    }
+}
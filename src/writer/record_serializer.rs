@@ -0,0 +1,302 @@
+use super::EncodedRecord;
+
+use csv::{self, ByteString};
+use error::Error;
+use serde::ser::{self, Impossible, Serialize, SerializeMap, SerializeSeq, SerializeStruct,
+                 SerializeTuple, SerializeTupleStruct, Serializer};
+use std::error;
+use std::fmt;
+use std::result;
+
+/// Error produced while serializing with `serde`.
+///
+/// This is converted into `Error::Csv(csv::Error::Encode(_))` before being
+/// returned to the user.
+#[derive(Debug)]
+pub struct SerError(String);
+
+pub type SerResult<T> = result::Result<T, SerError>;
+
+impl fmt::Display for SerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl error::Error for SerError {
+    fn description(&self) -> &str {
+        &self.0
+    }
+}
+
+impl ser::Error for SerError {
+    fn custom<T: fmt::Display>(msg: T) -> SerError {
+        SerError(msg.to_string())
+    }
+}
+
+impl From<SerError> for Error {
+    fn from(err: SerError) -> Error {
+        Error::Csv(csv::Error::Encode(err.0))
+    }
+}
+
+/// Serializer for a single record.
+///
+/// Like `RecordEncoder`, each scalar becomes one field of the record, and a
+/// map is serialized as the catch-all for columns that aren't field names of
+/// the record type.
+#[derive(Debug)]
+pub struct RecordSerializer {
+    record: EncodedRecord,
+}
+
+impl RecordSerializer {
+    /// Creates a new `RecordSerializer`. The value returned can be passed to
+    /// `Serialize::serialize`.
+    pub fn new() -> RecordSerializer {
+        RecordSerializer {
+            record: EncodedRecord {
+                fields: vec![],
+                catch_all_index: None,
+                catch_all: vec![],
+            },
+        }
+    }
+
+    /// Once a record has been serialized into this value, `into_record` can be
+    /// used to access the raw fields.
+    pub fn into_record(self) -> EncodedRecord {
+        self.record
+    }
+
+    fn push_bytes<S>(&mut self, s: S) -> SerResult<()>
+        where S: Into<Vec<u8>>
+    {
+        self.record.fields.push(s.into());
+        Ok(())
+    }
+
+    /// Serializes a key or value of the catch-all map, which must be a single
+    /// field.
+    fn serialize_single_field<T: ?Sized + Serialize>(value: &T) -> SerResult<ByteString> {
+        let mut serializer = RecordSerializer::new();
+        value.serialize(&mut serializer)?;
+        let mut fields = serializer.record.fields;
+        match fields.len() {
+            1 => Ok(fields.pop().unwrap()),
+            len => {
+                Err(SerError(format!("Keys and values of the catch-all map must be a single \
+                                      field, but got {} fields",
+                                     len)))
+            }
+        }
+    }
+
+    fn push_to_string<T: ToString>(&mut self, t: T) -> SerResult<()> {
+        self.push_bytes(t.to_string().into_bytes())
+    }
+}
+
+macro_rules! serialize_to_string {
+    ($($method:ident($ty:ty),)*) => {
+        $(
+            fn $method(self, v: $ty) -> SerResult<()> {
+                self.push_to_string(v)
+            }
+        )*
+    }
+}
+
+impl<'a> Serializer for &'a mut RecordSerializer {
+    type Ok = ();
+    type Error = SerError;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Impossible<(), SerError>;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Impossible<(), SerError>;
+
+    serialize_to_string! {
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> SerResult<()> {
+        self.push_bytes(v)
+    }
+
+    fn serialize_none(self) -> SerResult<()> {
+        self.push_bytes(Vec::new())
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> SerResult<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> SerResult<()> {
+        self.push_bytes(Vec::new())
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> SerResult<()> {
+        self.push_to_string(name)
+    }
+
+    fn serialize_unit_variant(self,
+                              _: &'static str,
+                              _: u32,
+                              variant: &'static str)
+                              -> SerResult<()> {
+        self.push_to_string(variant)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(self,
+                                                       _: &'static str,
+                                                       value: &T)
+                                                       -> SerResult<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(self,
+                                                        _: &'static str,
+                                                        _: u32,
+                                                        _: &'static str,
+                                                        value: &T)
+                                                        -> SerResult<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> SerResult<Self> {
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _: usize) -> SerResult<Self> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _: &'static str, _: usize) -> SerResult<Self> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(self,
+                               _: &'static str,
+                               _: u32,
+                               _: &'static str,
+                               _: usize)
+                               -> SerResult<Impossible<(), SerError>> {
+        Err(SerError("Enum variants with more than one value are not supported".into()))
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> SerResult<Self> {
+        if self.record.catch_all_index.is_some() {
+            return Err(SerError("The serializable type has more than one catch-all map".into()));
+        }
+        self.record.catch_all_index = Some(self.record.fields.len());
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> SerResult<Self> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(self,
+                                _: &'static str,
+                                _: u32,
+                                _: &'static str,
+                                _: usize)
+                                -> SerResult<Impossible<(), SerError>> {
+        Err(SerError("Enum variants with more than one value are not supported".into()))
+    }
+}
+
+impl<'a> SerializeSeq for &'a mut RecordSerializer {
+    type Ok = ();
+    type Error = SerError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> SerResult<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> SerResult<()> {
+        Ok(())
+    }
+}
+
+impl<'a> SerializeTuple for &'a mut RecordSerializer {
+    type Ok = ();
+    type Error = SerError;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> SerResult<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> SerResult<()> {
+        Ok(())
+    }
+}
+
+impl<'a> SerializeTupleStruct for &'a mut RecordSerializer {
+    type Ok = ();
+    type Error = SerError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> SerResult<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> SerResult<()> {
+        Ok(())
+    }
+}
+
+impl<'a> SerializeStruct for &'a mut RecordSerializer {
+    type Ok = ();
+    type Error = SerError;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self,
+                                              _: &'static str,
+                                              value: &T)
+                                              -> SerResult<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> SerResult<()> {
+        Ok(())
+    }
+}
+
+impl<'a> SerializeMap for &'a mut RecordSerializer {
+    type Ok = ();
+    type Error = SerError;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> SerResult<()> {
+        let key = RecordSerializer::serialize_single_field(key)?;
+        self.record.catch_all.push((key, Vec::new()));
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> SerResult<()> {
+        let value = RecordSerializer::serialize_single_field(value)?;
+        match self.record.catch_all.last_mut() {
+            Some(entry) => entry.1 = value,
+            None => return Err(SerError("Expected a map key".into())),
+        }
+        Ok(())
+    }
+
+    fn end(self) -> SerResult<()> {
+        Ok(())
+    }
+}